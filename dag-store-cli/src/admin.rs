@@ -0,0 +1,128 @@
+use crate::client::{dag_err, Client, Result};
+use dag_store::capabilities::store::{FileSystemStore, GcStats};
+use dag_store::server::auth::{now_secs, Authenticator, Grant};
+use dag_store_types::types::api::cache_stats;
+use dag_store_types::types::grpc::GetCacheStatsReq;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use tonic::Request;
+
+#[derive(Serialize)]
+pub struct AccessCountOutput {
+    pub hash: String,
+    pub accesses: u64,
+}
+
+#[derive(Serialize)]
+pub struct StatsOutput {
+    pub entry_count: u64,
+    pub byte_usage: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f64,
+    pub evictions: u64,
+    pub rejected: u64,
+    pub most_accessed: Vec<AccessCountOutput>,
+}
+
+impl fmt::Display for StatsOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "entries: {}", self.entry_count)?;
+        writeln!(f, "bytes: {} / {}", self.byte_usage, self.max_bytes)?;
+        writeln!(
+            f,
+            "hits: {}, misses: {} (hit ratio {:.3})",
+            self.hits, self.misses, self.hit_ratio
+        )?;
+        writeln!(f, "evictions: {}", self.evictions)?;
+        writeln!(f, "too large to cache: {}", self.rejected)?;
+        if !self.most_accessed.is_empty() {
+            writeln!(f, "most accessed:")?;
+            for a in self.most_accessed.iter() {
+                writeln!(f, "  {} {}", a.hash, a.accesses)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn stats(client: &mut Client, top_n: u64) -> Result<StatsOutput> {
+    let request = Request::new(GetCacheStatsReq { top_n });
+    let resp = client.inner.get_cache_stats(request).await?.into_inner();
+    let resp = cache_stats::Resp::from_proto(resp)?;
+
+    Ok(StatsOutput {
+        entry_count: resp.entry_count,
+        byte_usage: resp.byte_usage,
+        max_bytes: resp.max_bytes,
+        hits: resp.hits,
+        misses: resp.misses,
+        hit_ratio: resp.hit_ratio,
+        evictions: resp.evictions,
+        rejected: resp.rejected,
+        most_accessed: resp
+            .most_accessed
+            .into_iter()
+            .map(|a| AccessCountOutput {
+                hash: a.hash.to_string(),
+                accesses: a.accesses,
+            })
+            .collect(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct GcOutput {
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub stats: GcStats,
+}
+
+impl fmt::Display for GcOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        };
+        writeln!(
+            f,
+            "{} {} unreachable nodes ({} bytes), {} reachable",
+            verb, self.stats.removed_nodes, self.stats.removed_bytes, self.stats.reachable_nodes
+        )
+    }
+}
+
+/// collect garbage in the sled db at fs_path, which must not be open in a running dag store
+pub fn gc(fs_path: &str, dry_run: bool) -> Result<GcOutput> {
+    let store = FileSystemStore::open(fs_path).map_err(dag_err)?;
+    let stats = store.gc(dry_run).map_err(dag_err)?;
+    Ok(GcOutput { dry_run, stats })
+}
+
+#[derive(Serialize)]
+pub struct TokenOutput {
+    pub token: String,
+    /// seconds since the unix epoch
+    pub expires_at: u64,
+}
+
+impl fmt::Display for TokenOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.token)
+    }
+}
+
+/// mint a token accepted by dag stores configured with the same signing key file
+pub fn token(signing_key_file: &str, grant: &str, ttl_secs: u64) -> Result<TokenOutput> {
+    let signing_key =
+        Authenticator::read_signing_key_file(Path::new(signing_key_file)).map_err(dag_err)?;
+    let grant = Grant::parse(grant).map_err(dag_err)?;
+    let expires_at = now_secs() + ttl_secs;
+    Ok(TokenOutput {
+        token: Authenticator::sign(&signing_key, grant, expires_at),
+        expires_at,
+    })
+}
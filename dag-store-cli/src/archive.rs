@@ -0,0 +1,98 @@
+use crate::client::{Client, Result};
+use dag_store_types::types::api::{export_root, import_archive};
+use dag_store_types::types::grpc::ArchiveChunk;
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use tonic::Request;
+
+// archives are streamed to the dag store in chunks of this size
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize)]
+pub struct ExportOutput {
+    pub file: String,
+    pub bytes: u64,
+}
+
+impl fmt::Display for ExportOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "wrote {} bytes to {}", self.bytes, self.file)
+    }
+}
+
+/// write an archive of the dag(s) selected by req to file
+pub async fn export(
+    client: &mut Client,
+    req: export_root::Req,
+    file: String,
+) -> Result<ExportOutput> {
+    let mut out = File::create(&file)?;
+    let request = Request::new(req.into_proto());
+    let mut stream = client.inner.export_root(request).await?.into_inner();
+
+    let mut bytes = 0;
+    while let Some(chunk) = stream.message().await? {
+        out.write_all(&chunk.data)?;
+        bytes += chunk.data.len() as u64;
+    }
+    out.sync_all()?;
+
+    Ok(ExportOutput { file, bytes })
+}
+
+#[derive(Serialize)]
+pub struct ImportedKeyOutput {
+    pub key: String,
+    pub hash: String,
+    pub written: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportOutput {
+    pub node_count: u64,
+    pub keys: Vec<ImportedKeyOutput>,
+}
+
+impl fmt::Display for ImportOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "imported {} nodes", self.node_count)?;
+        for key in self.keys.iter() {
+            let note = if key.written {
+                ""
+            } else {
+                " (not written, key points elsewhere)"
+            };
+            writeln!(f, "{} -> {}{}", key.key, key.hash, note)?;
+        }
+        Ok(())
+    }
+}
+
+/// import an archive written by export
+pub async fn import(client: &mut Client, file: &str) -> Result<ImportOutput> {
+    let archive = std::fs::read(file)?;
+    let chunks: Vec<ArchiveChunk> = archive
+        .chunks(CHUNK_SIZE)
+        .map(|c| ArchiveChunk { data: c.to_vec() })
+        .collect();
+
+    let request = Request::new(futures::stream::iter(chunks));
+    let resp = client.inner.import_archive(request).await?.into_inner();
+    let resp = import_archive::Resp::from_proto(resp)?;
+
+    let keys = resp
+        .keys
+        .into_iter()
+        .map(|k| ImportedKeyOutput {
+            key: k.key,
+            hash: k.hash.to_string(),
+            written: k.written,
+        })
+        .collect();
+    Ok(ImportOutput {
+        node_count: resp.node_count,
+        keys,
+    })
+}
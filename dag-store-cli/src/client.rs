@@ -0,0 +1,76 @@
+use dag_store::tls::{self, ClientTls};
+use dag_store_types::types::api::{bulk_put, get, list_keys};
+use dag_store_types::types::domain::Hash;
+use dag_store_types::types::errors::DagCacheError;
+use dag_store_types::types::grpc::{self, dag_store_client::DagStoreClient};
+use tonic::transport::Channel;
+use tonic::Request;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+/// DagCacheError doesn't implement Error, so report its debug representation
+pub fn dag_err(e: DagCacheError) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+    format!("{:?}", e).into()
+}
+
+/// parse a base58 hash, as printed by the dag store and this tool
+pub fn parse_hash(s: &str) -> Result<Hash> {
+    Ok(Hash::from_base58(s)?)
+}
+
+/// dag store client presenting a bearer token (if any) on every request
+pub struct Client {
+    pub inner: DagStoreClient<Channel>,
+}
+
+impl Client {
+    pub async fn connect(
+        url: String,
+        tls: Option<&ClientTls>,
+        token: Option<&str>,
+    ) -> Result<Self> {
+        let inner = tls::connect_with_token(url, tls, token)
+            .await
+            .map_err(dag_err)?;
+        Ok(Client { inner })
+    }
+
+    pub async fn get_node(&mut self, hash: Hash) -> Result<get::Resp> {
+        let request = Request::new(hash.into_proto());
+        let resp = self.inner.get_node(request).await?;
+        Ok(get::Resp::from_proto(resp.into_inner())?)
+    }
+
+    /// whether each of hashes is present in the store, in order
+    pub async fn has_nodes(&mut self, hashes: &[Hash]) -> Result<Vec<bool>> {
+        let request = Request::new(grpc::HasNodesReq {
+            hashes: hashes.iter().map(|h| h.into_proto()).collect(),
+        });
+        let resp = self.inner.has_nodes(request).await?;
+        Ok(resp.into_inner().present)
+    }
+
+    pub async fn get_key(&mut self, key: &str) -> Result<Option<Hash>> {
+        let request = Request::new(grpc::GetHashForKeyReq {
+            key: key.to_string(),
+        });
+        let resp = self.inner.get_hash_for_key(request).await?;
+        Ok(resp.into_inner().hash.map(Hash::from_proto).transpose()?)
+    }
+
+    /// keys starting with prefix, ordered by key
+    pub async fn list_keys(&mut self, prefix: &str) -> Result<Vec<(String, Hash)>> {
+        let req = list_keys::Req {
+            prefix: prefix.to_string(),
+        };
+        let request = Request::new(req.into_proto());
+        let resp = self.inner.list_keys(request).await?;
+        Ok(list_keys::Resp::from_proto(resp.into_inner())?.keys)
+    }
+
+    pub async fn put_nodes(&mut self, req: bulk_put::Req) -> Result<bulk_put::Resp> {
+        let request = Request::new(req.into_proto());
+        let resp = self.inner.put_nodes(request).await?;
+        Ok(bulk_put::Resp::from_proto(resp.into_inner())?)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::client::{dag_err, Client, Result};
+use dag_store::capabilities::store::{FileSystemStore, FsckProblem, FsckReport};
+use dag_store_types::types::domain::Hash;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Serialize)]
+pub struct Problem {
+    pub hash: String,
+    /// the node linking to this one, or the key pointing to it
+    pub referenced_by: String,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct SizeMismatch {
+    pub parent: String,
+    pub child: String,
+    pub recorded: u64,
+    pub actual: u64,
+}
+
+/// result of checking every node reachable from a set of keys
+#[derive(Default, Serialize)]
+pub struct FsckOutput {
+    pub keys_checked: usize,
+    pub nodes_checked: u64,
+    /// nodes that are linked to (or pointed to by a key) but aren't in the store
+    pub missing: Vec<Problem>,
+    /// nodes that can't be read or don't hash to the hash they're stored under
+    pub corrupt: Vec<Problem>,
+    /// links recording a size other than that of the dag they point to
+    pub size_mismatches: Vec<SizeMismatch>,
+}
+
+impl FsckOutput {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty() && self.size_mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for FsckOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "checked {} nodes reachable from {} keys",
+            self.nodes_checked, self.keys_checked
+        )?;
+        for p in self.missing.iter() {
+            writeln!(f, "missing: {} (referenced by {})", p.hash, p.referenced_by)?;
+        }
+        for p in self.corrupt.iter() {
+            writeln!(
+                f,
+                "corrupt: {} (referenced by {}): {}",
+                p.hash, p.referenced_by, p.reason
+            )?;
+        }
+        for m in self.size_mismatches.iter() {
+            writeln!(
+                f,
+                "size mismatch: {} links to {} with size {}, actual size {}",
+                m.parent, m.child, m.recorded, m.actual
+            )?;
+        }
+        if self.is_clean() {
+            writeln!(f, "ok")?;
+        }
+        Ok(())
+    }
+}
+
+/// check that every node reachable from keys (or from all keys, if none are given) is present
+/// and intact, via a running dag store
+pub async fn fsck(client: &mut Client, keys: Vec<String>) -> Result<FsckOutput> {
+    let roots = if keys.is_empty() {
+        client.list_keys("").await?
+    } else {
+        let mut roots = Vec::new();
+        for key in keys {
+            let hash = client
+                .get_key(&key)
+                .await?
+                .ok_or_else(|| format!("{} is not set", key))?;
+            roots.push((key, hash));
+        }
+        roots
+    };
+
+    let mut out = FsckOutput {
+        keys_checked: roots.len(),
+        ..FsckOutput::default()
+    };
+
+    let referrers = roots.iter().map(|(k, _)| format!("key {}", k)).collect();
+    let hashes: Vec<Hash> = roots.iter().map(|(_, h)| *h).collect();
+    let mut stack = present(client, hashes, referrers, &mut out).await?;
+
+    let mut visited = HashSet::new();
+    let mut sizes = HashMap::new();
+    // (parent, child, recorded size) for every link followed
+    let mut links = Vec::new();
+    while let Some((hash, referenced_by)) = stack.pop() {
+        if !visited.insert(hash) {
+            continue;
+        }
+        out.nodes_checked += 1;
+
+        let node = match client.get_node(hash).await {
+            Ok(resp) => resp.requested_node,
+            Err(e) => {
+                out.corrupt.push(Problem {
+                    hash: hash.to_string(),
+                    referenced_by,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let actual = node.canonical_hash();
+        if actual != hash {
+            out.corrupt.push(Problem {
+                hash: hash.to_string(),
+                referenced_by,
+                reason: format!("content hashes to {}", actual),
+            });
+            continue;
+        }
+        sizes.insert(hash, node.dag_size());
+
+        let hashes: Vec<Hash> = node.links.iter().map(|l| l.hash).collect();
+        let referrers = hashes.iter().map(|_| hash.to_string()).collect();
+        links.extend(node.links.iter().map(|l| (hash, l.hash, l.size)));
+        stack.extend(present(client, hashes, referrers, &mut out).await?);
+    }
+
+    for (parent, child, recorded) in links {
+        match sizes.get(&child) {
+            Some(actual) if *actual != recorded => out.size_mismatches.push(SizeMismatch {
+                parent: parent.to_string(),
+                child: child.to_string(),
+                recorded,
+                actual: *actual,
+            }),
+            _ => {}
+        }
+    }
+
+    Ok(out)
+}
+
+// hashes that are in the store, paired with their referrer. missing hashes are recorded in out
+async fn present(
+    client: &mut Client,
+    hashes: Vec<Hash>,
+    referrers: Vec<String>,
+    out: &mut FsckOutput,
+) -> Result<Vec<(Hash, String)>> {
+    if hashes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let present = client.has_nodes(&hashes).await?;
+    let mut res = Vec::new();
+    for ((hash, referenced_by), present) in hashes.into_iter().zip(referrers).zip(present) {
+        if present {
+            res.push((hash, referenced_by));
+        } else {
+            out.missing.push(Problem {
+                hash: hash.to_string(),
+                referenced_by,
+                reason: "not in store".to_string(),
+            });
+        }
+    }
+    Ok(res)
+}
+
+#[derive(Serialize)]
+pub struct StoreFsckOutput {
+    #[serde(flatten)]
+    pub report: FsckReport,
+}
+
+impl fmt::Display for StoreFsckOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.report;
+        writeln!(
+            f,
+            "checked {} blobs and {} keys",
+            r.blobs_checked, r.keys_checked
+        )?;
+        let sections: [(&str, &Vec<FsckProblem>); 4] = [
+            ("corrupt blob", &r.corrupt_blobs),
+            ("dangling link", &r.dangling_links),
+            ("size mismatch", &r.size_mismatches),
+            ("dangling key", &r.dangling_keys),
+        ];
+        for (label, problems) in sections.iter() {
+            for p in problems.iter() {
+                writeln!(f, "{}: {}: {}", label, p.entry, p.reason)?;
+            }
+        }
+        if r.quarantined > 0 {
+            writeln!(f, "quarantined {} entries", r.quarantined)?;
+        }
+        if r.is_clean() {
+            writeln!(f, "ok")?;
+        }
+        Ok(())
+    }
+}
+
+/// check every entry in the sled db at fs_path, which must not be open in a running dag store
+pub fn fsck_store(fs_path: &str, quarantine: bool) -> Result<StoreFsckOutput> {
+    let store = FileSystemStore::open(fs_path).map_err(dag_err)?;
+    let report = store.fsck(quarantine).map_err(dag_err)?;
+    Ok(StoreFsckOutput { report })
+}
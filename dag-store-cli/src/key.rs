@@ -0,0 +1,276 @@
+use crate::client::{parse_hash, Client, Result};
+use crate::output::Output;
+use dag_store_types::types::api::bulk_put;
+use dag_store_types::types::domain::{Hash, TypedHash};
+use dag_store_types::types::validated_tree::ValidatedTree;
+use notes_types::commits::Commit;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum KeyCommand {
+    /// print the hash a key points to
+    #[structopt(name = "get")]
+    Get { key: String },
+
+    /// point a key at a node already in the store. fails if the key changes concurrently.
+    /// keys can only be set by writing a node, so the node is written again, counting against
+    /// the tenant's quota
+    #[structopt(name = "set")]
+    Set {
+        key: String,
+        hash: String,
+        /// only set the key if it currently points to this hash (defaults to its current value)
+        #[structopt(long = "expect")]
+        expect: Option<String>,
+        /// only set the key if it doesn't exist yet
+        #[structopt(long = "create", conflicts_with = "expect")]
+        create: bool,
+    },
+
+    /// list keys and the hashes they point to
+    #[structopt(name = "list")]
+    List {
+        /// only list keys starting with this prefix
+        #[structopt(default_value = "")]
+        prefix: String,
+    },
+
+    /// list the commits reachable from a key holding a commit, as written by notes-server.
+    /// the dag store itself doesn't keep a key's past values
+    #[structopt(name = "history")]
+    History {
+        key: String,
+        #[structopt(long = "limit", default_value = "20")]
+        limit: usize,
+    },
+}
+
+#[derive(Serialize)]
+pub struct KeyOutput {
+    pub key: String,
+    pub hash: Option<String>,
+}
+
+impl fmt::Display for KeyOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.hash {
+            Some(hash) => writeln!(f, "{}", hash),
+            None => writeln!(f, "{} is not set", self.key),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SetOutput {
+    pub key: String,
+    pub hash: String,
+    pub previous: Option<String>,
+}
+
+impl fmt::Display for SetOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let previous = self.previous.as_deref();
+        writeln!(
+            f,
+            "{} -> {} (was {})",
+            self.key,
+            self.hash,
+            previous.unwrap_or("unset")
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub struct ListOutput {
+    pub keys: Vec<KeyOutput>,
+}
+
+impl fmt::Display for ListOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for key in self.keys.iter() {
+            // ASSERTION: listed keys always have a hash
+            writeln!(f, "{} {}", key.key, key.hash.as_ref().unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct CommitOutput {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub root: Option<String>,
+    pub author: Option<String>,
+    pub timestamp_millis: Option<u64>,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryOutput {
+    pub key: String,
+    /// newest first
+    pub commits: Vec<CommitOutput>,
+}
+
+impl fmt::Display for HistoryOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for commit in self.commits.iter() {
+            writeln!(f, "commit {}", commit.hash)?;
+            if commit.parents.len() > 1 {
+                writeln!(f, "merge: {}", commit.parents.join(" "))?;
+            }
+            if let Some(author) = &commit.author {
+                writeln!(f, "author: {}", author)?;
+            }
+            if let Some(timestamp) = commit.timestamp_millis {
+                writeln!(f, "timestamp: {}", timestamp)?;
+            }
+            if let Some(root) = &commit.root {
+                writeln!(f, "root: {}", root)?;
+            }
+            if let Some(message) = &commit.message {
+                writeln!(f, "\n    {}", message)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn run(client: &mut Client, cmd: KeyCommand) -> Result<Box<dyn Output>> {
+    match cmd {
+        KeyCommand::Get { key } => {
+            let hash = client.get_key(&key).await?;
+            let hash = hash.map(|h| h.to_string());
+            Ok(Box::new(KeyOutput { key, hash }))
+        }
+        KeyCommand::Set {
+            key,
+            hash,
+            expect,
+            create,
+        } => {
+            let hash = parse_hash(&hash)?;
+            let previous = match expect {
+                Some(expect) => Some(parse_hash(&expect)?),
+                None if create => None,
+                None => client.get_key(&key).await?,
+            };
+            set(client, &key, hash, previous).await?;
+            Ok(Box::new(SetOutput {
+                key,
+                hash: hash.to_string(),
+                previous: previous.map(|p| p.to_string()),
+            }))
+        }
+        KeyCommand::List { prefix } => {
+            let keys = client.list_keys(&prefix).await?;
+            let keys = keys
+                .into_iter()
+                .map(|(key, hash)| KeyOutput {
+                    key,
+                    hash: Some(hash.to_string()),
+                })
+                .collect();
+            Ok(Box::new(ListOutput { keys }))
+        }
+        KeyCommand::History { key, limit } => {
+            let head = client
+                .get_key(&key)
+                .await?
+                .ok_or_else(|| format!("{} is not set", key))?;
+            let commits = history(client, head.promote(), limit).await?;
+            Ok(Box::new(HistoryOutput { key, commits }))
+        }
+    }
+}
+
+/// point key at hash if it currently points at previous. the only way to set a key is by
+/// writing a node, so the node is re-written with its links referring to the stored nodes
+pub async fn set(client: &mut Client, key: &str, hash: Hash, previous: Option<Hash>) -> Result<()> {
+    let node = client.get_node(hash).await?.requested_node;
+    // checked before the cas, as a re-put node that hashes differently would move the key
+    if node.canonical_hash() != hash {
+        return Err(format!("node {} doesn't hash to itself, not setting {}", hash, key).into());
+    }
+    let root_node = bulk_put::Node {
+        links: node
+            .links
+            .into_iter()
+            .map(bulk_put::NodeLink::Remote)
+            .collect(),
+        data: node.data,
+    };
+    let req = bulk_put::Req {
+        validated_tree: ValidatedTree::validate(root_node, HashMap::new())?,
+        cas: Some(bulk_put::CAS {
+            required_previous_hash: previous,
+            cas_key: key.to_string(),
+        }),
+    };
+    let resp = client.put_nodes(req).await?;
+    if resp.root_hash != hash {
+        let msg = format!(
+            "{} was set to rewritten node {}, not {}",
+            key, resp.root_hash, hash
+        );
+        return Err(msg.into());
+    }
+    Ok(())
+}
+
+// breadth first from head, as notes-server's history
+async fn history(
+    client: &mut Client,
+    head: TypedHash<Commit>,
+    limit: usize,
+) -> Result<Vec<CommitOutput>> {
+    let mut res = Vec::new();
+    let mut visited = HashSet::new();
+    let mut frontier = VecDeque::new();
+    frontier.push_back(head);
+
+    while let Some(hash) = frontier.pop_front() {
+        if res.len() >= limit {
+            break;
+        }
+        if !visited.insert(hash) {
+            continue;
+        }
+
+        let node = client.get_node(hash.demote()).await?.requested_node;
+        let commit = Commit::from_generic(node)
+            .map_err(|e| format!("{} is not a commit: {}", hash.demote(), e))?;
+        let parents = commit.parents();
+        frontier.extend(parents.iter().cloned());
+
+        let mut out = CommitOutput {
+            hash: hash.to_string(),
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            root: commit.root().map(|r| r.to_string()),
+            author: None,
+            timestamp_millis: None,
+            message: None,
+        };
+        match commit {
+            Commit::Commit {
+                author,
+                timestamp_millis,
+                message,
+                ..
+            } => {
+                out.author = Some(author);
+                out.timestamp_millis = Some(timestamp_millis);
+                out.message = message;
+            }
+            // shared origin of all commits, not worth listing
+            Commit::Null => continue,
+        }
+        res.push(out);
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,348 @@
+#![deny(warnings)]
+mod admin;
+mod archive;
+mod client;
+mod fsck;
+mod key;
+mod node;
+mod output;
+mod put;
+
+use client::{parse_hash, Client, Result};
+use dag_store::config;
+use dag_store::tls::ClientTls;
+use dag_store_types::types::api::export_root;
+use key::KeyCommand;
+use output::{emit, Output};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "dag-store-cli", about = "inspect and administer a dag-store")]
+struct Opt {
+    #[structopt(flatten)]
+    conn: ConnectOpt,
+
+    /// print results as json, for scripting
+    #[structopt(long = "json")]
+    json: bool,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+struct ConnectOpt {
+    /// url of the dag store
+    #[structopt(
+        short = "u",
+        long = "url",
+        env = "DAG_STORE_URL",
+        default_value = "http://localhost:8088"
+    )]
+    url: String,
+
+    /// file containing a bearer token sent with every request
+    #[structopt(long = "token_file", env = "DAG_STORE_TOKEN_FILE")]
+    token_file: Option<String>,
+
+    /// pem encoded ca used to verify the dag store, enables tls
+    #[structopt(long = "ca_file", env = "DAG_STORE_CA_FILE")]
+    ca_file: Option<String>,
+
+    /// client certificate presented to the dag store (mutual tls)
+    #[structopt(long = "cert_file", env = "DAG_STORE_CERT_FILE")]
+    cert_file: Option<String>,
+
+    /// private key for cert_file
+    #[structopt(long = "key_file", env = "DAG_STORE_KEY_FILE")]
+    key_file: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// print a node, its links and its data (as json or text, if it is either)
+    #[structopt(name = "get")]
+    Get { hash: String },
+
+    /// print the dag below a node
+    #[structopt(name = "tree")]
+    Tree {
+        hash: String,
+        /// only descend this many levels
+        #[structopt(long = "depth")]
+        depth: Option<u32>,
+    },
+
+    /// read, write and list keys
+    #[structopt(name = "key")]
+    Key(KeyCommand),
+
+    /// store a file as a tree of nodes, printing the root hash
+    #[structopt(name = "put")]
+    Put {
+        file: String,
+        /// point this key at the stored file
+        #[structopt(long = "key")]
+        key: Option<String>,
+        /// max bytes of the file stored per node, defaults to 256KiB
+        #[structopt(long = "chunk_size", default_value = "262144")]
+        chunk_size: usize,
+        /// max bytes of the file uploaded per request, defaults to 4MiB
+        #[structopt(long = "batch_size", default_value = "4194304")]
+        batch_size: usize,
+    },
+
+    /// print cache statistics
+    #[structopt(name = "stats")]
+    Stats {
+        /// number of most accessed hashes to list
+        #[structopt(long = "top", default_value = "10")]
+        top: u64,
+    },
+
+    /// remove nodes that aren't reachable from any key. runs directly against the sled db of a
+    /// dag store that isn't running
+    #[structopt(name = "gc")]
+    Gc {
+        #[structopt(short = "f", long = "fs_path")]
+        fs_path: String,
+        /// only count unreachable nodes
+        #[structopt(long = "dry_run")]
+        dry_run: bool,
+    },
+
+    /// write an archive of a node, a key or all keys (and the nodes below them) to a file
+    #[structopt(name = "export")]
+    Export {
+        file: String,
+        #[structopt(long = "hash", raw(required_unless_one = r#"&["key", "all_keys"]"#))]
+        hash: Option<String>,
+        #[structopt(long = "key", conflicts_with = "hash")]
+        key: Option<String>,
+        #[structopt(long = "all_keys", raw(conflicts_with_all = r#"&["hash", "key"]"#))]
+        all_keys: bool,
+    },
+
+    /// import an archive written by export, setting any keys it contains that aren't set yet
+    #[structopt(name = "import")]
+    Import { file: String },
+
+    /// check that every node reachable from the given keys (or all keys) is present and
+    /// intact. exits with status 1 if not
+    #[structopt(name = "fsck")]
+    Fsck {
+        /// key to check (may be repeated)
+        #[structopt(long = "key")]
+        keys: Vec<String>,
+        /// instead check every entry directly in the sled db of a dag store that isn't running
+        #[structopt(short = "f", long = "fs_path", conflicts_with = "keys")]
+        fs_path: Option<String>,
+        /// move corrupt blobs and dangling keys found in the sled db out of the store
+        #[structopt(long = "quarantine", requires = "fs_path")]
+        quarantine: bool,
+    },
+
+    /// mint a signed bearer token
+    #[structopt(name = "token")]
+    Token {
+        /// the dag store's auth_signing_key_file
+        #[structopt(long = "signing_key_file")]
+        signing_key_file: String,
+        /// capabilities to grant, eg `read,write,cas:notes-app/,tenant:alice` (or `admin`)
+        #[structopt(long = "grant")]
+        grant: String,
+        #[structopt(long = "ttl_secs", default_value = "3600")]
+        ttl_secs: u64,
+    },
+}
+
+impl ConnectOpt {
+    async fn connect(&self) -> Result<Client> {
+        let tls = ClientTls::from_opts(
+            self.ca_file.clone(),
+            self.cert_file.clone(),
+            self.key_file.clone(),
+        );
+        let token = match &self.token_file {
+            Some(path) => Some(config::read_token_file("token_file", path)?),
+            None => None,
+        };
+        Client::connect(self.url.clone(), tls.as_ref(), token.as_deref()).await
+    }
+}
+
+async fn run(opt: Opt) -> Result<Box<dyn Output>> {
+    let conn = opt.conn;
+    let output: Box<dyn Output> = match opt.cmd {
+        Command::Get { hash } => {
+            let mut client = conn.connect().await?;
+            Box::new(node::get(&mut client, parse_hash(&hash)?).await?)
+        }
+        Command::Tree { hash, depth } => {
+            let mut client = conn.connect().await?;
+            Box::new(node::tree(&mut client, parse_hash(&hash)?, depth).await?)
+        }
+        Command::Key(cmd) => key::run(&mut conn.connect().await?, cmd).await?,
+        Command::Put {
+            file,
+            key,
+            chunk_size,
+            batch_size,
+        } => {
+            if chunk_size == 0 {
+                return Err("chunk_size must be positive".into());
+            }
+            let file = std::io::BufReader::new(std::fs::File::open(&file)?);
+            let mut client = conn.connect().await?;
+            Box::new(put::put(&mut client, file, chunk_size, batch_size, key).await?)
+        }
+        Command::Stats { top } => Box::new(admin::stats(&mut conn.connect().await?, top).await?),
+        Command::Gc { fs_path, dry_run } => Box::new(admin::gc(&fs_path, dry_run)?),
+        Command::Export {
+            file,
+            hash,
+            key,
+            all_keys,
+        } => {
+            let req = match (hash, key) {
+                (Some(hash), _) => export_root::Req::Hash(parse_hash(&hash)?),
+                (None, Some(key)) => export_root::Req::Key(key),
+                (None, None) if all_keys => export_root::Req::AllKeys,
+                (None, None) => return Err("nothing to export".into()),
+            };
+            Box::new(archive::export(&mut conn.connect().await?, req, file).await?)
+        }
+        Command::Import { file } => {
+            Box::new(archive::import(&mut conn.connect().await?, &file).await?)
+        }
+        Command::Fsck {
+            keys,
+            fs_path: None,
+            ..
+        } => {
+            let report = fsck::fsck(&mut conn.connect().await?, keys).await?;
+            if !report.is_clean() {
+                emit(&report, opt.json)?;
+                std::process::exit(1);
+            }
+            Box::new(report)
+        }
+        Command::Fsck {
+            fs_path: Some(fs_path),
+            quarantine,
+            ..
+        } => {
+            let output = fsck::fsck_store(&fs_path, quarantine)?;
+            if !output.report.is_clean() {
+                emit(&output, opt.json)?;
+                std::process::exit(1);
+            }
+            Box::new(output)
+        }
+        Command::Token {
+            signing_key_file,
+            grant,
+            ttl_secs,
+        } => Box::new(admin::token(&signing_key_file, &grant, ttl_secs)?),
+    };
+    Ok(output)
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::from_args();
+    let json = opt.json;
+    let res = match run(opt).await {
+        Ok(output) => emit(output.as_ref(), json).map_err(|e| e.into()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dag_store::test_utils::{init_test_env, spawn_dag_store, test_runtime};
+
+    #[tokio::test]
+    async fn test_put_check_and_archive() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let (_runtime, url) = spawn_dag_store(runtime).await;
+        let mut client = Client::connect(url, None, None).await.unwrap();
+
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let put = put::put(
+            &mut client,
+            &data[..],
+            100,
+            10_000,
+            Some("file".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(put.node_count, 51);
+        let root = parse_hash(&put.hash).unwrap();
+        assert_eq!(client.get_key("file").await.unwrap(), Some(root));
+
+        // leaves, read left to right, hold the file
+        let nodes = node::fetch_dag(&mut client, root, None).await.unwrap();
+        let read: Vec<u8> = nodes[&root]
+            .links
+            .iter()
+            .flat_map(|l| nodes[&l.hash].data.0.clone())
+            .collect();
+        assert_eq!(read, data);
+
+        let tree = node::tree(&mut client, root, Some(0)).await.unwrap();
+        assert!(!tree.expanded);
+        assert!(tree.children.is_empty());
+        let tree = node::tree(&mut client, root, None).await.unwrap();
+        assert_eq!(tree.size, data.len() as u64);
+        assert_eq!(tree.children.len(), 50);
+
+        key::set(&mut client, "copy", root, None).await.unwrap();
+        assert_eq!(client.get_key("copy").await.unwrap(), Some(root));
+        // the key now exists, so setting it again as new fails
+        assert!(key::set(&mut client, "copy", root, None).await.is_err());
+
+        let report = fsck::fsck(&mut client, vec![]).await.unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.keys_checked, 2);
+        assert_eq!(report.nodes_checked, 51);
+        assert!(report.size_mismatches.is_empty());
+
+        let archive_dir = tempdir::TempDir::new("dag-store-cli-archive").unwrap();
+        let file = archive_dir.path().join("file.archive");
+        let file = file.to_str().unwrap().to_string();
+        let req = export_root::Req::Key("file".to_string());
+        let export = archive::export(&mut client, req, file.clone())
+            .await
+            .unwrap();
+        assert!(export.bytes as usize > data.len());
+        let import = archive::import(&mut client, &file).await.unwrap();
+        assert_eq!(import.node_count, 51);
+        assert_eq!(import.keys[0].hash, put.hash);
+
+        // in batches of 10 leaves, linked to by the root once all are written
+        let batched = put::put(&mut client, &data[..], 100, 1000, None)
+            .await
+            .unwrap();
+        assert_eq!(batched.node_count, 56);
+        let root = parse_hash(&batched.hash).unwrap();
+        let tree = node::tree(&mut client, root, None).await.unwrap();
+        assert_eq!(tree.size, data.len() as u64);
+        assert_eq!(tree.children.len(), 5);
+        let nodes = node::fetch_dag(&mut client, root, None).await.unwrap();
+        let read: Vec<u8> = nodes[&root]
+            .links
+            .iter()
+            .flat_map(|batch| nodes[&batch.hash].links.iter())
+            .flat_map(|l| nodes[&l.hash].data.0.clone())
+            .collect();
+        assert_eq!(read, data);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::client::{Client, Result};
+use dag_store_types::types::domain::{Hash, Header, Node};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Serialize)]
+pub struct LinkOutput {
+    pub id: String,
+    pub hash: String,
+    pub size: u64,
+}
+
+impl LinkOutput {
+    fn new(header: &Header) -> Self {
+        LinkOutput {
+            id: header.id.to_string(),
+            hash: header.hash.to_string(),
+            size: header.size,
+        }
+    }
+}
+
+/// a node with its data decoded where possible
+#[derive(Serialize)]
+pub struct NodeOutput {
+    pub hash: String,
+    pub size: u64,
+    pub links: Vec<LinkOutput>,
+    /// base64 encoded
+    pub data: String,
+    /// data parsed as json, if it is json
+    pub data_json: Option<serde_json::Value>,
+    /// data as text, if it's utf-8 but not json
+    pub data_text: Option<String>,
+}
+
+impl NodeOutput {
+    pub fn new(hash: Hash, node: &Node) -> Self {
+        let data_json: Option<serde_json::Value> = serde_json::from_slice(&node.data.0).ok();
+        let data_text = match data_json {
+            Some(_) => None,
+            None => String::from_utf8(node.data.0.clone()).ok(),
+        };
+        NodeOutput {
+            hash: hash.to_string(),
+            size: node.dag_size(),
+            links: node.links.iter().map(LinkOutput::new).collect(),
+            data: base64::encode(&node.data.0),
+            data_json,
+            data_text,
+        }
+    }
+}
+
+impl fmt::Display for NodeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "hash: {}", self.hash)?;
+        writeln!(f, "size: {} bytes", self.size)?;
+        writeln!(f, "links: {}", self.links.len())?;
+        for link in self.links.iter() {
+            writeln!(f, "  {}: {} ({} bytes)", link.id, link.hash, link.size)?;
+        }
+        match (&self.data_json, &self.data_text) {
+            (Some(json), _) => {
+                // ASSERTION: a parsed json value always serializes
+                let pretty = serde_json::to_string_pretty(json).unwrap();
+                writeln!(f, "data (json):\n{}", pretty)
+            }
+            (None, Some(text)) => writeln!(f, "data (text):\n{}", text),
+            (None, None) => writeln!(f, "data (base64):\n{}", self.data),
+        }
+    }
+}
+
+pub async fn get(client: &mut Client, hash: Hash) -> Result<NodeOutput> {
+    let resp = client.get_node(hash).await?;
+    Ok(NodeOutput::new(hash, &resp.requested_node))
+}
+
+#[derive(Serialize)]
+pub struct TreeOutput {
+    /// id of the link this node was reached by, None for the root
+    pub id: Option<String>,
+    pub hash: String,
+    pub size: u64,
+    pub data_bytes: usize,
+    /// false if the node's children weren't fetched, as they're beyond the max depth
+    pub expanded: bool,
+    /// true if the node appears earlier in the tree, its children are only listed there
+    pub repeated: bool,
+    pub children: Vec<TreeOutput>,
+}
+
+impl TreeOutput {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let id = self.id.as_ref().map(|id| format!("{}: ", id));
+        write!(
+            f,
+            "{:indent$}{}{} ({} bytes, {} bytes data)",
+            "",
+            id.unwrap_or_default(),
+            self.hash,
+            self.size,
+            self.data_bytes,
+            indent = indent * 2
+        )?;
+        if self.repeated {
+            write!(f, " (repeated)")?;
+        } else if !self.expanded {
+            write!(f, " ...")?;
+        }
+        writeln!(f)?;
+        for child in self.children.iter() {
+            child.write(f, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TreeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// fetch the nodes of the dag below root, up to max_depth levels down
+pub async fn fetch_dag(
+    client: &mut Client,
+    root: Hash,
+    max_depth: Option<u32>,
+) -> Result<HashMap<Hash, Node>> {
+    let mut nodes = HashMap::new();
+    // shallowest depth each node's links were followed from, a node reached by multiple paths
+    // only needs following again if it's reached at a shallower depth
+    let mut followed_at = HashMap::new();
+    let mut frontier = vec![(root, 0)];
+    while let Some((hash, depth)) = frontier.pop() {
+        if !nodes.contains_key(&hash) {
+            // the dag store sends some of the node's descendants along with it
+            let resp = client.get_node(hash).await?;
+            for extra in resp.extra_nodes {
+                nodes.insert(extra.header.hash, extra.node);
+            }
+            nodes.insert(hash, resp.requested_node);
+        }
+        if matches!(max_depth, Some(max) if depth >= max) {
+            continue;
+        }
+        if matches!(followed_at.get(&hash), Some(d) if *d <= depth) {
+            continue;
+        }
+        followed_at.insert(hash, depth);
+        // ASSERTION: inserted above if not already present
+        for link in nodes[&hash].links.iter() {
+            frontier.push((link.hash, depth + 1));
+        }
+    }
+    Ok(nodes)
+}
+
+pub async fn tree(client: &mut Client, root: Hash, max_depth: Option<u32>) -> Result<TreeOutput> {
+    let nodes = fetch_dag(client, root, max_depth).await?;
+    let mut seen = HashSet::new();
+    Ok(build_tree(None, root, 0, max_depth, &nodes, &mut seen))
+}
+
+fn build_tree(
+    id: Option<String>,
+    hash: Hash,
+    depth: u32,
+    max_depth: Option<u32>,
+    nodes: &HashMap<Hash, Node>,
+    seen: &mut HashSet<Hash>,
+) -> TreeOutput {
+    // ASSERTION: fetch_dag fetches every node up to and including max_depth
+    let node = &nodes[&hash];
+    let repeated = !seen.insert(hash);
+    let expanded = !repeated && !matches!(max_depth, Some(max) if depth >= max);
+    let children = if expanded {
+        node.links
+            .iter()
+            .map(|l| {
+                let id = Some(l.id.to_string());
+                build_tree(id, l.hash, depth + 1, max_depth, nodes, seen)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    TreeOutput {
+        id,
+        hash: hash.to_string(),
+        size: node.dag_size(),
+        data_bytes: node.data.0.len(),
+        expanded: expanded || node.links.is_empty(),
+        repeated,
+        children,
+    }
+}
@@ -0,0 +1,22 @@
+use serde::Serialize;
+use std::fmt;
+
+/// result of a command, printed as text or (with --json) as json for scripting
+pub trait Output: fmt::Display {
+    fn to_json(&self) -> serde_json::Result<String>;
+}
+
+impl<T: Serialize + fmt::Display> Output for T {
+    fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+pub fn emit(output: &dyn Output, json: bool) -> serde_json::Result<()> {
+    if json {
+        println!("{}", output.to_json()?);
+    } else {
+        print!("{}", output);
+    }
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::client::{Client, Result};
+use dag_store_types::types::api::bulk_put::{self, NodeLink};
+use dag_store_types::types::domain::{Header, Id};
+use dag_store_types::types::encodings::Base64;
+use dag_store_types::types::validated_tree::ValidatedTree;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+// max links per interior node
+const FAN_OUT: usize = 1024;
+
+#[derive(Serialize)]
+pub struct PutOutput {
+    pub hash: String,
+    pub bytes: usize,
+    pub node_count: usize,
+    pub key: Option<String>,
+}
+
+impl fmt::Display for PutOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({} bytes in {} nodes)",
+            self.hash, self.bytes, self.node_count
+        )?;
+        if let Some(key) = &self.key {
+            writeln!(f, "{} -> {}", key, self.hash)?;
+        }
+        Ok(())
+    }
+}
+
+/// reads chunk_size pieces of a reader, one ahead so the last chunk is known as it's returned
+struct Chunks<R> {
+    reader: R,
+    chunk_size: usize,
+    next: Option<Vec<u8>>,
+}
+
+impl<R: Read> Chunks<R> {
+    fn new(reader: R, chunk_size: usize) -> io::Result<Self> {
+        let mut chunks = Chunks {
+            reader,
+            chunk_size,
+            next: None,
+        };
+        // an empty reader is a single empty chunk
+        chunks.next = Some(chunks.read()?);
+        Ok(chunks)
+    }
+
+    fn read(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.chunk_size);
+        (&mut self.reader)
+            .take(self.chunk_size as u64)
+            .read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn next(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let chunk = match self.next.take() {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        // a short chunk means the reader is exhausted
+        if chunk.len() == self.chunk_size {
+            let following = self.read()?;
+            if !following.is_empty() {
+                self.next = Some(following);
+            }
+        }
+        Ok(Some(chunk))
+    }
+
+    fn is_done(&self) -> bool {
+        self.next.is_none()
+    }
+}
+
+/// writes a tree bottom up, one request per batch, setting the key (if any) with the last
+struct Upload<'a> {
+    client: &'a mut Client,
+    cas: Option<bulk_put::CAS>,
+    next_id: u128,
+    node_count: usize,
+}
+
+impl<'a> Upload<'a> {
+    fn id(&mut self) -> Id {
+        let id = Id(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// write leaves with data, under a node linking them if there are several, returning a
+    /// header linking to the result. is_root if this is the whole tree
+    async fn leaves(&mut self, mut leaves: Vec<Vec<u8>>, is_root: bool) -> Result<Header> {
+        let size = leaves.iter().map(|l| l.len() as u64).sum();
+        if leaves.len() == 1 {
+            // ASSERTION: leaves has one element
+            let data = leaves.pop().unwrap();
+            return self
+                .put(node(vec![], data), HashMap::new(), size, is_root)
+                .await;
+        }
+
+        let mut nodes = HashMap::new();
+        let mut links = Vec::new();
+        for data in leaves.into_iter() {
+            let id = self.id();
+            nodes.insert(id, node(vec![], data));
+            links.push(NodeLink::Local(id));
+        }
+        self.put(node(links, vec![]), nodes, size, is_root).await
+    }
+
+    /// write a node linking to already written children
+    async fn interior(&mut self, children: &[Header], is_root: bool) -> Result<Header> {
+        let size = children.iter().map(|c| c.size).sum();
+        let links = children.iter().cloned().map(NodeLink::Remote).collect();
+        self.put(node(links, vec![]), HashMap::new(), size, is_root)
+            .await
+    }
+
+    async fn put(
+        &mut self,
+        root_node: bulk_put::Node,
+        nodes: HashMap<Id, bulk_put::Node>,
+        size: u64,
+        is_root: bool,
+    ) -> Result<Header> {
+        let node_count = nodes.len() + 1;
+        // ASSERTION: every node is linked to exactly once by the root
+        let validated_tree = ValidatedTree::validate(root_node, nodes).unwrap();
+        let cas = if is_root { self.cas.take() } else { None };
+        let resp = self
+            .client
+            .put_nodes(bulk_put::Req {
+                validated_tree,
+                cas,
+            })
+            .await?;
+        self.node_count += node_count;
+        Ok(Header {
+            id: self.id(),
+            hash: resp.root_hash,
+            size,
+        })
+    }
+}
+
+fn node(links: Vec<NodeLink>, data: Vec<u8>) -> bulk_put::Node {
+    bulk_put::Node {
+        links,
+        data: Base64(data),
+    }
+}
+
+/// store the contents of reader as a tree of nodes, optionally pointing key at its root. leaves
+/// hold up to chunk_size bytes each, in order. the file is streamed and written bottom up: each
+/// request holds up to batch_bytes of leaves (and the node linking them), then interior nodes
+/// with up to FAN_OUT links are written referring to the nodes already stored
+pub async fn put<R: Read>(
+    client: &mut Client,
+    reader: R,
+    chunk_size: usize,
+    batch_bytes: usize,
+    key: Option<String>,
+) -> Result<PutOutput> {
+    let cas = match &key {
+        Some(key) => Some(bulk_put::CAS {
+            required_previous_hash: client.get_key(key).await?,
+            cas_key: key.clone(),
+        }),
+        None => None,
+    };
+    let mut upload = Upload {
+        client,
+        cas,
+        next_id: 0,
+        node_count: 0,
+    };
+
+    let leaves_per_batch = (batch_bytes / chunk_size).clamp(1, FAN_OUT);
+    let mut chunks = Chunks::new(reader, chunk_size)?;
+    let mut bytes = 0;
+    let mut level = Vec::new();
+    while !chunks.is_done() {
+        let mut leaves = Vec::new();
+        while leaves.len() < leaves_per_batch {
+            match chunks.next()? {
+                Some(chunk) => leaves.push(chunk),
+                None => break,
+            }
+        }
+        bytes += leaves.iter().map(|l| l.len()).sum::<usize>();
+        let is_root = level.is_empty() && chunks.is_done();
+        level.push(upload.leaves(leaves, is_root).await?);
+    }
+
+    while level.len() > 1 {
+        let is_root = level.len() <= FAN_OUT;
+        let mut parents = Vec::new();
+        for children in level.chunks(FAN_OUT) {
+            parents.push(upload.interior(children, is_root).await?);
+        }
+        level = parents;
+    }
+
+    // ASSERTION: there's always at least one chunk, so one node is left at the top
+    let root = level.pop().unwrap();
+    Ok(PutOutput {
+        hash: root.hash.to_string(),
+        bytes,
+        node_count: upload.node_count,
+        key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let read_all = |data: &[u8], chunk_size| {
+            let mut chunks = Chunks::new(data, chunk_size).unwrap();
+            let mut out = Vec::new();
+            while let Some(chunk) = chunks.next().unwrap() {
+                out.push(chunk);
+            }
+            assert!(chunks.is_done());
+            out
+        };
+
+        let data: Vec<u8> = (0..100u8).collect();
+        let chunks = read_all(&data, 30);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), data);
+
+        // exact multiples don't end with an empty chunk, empty input is one empty chunk
+        assert_eq!(read_all(&data, 50).len(), 2);
+        assert_eq!(read_all(&[], 50), vec![Vec::<u8>::new()]);
+    }
+}
@@ -295,3 +295,468 @@ pub mod get {
         }
     }
 }
+
+pub mod export_root {
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    pub enum Req {
+        Hash(Hash),
+        Key(String),
+        AllKeys,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Req {
+        pub fn into_proto(self) -> grpc::ExportRootReq {
+            let root = match self {
+                Req::Hash(h) => grpc::export_root_req::Root::Hash(h.into_proto()),
+                Req::Key(k) => grpc::export_root_req::Root::Key(k),
+                Req::AllKeys => grpc::export_root_req::Root::AllKeys(true),
+            };
+            grpc::ExportRootReq { root: Some(root) }
+        }
+
+        pub fn from_proto(p: grpc::ExportRootReq) -> Result<Self, ProtoDecodingError> {
+            match p.root {
+                Some(grpc::export_root_req::Root::Hash(h)) => Hash::from_proto(h).map(Req::Hash),
+                Some(grpc::export_root_req::Root::Key(k)) => Ok(Req::Key(k)),
+                Some(grpc::export_root_req::Root::AllKeys(true)) => Ok(Req::AllKeys),
+                Some(grpc::export_root_req::Root::AllKeys(false)) | None => Err(
+                    ProtoDecodingError("no value for export root req oneof".to_string()),
+                ),
+            }
+        }
+    }
+}
+
+pub mod import_archive {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ImportedKey {
+        pub key: String,
+        pub hash: Hash,
+        /// false if the key already pointed at a different hash and was left as-is
+        pub written: bool,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Resp {
+        pub node_count: u64,
+        pub keys: Vec<ImportedKey>,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Resp {
+        pub fn into_proto(self) -> grpc::ImportArchiveResp {
+            grpc::ImportArchiveResp {
+                node_count: self.node_count,
+                keys: self
+                    .keys
+                    .into_iter()
+                    .map(|k| grpc::ImportedKey {
+                        key: k.key,
+                        hash: Some(k.hash.into_proto()),
+                        written: k.written,
+                    })
+                    .collect(),
+            }
+        }
+
+        pub fn from_proto(p: grpc::ImportArchiveResp) -> Result<Self, ProtoDecodingError> {
+            let keys: Result<Vec<ImportedKey>, ProtoDecodingError> = p
+                .keys
+                .into_iter()
+                .map(|k| {
+                    let hash = k.hash.ok_or(ProtoDecodingError(
+                        "hash not present on ImportedKey proto".to_string(),
+                    ))?;
+                    let hash = Hash::from_proto(hash)?;
+                    Ok(ImportedKey {
+                        key: k.key,
+                        hash,
+                        written: k.written,
+                    })
+                })
+                .collect();
+            let keys = keys?;
+
+            Ok(Resp {
+                node_count: p.node_count,
+                keys,
+            })
+        }
+    }
+}
+
+pub mod cache_stats {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct AccessCount {
+        pub hash: Hash,
+        pub accesses: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Resp {
+        pub entry_count: u64,
+        pub byte_usage: u64,
+        pub max_bytes: u64,
+        pub hits: u64,
+        pub misses: u64,
+        pub hit_ratio: f64,
+        pub evictions: u64,
+        /// puts of nodes too large to cache
+        pub rejected: u64,
+        /// descending by access count
+        pub most_accessed: Vec<AccessCount>,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Resp {
+        pub fn into_proto(self) -> grpc::GetCacheStatsResp {
+            grpc::GetCacheStatsResp {
+                entry_count: self.entry_count,
+                byte_usage: self.byte_usage,
+                max_bytes: self.max_bytes,
+                hits: self.hits,
+                misses: self.misses,
+                hit_ratio: self.hit_ratio,
+                evictions: self.evictions,
+                rejected: self.rejected,
+                most_accessed: self
+                    .most_accessed
+                    .into_iter()
+                    .map(|a| grpc::HashAccessCount {
+                        hash: Some(a.hash.into_proto()),
+                        accesses: a.accesses,
+                    })
+                    .collect(),
+            }
+        }
+
+        pub fn from_proto(p: grpc::GetCacheStatsResp) -> Result<Self, ProtoDecodingError> {
+            let most_accessed: Result<Vec<AccessCount>, ProtoDecodingError> = p
+                .most_accessed
+                .into_iter()
+                .map(|a| {
+                    let hash = a.hash.ok_or(ProtoDecodingError(
+                        "hash not present on HashAccessCount proto".to_string(),
+                    ))?;
+                    let hash = Hash::from_proto(hash)?;
+                    Ok(AccessCount {
+                        hash,
+                        accesses: a.accesses,
+                    })
+                })
+                .collect();
+            let most_accessed = most_accessed?;
+
+            Ok(Resp {
+                entry_count: p.entry_count,
+                byte_usage: p.byte_usage,
+                max_bytes: p.max_bytes,
+                hits: p.hits,
+                misses: p.misses,
+                hit_ratio: p.hit_ratio,
+                evictions: p.evictions,
+                rejected: p.rejected,
+                most_accessed,
+            })
+        }
+    }
+}
+
+pub mod list_keys {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Req {
+        pub prefix: String,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Req {
+        pub fn into_proto(self) -> grpc::ListKeysReq {
+            grpc::ListKeysReq {
+                prefix: self.prefix,
+            }
+        }
+
+        pub fn from_proto(p: grpc::ListKeysReq) -> Self {
+            Req { prefix: p.prefix }
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub struct Resp {
+        /// ordered by key
+        pub keys: Vec<(String, Hash)>,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Resp {
+        pub fn into_proto(self) -> grpc::ListKeysResp {
+            grpc::ListKeysResp {
+                keys: self
+                    .keys
+                    .into_iter()
+                    .map(|(key, hash)| grpc::KeyHash {
+                        key,
+                        hash: Some(hash.into_proto()),
+                    })
+                    .collect(),
+            }
+        }
+
+        pub fn from_proto(p: grpc::ListKeysResp) -> Result<Self, ProtoDecodingError> {
+            let keys = p
+                .keys
+                .into_iter()
+                .map(|kh| {
+                    let hash = kh.hash.ok_or(ProtoDecodingError(
+                        "hash not present on KeyHash proto".to_string(),
+                    ))?;
+                    Ok((kh.key, Hash::from_proto(hash)?))
+                })
+                .collect::<Result<Vec<_>, ProtoDecodingError>>()?;
+            Ok(Resp { keys })
+        }
+    }
+}
+
+pub mod warm_cache {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Req {
+        pub root: Hash,
+        /// 0 warms only the root node
+        pub depth: u32,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Req {
+        pub fn into_proto(self) -> grpc::WarmCacheReq {
+            grpc::WarmCacheReq {
+                root: Some(self.root.into_proto()),
+                depth: self.depth,
+            }
+        }
+
+        pub fn from_proto(p: grpc::WarmCacheReq) -> Result<Self, ProtoDecodingError> {
+            let root = p.root.ok_or(ProtoDecodingError(
+                "root not present on WarmCacheReq proto".to_string(),
+            ))?;
+            let root = Hash::from_proto(root)?;
+
+            Ok(Req {
+                root,
+                depth: p.depth,
+            })
+        }
+    }
+}
+
+pub mod resolve {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum Root {
+        Hash(Hash),
+        Key(String),
+    }
+
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    pub enum Segment {
+        /// position in the parent's links
+        Index(u64),
+        /// Header.id of one of the parent's links
+        Id(Id),
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Req {
+        pub root: Root,
+        pub path: Vec<Segment>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Resp {
+        pub node: Node,
+        /// headers traversed from the root, one per path segment
+        pub path: Vec<Header>,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Segment {
+        pub fn into_proto(self) -> grpc::PathSegment {
+            let segment = match self {
+                Segment::Index(i) => grpc::path_segment::Segment::Index(i),
+                Segment::Id(id) => grpc::path_segment::Segment::Id(id.into_proto()),
+            };
+            grpc::PathSegment {
+                segment: Some(segment),
+            }
+        }
+
+        pub fn from_proto(p: grpc::PathSegment) -> Result<Self, ProtoDecodingError> {
+            match p.segment {
+                Some(grpc::path_segment::Segment::Index(i)) => Ok(Segment::Index(i)),
+                Some(grpc::path_segment::Segment::Id(id)) => Ok(Segment::Id(Id::from_proto(id)?)),
+                None => Err(ProtoDecodingError(
+                    "segment not present on PathSegment proto".to_string(),
+                )),
+            }
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Req {
+        pub fn into_proto(self) -> grpc::ResolveReq {
+            let root = match self.root {
+                Root::Hash(h) => grpc::resolve_req::Root::Hash(h.into_proto()),
+                Root::Key(k) => grpc::resolve_req::Root::Key(k),
+            };
+            grpc::ResolveReq {
+                root: Some(root),
+                path: self.path.into_iter().map(|s| s.into_proto()).collect(),
+            }
+        }
+
+        pub fn from_proto(p: grpc::ResolveReq) -> Result<Self, ProtoDecodingError> {
+            let root = match p.root {
+                Some(grpc::resolve_req::Root::Hash(h)) => Root::Hash(Hash::from_proto(h)?),
+                Some(grpc::resolve_req::Root::Key(k)) => Root::Key(k),
+                None => {
+                    return Err(ProtoDecodingError(
+                        "root not present on ResolveReq proto".to_string(),
+                    ))
+                }
+            };
+            let path: Result<Vec<Segment>, ProtoDecodingError> =
+                p.path.into_iter().map(Segment::from_proto).collect();
+            let path = path?;
+
+            Ok(Req { root, path })
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Resp {
+        pub fn into_proto(self) -> grpc::ResolveResp {
+            grpc::ResolveResp {
+                node: Some(self.node.into_proto()),
+                path: self.path.into_iter().map(|h| h.into_proto()).collect(),
+            }
+        }
+
+        pub fn from_proto(p: grpc::ResolveResp) -> Result<Self, ProtoDecodingError> {
+            let node = p.node.ok_or(ProtoDecodingError(
+                "node not present on ResolveResp proto".to_string(),
+            ))?;
+            let node = Node::from_proto(node)?;
+            let path: Result<Vec<Header>, ProtoDecodingError> =
+                p.path.into_iter().map(Header::from_proto).collect();
+            let path = path?;
+
+            Ok(Resp { node, path })
+        }
+    }
+}
+
+pub mod diff {
+    use super::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Req {
+        pub old: Hash,
+        pub new: Hash,
+        /// max nodes fetched while diffing, 0 for the server default
+        pub node_budget: u64,
+    }
+
+    /// links are matched up between old and new nodes by Header.id
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Entry {
+        Added(Header),
+        Removed(Header),
+        Changed {
+            old: Header,
+            new: Header,
+        },
+        /// final entry if the diff was cut short
+        BudgetExhausted,
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Req {
+        pub fn into_proto(self) -> grpc::DiffReq {
+            grpc::DiffReq {
+                old: Some(self.old.into_proto()),
+                new: Some(self.new.into_proto()),
+                node_budget: self.node_budget,
+            }
+        }
+
+        pub fn from_proto(p: grpc::DiffReq) -> Result<Self, ProtoDecodingError> {
+            let old = p.old.ok_or(ProtoDecodingError(
+                "old not present on DiffReq proto".to_string(),
+            ))?;
+            let new = p.new.ok_or(ProtoDecodingError(
+                "new not present on DiffReq proto".to_string(),
+            ))?;
+
+            Ok(Req {
+                old: Hash::from_proto(old)?,
+                new: Hash::from_proto(new)?,
+                node_budget: p.node_budget,
+            })
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    impl Entry {
+        pub fn into_proto(self) -> grpc::DiffEntry {
+            let entry = match self {
+                Entry::Added(h) => grpc::diff_entry::Entry::Added(h.into_proto()),
+                Entry::Removed(h) => grpc::diff_entry::Entry::Removed(h.into_proto()),
+                Entry::Changed { old, new } => {
+                    grpc::diff_entry::Entry::Changed(grpc::ChangedLink {
+                        old: Some(old.into_proto()),
+                        new: Some(new.into_proto()),
+                    })
+                }
+                Entry::BudgetExhausted => grpc::diff_entry::Entry::BudgetExhausted(true),
+            };
+            grpc::DiffEntry { entry: Some(entry) }
+        }
+
+        pub fn from_proto(p: grpc::DiffEntry) -> Result<Self, ProtoDecodingError> {
+            match p.entry {
+                Some(grpc::diff_entry::Entry::Added(h)) => Header::from_proto(h).map(Entry::Added),
+                Some(grpc::diff_entry::Entry::Removed(h)) => {
+                    Header::from_proto(h).map(Entry::Removed)
+                }
+                Some(grpc::diff_entry::Entry::Changed(c)) => {
+                    let old = c.old.ok_or(ProtoDecodingError(
+                        "old not present on ChangedLink proto".to_string(),
+                    ))?;
+                    let new = c.new.ok_or(ProtoDecodingError(
+                        "new not present on ChangedLink proto".to_string(),
+                    ))?;
+                    Ok(Entry::Changed {
+                        old: Header::from_proto(old)?,
+                        new: Header::from_proto(new)?,
+                    })
+                }
+                Some(grpc::diff_entry::Entry::BudgetExhausted(true)) => Ok(Entry::BudgetExhausted),
+                Some(grpc::diff_entry::Entry::BudgetExhausted(false)) | None => Err(
+                    ProtoDecodingError("no value for diff entry oneof".to_string()),
+                ),
+            }
+        }
+    }
+}
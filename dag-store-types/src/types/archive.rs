@@ -0,0 +1,525 @@
+use crate::types::domain::{Hash, Header, Id, Node};
+use crate::types::encodings::Base64;
+use std::convert::TryFrom;
+
+// archive layout (all integers big-endian):
+//   header:  magic (8 bytes), version (u8), root count (u32), roots
+//   root:    has key (u8), [key len (u32), utf8 key], hash (32 bytes)
+//   records: tag (u8), then either
+//              node: hash (32 bytes), link count (u32), links, data len (u64), data
+//              link: id (u128), hash (32 bytes), size (u64)
+//            or
+//              end: node count (u64)
+// nodes are written in topological order (children before parents), so an archive
+// can be imported in a single pass without ever writing a node with dangling links
+
+pub const MAGIC: &[u8; 8] = b"dagstore";
+pub const VERSION: u8 = 1;
+
+const TAG_END: u8 = 0;
+const TAG_NODE: u8 = 1;
+
+const HASH_LEN: usize = 32;
+const LINK_LEN: usize = 16 + HASH_LEN + 8;
+
+/// largest header or record the reader will buffer, anything claiming to be larger is rejected
+/// instead of being read into memory
+pub const MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+
+/// root of an archived DAG, optionally associated with a mutable hash store key
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Root {
+    pub key: Option<String>,
+    pub hash: Hash,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ArchiveHeader {
+    pub roots: Vec<Root>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Item {
+    Header(ArchiveHeader),
+    Node(Hash, Node),
+    End { node_count: u64 },
+}
+
+pub fn encode_header(header: &ArchiveHeader, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    buf.extend_from_slice(&(header.roots.len() as u32).to_be_bytes());
+    for root in header.roots.iter() {
+        match &root.key {
+            Some(key) => {
+                buf.push(1);
+                buf.extend_from_slice(&(key.len() as u32).to_be_bytes());
+                buf.extend_from_slice(key.as_bytes());
+            }
+            None => buf.push(0),
+        }
+        buf.extend_from_slice(root.hash.0.as_bytes());
+    }
+}
+
+/// encode a single node record, hash is computed here and verified on decode
+pub fn encode_node(node: &Node, buf: &mut Vec<u8>) {
+    buf.push(TAG_NODE);
+    buf.extend_from_slice(node.canonical_hash().0.as_bytes());
+    buf.extend_from_slice(&(node.links.len() as u32).to_be_bytes());
+    for link in node.links.iter() {
+        buf.extend_from_slice(&link.id.0.to_be_bytes());
+        buf.extend_from_slice(link.hash.0.as_bytes());
+        buf.extend_from_slice(&link.size.to_be_bytes());
+    }
+    buf.extend_from_slice(&(node.data.0.len() as u64).to_be_bytes());
+    buf.extend_from_slice(&node.data.0);
+}
+
+pub fn encode_end(node_count: u64, buf: &mut Vec<u8>) {
+    buf.push(TAG_END);
+    buf.extend_from_slice(&node_count.to_be_bytes());
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum State {
+    Header,
+    Records,
+    Done,
+}
+
+// outcome of parsing the start of a buffer
+enum Parsed {
+    Item(Item, usize),
+    // the item is at least this many bytes long, more are needed to parse it
+    Incomplete(usize),
+}
+
+/// incremental archive decoder - push chunks of bytes in as they arrive, pull items out
+pub struct ArchiveReader {
+    buf: Vec<u8>,
+    // start of the unparsed bytes in buf
+    pos: usize,
+    // bytes the item at pos is known to need, parsing isn't retried until they've arrived
+    needed: usize,
+    state: State,
+    node_count: u64,
+}
+
+impl ArchiveReader {
+    pub fn new() -> Self {
+        ArchiveReader {
+            buf: Vec::new(),
+            pos: 0,
+            needed: 0,
+            state: State::Header,
+            node_count: 0,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        // drop parsed items, at most once per pushed chunk
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn remaining(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// returns the next complete item, or None if more bytes are required
+    pub fn next_item(&mut self) -> Result<Option<Item>, ArchiveError> {
+        if self.remaining().len() < self.needed {
+            return Ok(None);
+        }
+
+        let parsed = match self.state {
+            State::Header => parse_header(self.remaining())?,
+            State::Records => parse_record(self.remaining())?,
+            State::Done => {
+                if self.remaining().is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(ArchiveError(
+                        "trailing bytes after end of archive".to_string(),
+                    ));
+                }
+            }
+        };
+
+        match parsed {
+            Parsed::Incomplete(needed) => {
+                if needed > MAX_RECORD_LEN {
+                    return Err(ArchiveError(format!(
+                        "archive record of at least {} bytes exceeds max of {}",
+                        needed, MAX_RECORD_LEN
+                    )));
+                }
+                self.needed = needed;
+                Ok(None)
+            }
+            Parsed::Item(item, consumed) => {
+                self.pos += consumed;
+                self.needed = 0;
+                match &item {
+                    Item::Header(_) => self.state = State::Records,
+                    Item::Node(_, _) => self.node_count += 1,
+                    Item::End { node_count } => {
+                        if *node_count != self.node_count {
+                            return Err(ArchiveError(format!(
+                                "archive trailer claims {} nodes, read {}",
+                                node_count, self.node_count
+                            )));
+                        }
+                        self.state = State::Done;
+                    }
+                }
+                Ok(Some(item))
+            }
+        }
+    }
+
+    /// fails if the archive was truncated (no end record) or has trailing bytes
+    pub fn finish(&self) -> Result<(), ArchiveError> {
+        if self.state != State::Done {
+            return Err(ArchiveError("archive truncated".to_string()));
+        }
+        if !self.remaining().is_empty() {
+            return Err(ArchiveError(
+                "trailing bytes after end of archive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ArchiveReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// minimal cursor over a byte slice. None signals 'not enough bytes yet', in which case needed
+// holds the number of bytes required to get further
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    needed: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Cursor {
+            bytes,
+            pos: 0,
+            needed: 0,
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            self.needed = self.pos.saturating_add(n);
+            None
+        } else {
+            let res = &self.bytes[self.pos..self.pos + n];
+            self.pos += n;
+            Some(res)
+        }
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|x| x[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut array = [0; 4];
+        array.copy_from_slice(self.take(4)?);
+        Some(u32::from_be_bytes(array))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut array = [0; 8];
+        array.copy_from_slice(self.take(8)?);
+        Some(u64::from_be_bytes(array))
+    }
+
+    fn hash(&mut self) -> Option<Hash> {
+        // ASSERTION: take returns exactly HASH_LEN bytes
+        self.take(HASH_LEN).and_then(Hash::from_bytes)
+    }
+
+    fn incomplete(&self) -> Parsed {
+        Parsed::Incomplete(self.needed)
+    }
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Parsed, ArchiveError> {
+    let mut c = Cursor::new(bytes);
+
+    match c.take(MAGIC.len()) {
+        None => return Ok(c.incomplete()),
+        Some(magic) if magic != MAGIC => {
+            return Err(ArchiveError(
+                "not a dag-store archive (bad magic)".to_string(),
+            ))
+        }
+        Some(_) => (),
+    }
+
+    let version = match c.u8() {
+        None => return Ok(c.incomplete()),
+        Some(v) => v,
+    };
+    if version != VERSION {
+        return Err(ArchiveError(format!(
+            "unsupported archive version {}",
+            version
+        )));
+    }
+
+    let root_count = match c.u32() {
+        None => return Ok(c.incomplete()),
+        Some(n) => n,
+    };
+
+    let mut roots = Vec::new();
+    for _ in 0..root_count {
+        let key = match c.u8() {
+            None => return Ok(c.incomplete()),
+            Some(0) => None,
+            Some(1) => {
+                let len = match c.u32() {
+                    None => return Ok(c.incomplete()),
+                    Some(len) => len as usize,
+                };
+                let key = match c.take(len) {
+                    None => return Ok(c.incomplete()),
+                    Some(key) => key,
+                };
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|e| ArchiveError(format!("root key not valid utf8: {:?}", e)))?;
+                Some(key)
+            }
+            Some(x) => return Err(ArchiveError(format!("invalid root key flag {}", x))),
+        };
+
+        let hash = match c.hash() {
+            None => return Ok(c.incomplete()),
+            Some(hash) => hash,
+        };
+
+        roots.push(Root { key, hash });
+    }
+
+    Ok(Parsed::Item(Item::Header(ArchiveHeader { roots }), c.pos))
+}
+
+fn parse_record(bytes: &[u8]) -> Result<Parsed, ArchiveError> {
+    let mut c = Cursor::new(bytes);
+
+    let node = match c.u8() {
+        None => return Ok(c.incomplete()),
+        Some(TAG_END) => {
+            return Ok(match c.u64() {
+                None => c.incomplete(),
+                Some(node_count) => Parsed::Item(Item::End { node_count }, c.pos),
+            })
+        }
+        Some(TAG_NODE) => (|| {
+            let hash = c.hash()?;
+            let link_count = c.u32()? as usize;
+            // take the links all at once, so the bytes needed are known before any are read
+            let mut links_c = Cursor::new(c.take(link_count * LINK_LEN)?);
+            let mut links = Vec::with_capacity(link_count);
+            for _ in 0..link_count {
+                let mut id = [0; 16];
+                id.copy_from_slice(links_c.take(16)?);
+                let id = Id(u128::from_be_bytes(id));
+                let hash = links_c.hash()?;
+                let size = links_c.u64()?;
+                links.push(Header { id, hash, size });
+            }
+            let data_len = c.u64()?;
+            let data_len = usize::try_from(data_len).unwrap_or(usize::MAX);
+            let data = c.take(data_len)?.to_vec();
+            Some((
+                hash,
+                Node {
+                    links,
+                    data: Base64(data),
+                },
+            ))
+        })(),
+        Some(tag) => return Err(ArchiveError(format!("invalid record tag {}", tag))),
+    };
+
+    match node {
+        None => Ok(c.incomplete()),
+        Some((hash, node)) => {
+            let actual = node.canonical_hash();
+            if actual != hash {
+                return Err(ArchiveError(format!(
+                    "hash mismatch for archived node, expected {} got {}",
+                    hash, actual
+                )));
+            }
+            Ok(Parsed::Item(Item::Node(hash, node), c.pos))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveError(pub String);
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(data: Vec<u8>) -> Node {
+        Node {
+            links: vec![],
+            data: Base64(data),
+        }
+    }
+
+    fn archive() -> (Vec<u8>, Vec<Item>) {
+        let l1 = leaf(vec![1, 3, 3, 7]);
+        let l2 = leaf(vec![3, 1, 4, 1, 5]);
+        let root = Node {
+            links: vec![
+                Header {
+                    id: Id(1),
+                    hash: l1.canonical_hash(),
+                    size: 4,
+                },
+                Header {
+                    id: Id(2),
+                    hash: l2.canonical_hash(),
+                    size: 5,
+                },
+            ],
+            data: Base64(vec![0, 1, 1, 2]),
+        };
+
+        let header = ArchiveHeader {
+            roots: vec![Root {
+                key: Some("notes-app".to_string()),
+                hash: root.canonical_hash(),
+            }],
+        };
+
+        let mut buf = Vec::new();
+        encode_header(&header, &mut buf);
+        encode_node(&l1, &mut buf);
+        encode_node(&l2, &mut buf);
+        encode_node(&root, &mut buf);
+        encode_end(3, &mut buf);
+
+        let items = vec![
+            Item::Header(header),
+            Item::Node(l1.canonical_hash(), l1),
+            Item::Node(l2.canonical_hash(), l2),
+            Item::Node(root.canonical_hash(), root),
+            Item::End { node_count: 3 },
+        ];
+
+        (buf, items)
+    }
+
+    #[test]
+    fn test_round_trip_byte_at_a_time() {
+        let (buf, expected) = archive();
+
+        let mut reader = ArchiveReader::new();
+        let mut items = Vec::new();
+        for b in buf.iter() {
+            reader.push(&[*b]);
+            while let Some(item) = reader.next_item().expect("decode failed") {
+                items.push(item);
+            }
+        }
+        reader.finish().expect("archive incomplete");
+
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn test_corrupt_node_rejected() {
+        let (mut buf, _) = archive();
+        // flip the last data byte of the root node (just before the end record)
+        let idx = buf.len() - 10;
+        buf[idx] ^= 0xFF;
+
+        let mut reader = ArchiveReader::new();
+        reader.push(&buf);
+        let res: Result<(), ArchiveError> = (|| {
+            while reader.next_item()?.is_some() {}
+            Ok(())
+        })();
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_truncated_archive_rejected() {
+        let (buf, _) = archive();
+        // cut off part way through the root node
+        let truncated = &buf[..buf.len() - 20];
+
+        let mut reader = ArchiveReader::new();
+        reader.push(truncated);
+        let mut items = 0;
+        while reader.next_item().expect("decode failed").is_some() {
+            items += 1;
+        }
+
+        // header and both leaves
+        assert_eq!(items, 3);
+        assert!(reader.finish().is_err());
+    }
+
+    #[test]
+    fn test_oversized_record_rejected() {
+        let (buf, _) = archive();
+        let mut reader = ArchiveReader::new();
+        // just the header, then a node record claiming a huge data len
+        reader.push(&buf[..MAGIC.len() + 1 + 4 + 1 + 4 + "notes-app".len() + HASH_LEN]);
+        assert!(matches!(reader.next_item(), Ok(Some(Item::Header(_)))));
+
+        let mut record = vec![TAG_NODE];
+        record.extend_from_slice(leaf(vec![]).canonical_hash().0.as_bytes());
+        record.extend_from_slice(&0u32.to_be_bytes());
+        record.extend_from_slice(&u64::MAX.to_be_bytes());
+        reader.push(&record);
+        assert!(reader.next_item().is_err());
+
+        // same for a huge link count
+        let mut reader = ArchiveReader::new();
+        reader.push(&buf[..MAGIC.len() + 1 + 4 + 1 + 4 + "notes-app".len() + HASH_LEN]);
+        reader.next_item().unwrap();
+        let mut record = vec![TAG_NODE];
+        record.extend_from_slice(leaf(vec![]).canonical_hash().0.as_bytes());
+        record.extend_from_slice(&u32::MAX.to_be_bytes());
+        reader.push(&record);
+        assert!(reader.next_item().is_err());
+    }
+}
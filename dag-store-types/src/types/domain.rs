@@ -200,6 +200,11 @@ pub struct Node {
 }
 
 impl Node {
+    /// size of the dag rooted at this node: its data plus the size each link records
+    pub fn dag_size(&self) -> u64 {
+        self.data.0.len() as u64 + self.links.iter().map(|l| l.size).sum::<u64>()
+    }
+
     /// stable hashing function (not using proto because there's no canonical encoding)
     pub fn canonical_hash(&self) -> Hash {
         let mut hasher = blake3::Hasher::new();
@@ -1,19 +1,57 @@
+use crate::types::archive::ArchiveError;
 use crate::types::domain::Hash;
 use std::error::Error;
 #[cfg(feature = "grpc")]
+use std::time::Duration;
+#[cfg(feature = "grpc")]
 use tonic::{Code, Status};
 
-#[derive(Debug)]
+// tonic statuses can't carry metadata, so retry hints are appended to the message
+#[cfg(feature = "grpc")]
+const RETRY_AFTER_PREFIX: &str = ", retry after ";
+
+#[derive(Clone, Debug)]
 pub enum DagCacheError {
     ProtoDecodingError(ProtoDecodingError),
+    ArchiveError(ArchiveError),
     UnexpectedError(String),
-    CASViolationError { actual_hash: Option<Hash> },
+    /// malformed request field, retrying won't help
+    InvalidArgument(String),
+    NotFound(String),
+    PermissionDenied(String),
+    QuotaExceeded(String),
+    /// request exceeds a configured size limit, retrying won't help
+    LimitExceeded(String),
+    /// client is sending requests too quickly, may succeed after retry_after_millis
+    RateLimited {
+        reason: String,
+        retry_after_millis: u64,
+    },
+    CASViolationError {
+        actual_hash: Option<Hash>,
+    },
 }
 
 impl DagCacheError {
     pub fn unexpected<E: std::error::Error>(e: E) -> Self {
         DagCacheError::UnexpectedError(format!("unexpected error: {}", e))
     }
+
+    /// name of the variant, for use as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DagCacheError::ProtoDecodingError(_) => "ProtoDecodingError",
+            DagCacheError::ArchiveError(_) => "ArchiveError",
+            DagCacheError::UnexpectedError(_) => "UnexpectedError",
+            DagCacheError::InvalidArgument(_) => "InvalidArgument",
+            DagCacheError::NotFound(_) => "NotFound",
+            DagCacheError::PermissionDenied(_) => "PermissionDenied",
+            DagCacheError::QuotaExceeded(_) => "QuotaExceeded",
+            DagCacheError::LimitExceeded(_) => "LimitExceeded",
+            DagCacheError::RateLimited { .. } => "RateLimited",
+            DagCacheError::CASViolationError { .. } => "CASViolationError",
+        }
+    }
 }
 
 #[cfg(feature = "grpc")]
@@ -24,9 +62,25 @@ impl From<DagCacheError> for Status {
                 Code::InvalidArgument,
                 format!("error decoding proto, {:?}", de),
             ),
+            DagCacheError::ArchiveError(ae) => Status::new(
+                Code::InvalidArgument,
+                format!("error decoding archive, {:?}", ae),
+            ),
             DagCacheError::UnexpectedError(s) => {
                 Status::new(Code::Internal, format!("unexpected error: {:?}", s))
             }
+            DagCacheError::InvalidArgument(s) => Status::new(Code::InvalidArgument, s),
+            DagCacheError::NotFound(s) => Status::new(Code::NotFound, s),
+            DagCacheError::PermissionDenied(s) => Status::new(Code::PermissionDenied, s),
+            DagCacheError::QuotaExceeded(s) => Status::new(Code::ResourceExhausted, s),
+            DagCacheError::LimitExceeded(s) => Status::new(Code::ResourceExhausted, s),
+            DagCacheError::RateLimited {
+                reason,
+                retry_after_millis,
+            } => Status::new(
+                Code::ResourceExhausted,
+                format!("{}{}{}ms", reason, RETRY_AFTER_PREFIX, retry_after_millis),
+            ),
             DagCacheError::CASViolationError { actual_hash } => Status::new(
                 Code::DeadlineExceeded,
                 format!("cas violation: actual: {:?}", actual_hash),
@@ -34,13 +88,31 @@ impl From<DagCacheError> for Status {
         }
     }
 }
+
+/// the retry hint attached to a rate limited request's status, if any
+#[cfg(feature = "grpc")]
+pub fn retry_after(status: &Status) -> Option<Duration> {
+    if status.code() != Code::ResourceExhausted {
+        return None;
+    }
+    let (_, hint) = status.message().rsplit_once(RETRY_AFTER_PREFIX)?;
+    let millis = hint.strip_suffix("ms")?.parse().ok()?;
+    Some(Duration::from_millis(millis))
+}
+
+impl From<ArchiveError> for DagCacheError {
+    fn from(error: ArchiveError) -> DagCacheError {
+        DagCacheError::ArchiveError(error)
+    }
+}
+
 impl From<ProtoDecodingError> for DagCacheError {
     fn from(error: ProtoDecodingError) -> DagCacheError {
         DagCacheError::ProtoDecodingError(error)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ProtoDecodingError(pub String);
 
 impl std::fmt::Display for ProtoDecodingError {
@@ -1,4 +1,5 @@
 pub mod api;
+pub mod archive;
 pub mod domain;
 pub mod encodings;
 pub mod errors;
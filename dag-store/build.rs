@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_build::configure().compile(
+        &["proto/otlp_trace.proto", "proto/health.proto"],
+        &["proto"],
+    )?;
+    Ok(())
+}
@@ -1,17 +1,97 @@
 #![deny(warnings)]
-use dag_store::{opts, run};
+use dag_store::{cache_snapshot, metrics, opts, run_with_shutdown};
 use opts::Opt;
+use std::path::PathBuf;
 use structopt::StructOpt;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opt = Opt::from_args();
-    // TODO: move addr parsing _into_ opts
-    let bind_to = format!("0.0.0.0:{}", &opt.port);
-    let runtime = opt.into_runtime();
+    let print_config = opt.print_config;
+    let config = match opt.into_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid config: {}", e);
+            std::process::exit(2);
+        }
+    };
+    if print_config {
+        print!("{}", dag_store::config::to_toml(&config));
+        return Ok(());
+    }
 
-    let addr = bind_to.parse().unwrap();
+    let addr = config.bind_addr;
+    let replicate_keys = config.replicate_keys.clone();
+    let server_tls = match config.server_tls() {
+        Ok(server_tls) => server_tls,
+        Err(e) => {
+            eprintln!("invalid config: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let metrics_addr = config.metrics_addr();
+    let snapshot_path = config.cache_snapshot.clone().map(PathBuf::from);
+    let (runtime, replicator) = match config.into_runtime() {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("invalid config: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let cache = runtime.cache.clone();
 
-    run(runtime, addr).await?;
+    if let Some(path) = &snapshot_path {
+        cache_snapshot::spawn_rehydrate(&runtime.hashed_blob_store, &cache, path.clone());
+    }
+
+    if let Some(metrics_addr) = metrics_addr {
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_addr, cache).await {
+                error!("metrics server failed: {:?}", e);
+            }
+        });
+    }
+
+    if let Some(replicator) = replicator {
+        replicator.spawn(replicate_keys);
+    }
+
+    run_with_shutdown(runtime, addr, server_tls, shutdown_signal()).await?;
+
+    if let Some(path) = &snapshot_path {
+        if let Err(e) = cache_snapshot::write(&cache, path) {
+            error!("failed writing cache snapshot: {:?}", e);
+        }
+    }
     Ok(())
 }
+
+/// resolves on SIGINT or SIGTERM
+async fn shutdown_signal() {
+    // failure to install a handler means we can't shut down gracefully on that signal
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("failed installing SIGTERM handler: {:?}", e);
+            if tokio::signal::ctrl_c().await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+            return;
+        }
+    };
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => match res {
+            Ok(()) => info!("received SIGINT"),
+            Err(_) => {
+                // no SIGINT handler, wait on SIGTERM alone
+                sigterm.recv().await;
+                info!("received SIGTERM");
+            }
+        },
+        _ = sigterm.recv() => info!("received SIGTERM"),
+    }
+}
@@ -0,0 +1,126 @@
+use crate::capabilities::{Cache, HashedBlobStore};
+use dag_store_types::types::domain::Hash;
+use dag_store_types::types::errors::DagCacheError;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+
+/// write the hashes of all cached nodes to path, one base58 hash per line, least recently
+/// used first. only hashes are persisted, nodes are re-read from the store on rehydration
+#[instrument(skip(cache))]
+pub fn write(cache: &Cache, path: &Path) -> Result<usize, DagCacheError> {
+    let hashes = cache.hashes_lru_order();
+
+    // write then rename, so a crash mid-write doesn't leave a truncated snapshot
+    let tmp_path = tmp_path(path);
+    let mut file = fs::File::create(&tmp_path).map_err(DagCacheError::unexpected)?;
+    for hash in hashes.iter() {
+        writeln!(file, "{}", hash.to_base58()).map_err(DagCacheError::unexpected)?;
+    }
+    file.sync_all().map_err(DagCacheError::unexpected)?;
+    fs::rename(&tmp_path, path).map_err(DagCacheError::unexpected)?;
+
+    info!("wrote {} hashes to cache snapshot", hashes.len());
+    Ok(hashes.len())
+}
+
+fn read(path: &Path) -> Result<Vec<Hash>, DagCacheError> {
+    let contents = fs::read_to_string(path).map_err(DagCacheError::unexpected)?;
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Hash::from_base58(line).map_err(|e| {
+                DagCacheError::UnexpectedError(format!("invalid hash in cache snapshot: {:?}", e))
+            })
+        })
+        .collect()
+}
+
+/// load the nodes listed in a snapshot into the cache, in snapshot order so LRU order is
+/// preserved. a missing snapshot is not an error, nodes missing from the store are skipped
+#[instrument(skip(store, cache))]
+pub async fn rehydrate(
+    store: &Arc<dyn HashedBlobStore>,
+    cache: &Arc<Cache>,
+    path: &Path,
+) -> Result<usize, DagCacheError> {
+    if !path.exists() {
+        info!("no cache snapshot found, starting with empty cache");
+        return Ok(0);
+    }
+
+    let hashes = read(path)?;
+    let mut loaded = 0;
+    for hash in hashes.into_iter() {
+        match store.get(hash).await {
+            Ok(node) => {
+                cache.put(hash, Arc::new(node));
+                loaded += 1;
+            }
+            Err(e) => error!("unable to rehydrate {} from store: {:?}", hash, e),
+        }
+    }
+
+    info!("rehydrated {} nodes from cache snapshot", loaded);
+    Ok(loaded)
+}
+
+/// rehydrate in the background, so the server can start serving immediately
+pub fn spawn_rehydrate(store: &Arc<dyn HashedBlobStore>, cache: &Arc<Cache>, path: PathBuf) {
+    let store = store.clone();
+    let cache = cache.clone();
+    tokio::spawn(async move {
+        if let Err(e) = rehydrate(&store, &cache, &path).await {
+            error!("cache rehydration failed: {:?}", e);
+        }
+    });
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, test_runtime};
+    use dag_store_types::types::domain::Node;
+    use dag_store_types::types::encodings::Base64;
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        init_test_env();
+        let (runtime, dir) = test_runtime();
+        let path = dir.path().join("cache_snapshot");
+
+        let mut hashes = Vec::new();
+        for i in 0..4 {
+            let node = Node {
+                links: vec![],
+                data: Base64(vec![i]),
+            };
+            let hash = runtime.hashed_blob_store.put(node.clone()).await.unwrap();
+            runtime.cache.put(hash, Arc::new(node));
+            hashes.push(hash);
+        }
+        // most recently used
+        runtime.cache.get(hashes[0]);
+
+        assert_eq!(write(&runtime.cache, &path).unwrap(), 4);
+
+        let restarted = Arc::new(Cache::new(1024 * 1024));
+        let loaded = rehydrate(&runtime.hashed_blob_store, &restarted, &path)
+            .await
+            .unwrap();
+        assert_eq!(loaded, 4);
+        assert_eq!(
+            restarted.hashes_lru_order(),
+            vec![hashes[1], hashes[2], hashes[3], hashes[0]]
+        );
+    }
+}
@@ -1,27 +1,286 @@
-use dag_store_types::types::domain::{Hash, Node};
+use crate::capabilities::single_flight::SingleFlight;
+use dag_store_types::types::domain::{Hash, Header, Node};
 use lru::LruCache;
-use std::sync::Mutex;
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-pub struct Cache(pub Mutex<LruCache<Hash, Node>>);
+// number of independently-locked shards, requests for different shards never contend
+const SHARD_COUNT: usize = 16;
+
+/// sharded LRU cache bounded by the total size of cached nodes (see `node_size`)
+pub struct Cache {
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    rejected: AtomicU64,
+    // logical clock used to order entries across shards
+    clock: AtomicU64,
+    /// store reads for uncached nodes currently in progress, shared by concurrent misses
+    pub in_flight: SingleFlight<Hash, Arc<Node>>,
+}
+
+struct Shard {
+    entries: LruCache<Hash, Entry>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+struct Entry {
+    node: Arc<Node>,
+    // number of cache hits since this node was inserted
+    accesses: u64,
+    // value of the cache clock as of the last put or hit
+    last_access: u64,
+}
+
+/// point-in-time snapshot of cache counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: u64,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// puts of nodes too large to cache, see `Cache::new`
+    pub rejected: u64,
+}
+
+/// approximate in-memory size of a node: data plus link headers
+pub fn node_size(node: &Node) -> usize {
+    node.data.0.len() + node.links.len() * std::mem::size_of::<Header>()
+}
+
+// hashes are uniformly distributed, so the first byte is as good as any
+fn shard_index(k: &Hash) -> usize {
+    k.0.as_bytes()[0] as usize % SHARD_COUNT
+}
 
 impl Cache {
-    pub fn new(max_cache_entries: usize) -> Self {
-        let cache = LruCache::new(max_cache_entries);
-        // TODO: use RW lock instead, probably
-        Cache(Mutex::new(cache))
+    /// each shard holds max_cache_bytes / SHARD_COUNT bytes, so nodes larger than that
+    /// (see `node_size`) are never cached and every read of them goes to the store.
+    /// such puts are counted in `CacheStats::rejected`
+    pub fn new(max_cache_bytes: usize) -> Self {
+        let max_bytes = max_cache_bytes / SHARD_COUNT;
+        let shards = (0..SHARD_COUNT)
+            .map(|_| {
+                Mutex::new(Shard {
+                    entries: LruCache::unbounded(),
+                    bytes: 0,
+                    max_bytes,
+                })
+            })
+            .collect();
+
+        Cache {
+            shards,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            clock: AtomicU64::new(0),
+            in_flight: SingleFlight::new(),
+        }
     }
 
-    // TODO: rain says investigate stable deref (given that all refs here are immutable)
-    pub fn get(&self, k: Hash) -> Option<Node> {
+    fn shard(&self, k: &Hash) -> &Mutex<Shard> {
+        &self.shards[shard_index(k)]
+    }
+
+    pub fn get(&self, k: Hash) -> Option<Arc<Node>> {
         // succeed or die. failure is unrecoverable (mutex poisoned)
-        let mut cache = self.0.lock().unwrap();
-        let mv = cache.get(&k);
-        mv.cloned() // this feels weird? clone(d) is actually needed, right?
+        let mut shard = self.shard(&k).lock().unwrap();
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        let res = shard.entries.get_mut(&k).map(|entry| {
+            entry.accesses += 1;
+            entry.last_access = now;
+            entry.node.clone()
+        });
+        match res {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        res
+    }
+
+    /// get without touching LRU order or hit/miss counters
+    pub fn peek(&self, k: Hash) -> Option<Arc<Node>> {
+        let shard = self.shard(&k).lock().unwrap();
+        shard.entries.peek(&k).map(|entry| entry.node.clone())
     }
 
-    pub fn put(&self, k: Hash, v: Node) {
+    /// check for presence without touching LRU order or hit/miss counters
+    pub fn contains(&self, k: Hash) -> bool {
+        let shard = self.shard(&k).lock().unwrap();
+        shard.entries.contains(&k)
+    }
+
+    pub fn put(&self, k: Hash, v: Arc<Node>) {
+        let size = node_size(&v);
         // succeed or die. failure is unrecoverable (mutex poisoned)
-        let mut cache = self.0.lock().unwrap();
-        cache.put(k, v);
+        let mut shard = self.shard(&k).lock().unwrap();
+        if size > shard.max_bytes {
+            // would evict everything else in the shard and still not fit
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let entry = Entry {
+            node: v,
+            accesses: 0,
+            last_access: self.clock.fetch_add(1, Ordering::Relaxed),
+        };
+        if let Some(prev) = shard.entries.put(k, entry) {
+            shard.bytes -= node_size(&prev.node);
+        }
+        shard.bytes += size;
+
+        while shard.bytes > shard.max_bytes {
+            match shard.entries.pop_lru() {
+                Some((_, evicted)) => {
+                    shard.bytes -= node_size(&evicted.node);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            entries: 0,
+            bytes: 0,
+            max_bytes: 0,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        };
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            stats.entries += shard.entries.len() as u64;
+            stats.bytes += shard.bytes as u64;
+            stats.max_bytes += shard.max_bytes as u64;
+        }
+        stats
+    }
+
+    /// up to n cached hashes with the most hits, descending by hit count
+    pub fn most_accessed(&self, n: usize) -> Vec<(Hash, u64)> {
+        // min-heap of the top n seen so far, the least accessed of them at the root
+        let mut top = BinaryHeap::with_capacity(n + 1);
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            for (k, e) in shard.entries.iter() {
+                top.push(Ranked(e.accesses, *k));
+                if top.len() > n {
+                    top.pop();
+                }
+            }
+        }
+        let mut counts: Vec<_> = top.into_iter().map(|Ranked(a, k)| (k, a)).collect();
+        counts.sort_by_key(|(_, accesses)| Reverse(*accesses));
+        counts
+    }
+
+    /// all cached hashes, least recently used first
+    pub fn hashes_lru_order(&self) -> Vec<Hash> {
+        let mut hashes = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            hashes.extend(shard.entries.iter().map(|(k, e)| (*k, e.last_access)));
+        }
+        hashes.sort_by_key(|(_, last_access)| *last_access);
+        hashes.into_iter().map(|(k, _)| k).collect()
+    }
+}
+
+// cached hash ranked by access count alone, reversed so a BinaryHeap pops the least accessed
+struct Ranked(u64, Hash);
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.0.cmp(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dag_store_types::types::encodings::Base64;
+
+    fn node(data: Vec<u8>) -> Arc<Node> {
+        Arc::new(Node {
+            links: vec![],
+            data: Base64(data),
+        })
+    }
+
+    #[test]
+    fn test_bounded_by_bytes() {
+        let cache = Cache::new(SHARD_COUNT * 100);
+
+        // pick nodes that all land in the same shard
+        let nodes: Vec<_> = (0..=255u8).map(|i| node(vec![i; 40])).collect();
+        let shard = shard_index(&nodes[0].canonical_hash());
+        let same_shard: Vec<_> = nodes
+            .into_iter()
+            .filter(|n| shard_index(&n.canonical_hash()) == shard)
+            .take(3)
+            .collect();
+        assert_eq!(same_shard.len(), 3);
+
+        for n in same_shard.iter() {
+            cache.put(n.canonical_hash(), n.clone());
+        }
+
+        // 3 * 40 bytes doesn't fit in a 100 byte shard, least recently used is evicted
+        assert!(cache.get(same_shard[0].canonical_hash()).is_none());
+        assert!(cache.get(same_shard[1].canonical_hash()).is_some());
+        assert!(cache.get(same_shard[2].canonical_hash()).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.bytes, 80);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+
+        cache.get(same_shard[2].canonical_hash());
+        assert_eq!(
+            cache.most_accessed(1),
+            vec![(same_shard[2].canonical_hash(), 2)]
+        );
+
+        assert_eq!(
+            cache.hashes_lru_order(),
+            vec![
+                same_shard[1].canonical_hash(),
+                same_shard[2].canonical_hash()
+            ]
+        );
+
+        // too large to ever fit, not cached
+        let huge = node(vec![0; 200]);
+        cache.put(huge.canonical_hash(), huge.clone());
+        assert!(!cache.contains(huge.canonical_hash()));
+        assert_eq!(cache.stats().rejected, 1);
     }
 }
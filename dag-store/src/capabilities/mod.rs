@@ -1,5 +1,10 @@
 pub mod cache;
+pub mod remote;
+pub mod single_flight;
 pub mod store;
+pub mod tenant;
+pub mod tiered;
+pub mod watch;
 pub use crate::capabilities::cache::Cache;
 use dag_store_types::types::domain::{Hash, Node};
 use dag_store_types::types::errors::DagCacheError;
@@ -15,7 +20,23 @@ where
     Self: Send + Sync,
 {
     async fn get(&self, k: Hash) -> Result<Node, DagCacheError>;
+    async fn has(&self, k: Hash) -> Result<bool, DagCacheError>;
     async fn put(&self, v: Node) -> Result<Hash, DagCacheError>;
+
+    /// whether each of ks is present, in order. stores that can check many hashes in one round
+    /// trip should override this
+    async fn has_many(&self, ks: &[Hash]) -> Result<Vec<bool>, DagCacheError> {
+        let mut present = Vec::with_capacity(ks.len());
+        for k in ks.iter() {
+            present.push(self.has(*k).await?);
+        }
+        Ok(present)
+    }
+
+    /// persist any buffered writes, called on shutdown
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        Ok(())
+    }
 }
 
 // used to store key->hash mappings for CAS use
@@ -25,12 +46,18 @@ where
     Self: Send + Sync,
 {
     async fn get(&self, k: &str) -> Result<Option<Hash>, DagCacheError>;
+    async fn list(&self) -> Result<Vec<(String, Hash)>, DagCacheError>;
     async fn cas(
         &self,
         k: &str,
         previous_hash: Option<Hash>,
         proposed_hash: Hash,
     ) -> Result<(), DagCacheError>;
+
+    /// persist any buffered writes, called on shutdown
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        Ok(())
+    }
 }
 
 #[instrument(skip(store, cache))]
@@ -38,7 +65,7 @@ pub async fn get_and_cache<'a>(
     store: &'a Arc<dyn HashedBlobStore>,
     cache: &'a Arc<Cache>,
     hash: Hash,
-) -> Result<Node, DagCacheError> {
+) -> Result<Arc<Node>, DagCacheError> {
     match cache.get(hash.clone()) {
         Some(dag_node) => {
             info!("cache hit");
@@ -47,10 +74,18 @@ pub async fn get_and_cache<'a>(
         None => {
             info!("cache miss");
 
-            let dag_node = store.get(hash.clone()).await?;
+            // concurrent misses for the same hash share a single store read
+            let dag_node = cache
+                .in_flight
+                .run(hash, || async {
+                    let dag_node = Arc::new(store.get(hash).await?);
+
+                    info!("writing result of post cache miss lookup to cache");
+                    cache.put(hash, dag_node.clone());
 
-            info!("writing result of post cache miss lookup to cache");
-            cache.put(hash.clone(), dag_node.clone());
+                    Ok(dag_node)
+                })
+                .await?;
 
             Ok(dag_node)
         }
@@ -65,7 +100,96 @@ pub async fn put_and_cache<'a>(
 ) -> Result<Hash, DagCacheError> {
     let hash = store.put(node.clone()).await?;
 
-    cache.put(hash.clone(), node);
+    cache.put(hash.clone(), Arc::new(node));
 
     Ok(hash)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dag_store_types::types::encodings::Base64;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    // holds a single node, slow enough for concurrent reads to overlap
+    struct SlowStore {
+        node: Node,
+        reads: AtomicUsize,
+    }
+
+    #[tonic::async_trait]
+    impl HashedBlobStore for SlowStore {
+        async fn get(&self, k: Hash) -> Result<Node, DagCacheError> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            if k == self.node.canonical_hash() {
+                Ok(self.node.clone())
+            } else {
+                Err(DagCacheError::NotFound(format!("{}", k)))
+            }
+        }
+
+        async fn has(&self, k: Hash) -> Result<bool, DagCacheError> {
+            Ok(k == self.node.canonical_hash())
+        }
+
+        async fn put(&self, _v: Node) -> Result<Hash, DagCacheError> {
+            unimplemented!()
+        }
+    }
+
+    async fn get_concurrently(
+        store: &Arc<dyn HashedBlobStore>,
+        cache: &Arc<Cache>,
+        hash: Hash,
+    ) -> Vec<Result<Arc<Node>, DagCacheError>> {
+        let mut handles = Vec::new();
+        for _ in 0..4 {
+            let store = store.clone();
+            let cache = cache.clone();
+            handles.push(tokio::spawn(async move {
+                get_and_cache(&store, &cache, hash).await
+            }));
+        }
+        let mut results = Vec::new();
+        for handle in handles.into_iter() {
+            results.push(handle.await.unwrap());
+        }
+        results
+    }
+
+    #[tokio::test]
+    async fn test_get_and_cache_shares_reads() {
+        let node = Node {
+            links: vec![],
+            data: Base64(vec![1, 3, 3, 7]),
+        };
+        let present = node.canonical_hash();
+        let missing = Node {
+            links: vec![],
+            data: Base64(vec![0]),
+        }
+        .canonical_hash();
+        let slow = Arc::new(SlowStore {
+            node: node.clone(),
+            reads: AtomicUsize::new(0),
+        });
+        let store: Arc<dyn HashedBlobStore> = slow.clone();
+        let cache = Arc::new(Cache::new(1024));
+
+        for res in get_concurrently(&store, &cache, present).await {
+            assert_eq!(*res.unwrap(), node);
+        }
+        assert_eq!(slow.reads.load(Ordering::SeqCst), 1);
+
+        // waiters see the leader's error as-is, not flattened into UnexpectedError
+        for res in get_concurrently(&store, &cache, missing).await {
+            match res {
+                Err(DagCacheError::NotFound(_)) => {}
+                other => panic!("expected NotFound, got {:?}", other),
+            }
+        }
+        assert_eq!(slow.reads.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::capabilities::HashedBlobStore;
+use crate::tls::{self, ClientTls};
+use dag_store_types::types::domain::{Hash, Node};
+use dag_store_types::types::errors::DagCacheError;
+use dag_store_types::types::grpc::{dag_store_client::DagStoreClient, HasNodesReq};
+use tokio::sync::Mutex;
+use tonic::transport::{Channel, Endpoint};
+use tracing::{info, instrument};
+
+/// store backed by another dag-store instance, accessed via grpc
+pub struct RemoteStore {
+    endpoint: Endpoint,
+    // bearer token presented to the remote, if it requires auth
+    token: Option<String>,
+    // connected lazily on first use, dropped on transport errors to force a reconnect
+    client: Mutex<Option<DagStoreClient<Channel>>>,
+}
+
+impl RemoteStore {
+    pub fn new(
+        url: String,
+        tls: Option<&ClientTls>,
+        token: Option<String>,
+    ) -> Result<Self, DagCacheError> {
+        let endpoint = tls::endpoint(url, tls)?;
+        Ok(RemoteStore {
+            endpoint,
+            token,
+            client: Mutex::new(None),
+        })
+    }
+
+    async fn client(&self) -> Result<DagStoreClient<Channel>, DagCacheError> {
+        let mut client = self.client.lock().await;
+        match &*client {
+            Some(c) => Ok(c.clone()),
+            None => {
+                info!("connecting to remote dag store");
+                let channel = self
+                    .endpoint
+                    .connect()
+                    .await
+                    .map_err(DagCacheError::unexpected)?;
+                let c = tls::client(channel, self.token.as_deref())?;
+                *client = Some(c.clone());
+                Ok(c)
+            }
+        }
+    }
+
+    async fn reset(&self, status: tonic::Status) -> DagCacheError {
+        if status.code() == tonic::Code::Unknown || status.code() == tonic::Code::Unavailable {
+            *self.client.lock().await = None;
+        }
+        DagCacheError::UnexpectedError(format!("remote dag store error: {:?}", status))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_remote(&self, hash: Hash) -> Result<Node, DagCacheError> {
+        let mut client = self.client().await?;
+        let resp = match client
+            .get_node(tonic::Request::new(hash.into_proto()))
+            .await
+        {
+            Ok(resp) => resp.into_inner(),
+            Err(status) => return Err(self.reset(status).await),
+        };
+
+        let node = resp
+            .requested_node
+            .ok_or_else(|| DagCacheError::UnexpectedError("remote returned no node".to_string()))?;
+        let node = Node::from_proto(node)?;
+
+        // content-addressed, so no need to trust the remote
+        let actual = node.canonical_hash();
+        if actual != hash {
+            return Err(DagCacheError::UnexpectedError(format!(
+                "remote returned node with hash {} for {}",
+                actual, hash
+            )));
+        }
+
+        Ok(node)
+    }
+
+    #[instrument(skip(self, hashes))]
+    async fn has_remote(&self, hashes: &[Hash]) -> Result<Vec<bool>, DagCacheError> {
+        let mut client = self.client().await?;
+        let req = HasNodesReq {
+            hashes: hashes.iter().map(|h| h.into_proto()).collect(),
+        };
+        let resp = match client.has_nodes(tonic::Request::new(req)).await {
+            Ok(resp) => resp.into_inner(),
+            Err(status) => return Err(self.reset(status).await),
+        };
+
+        if resp.present.len() != hashes.len() {
+            return Err(DagCacheError::UnexpectedError(format!(
+                "remote returned presence of {} nodes, asked for {}",
+                resp.present.len(),
+                hashes.len()
+            )));
+        }
+        Ok(resp.present)
+    }
+
+    #[instrument(skip(self, v))]
+    async fn put_remote(&self, v: Node) -> Result<Hash, DagCacheError> {
+        let mut client = self.client().await?;
+        let hash = match client.put_node(tonic::Request::new(v.into_proto())).await {
+            Ok(resp) => resp.into_inner(),
+            Err(status) => return Err(self.reset(status).await),
+        };
+
+        let hash = Hash::from_proto(hash)?;
+        Ok(hash)
+    }
+}
+
+#[tonic::async_trait]
+impl HashedBlobStore for RemoteStore {
+    async fn get(&self, hash: Hash) -> Result<Node, DagCacheError> {
+        self.get_remote(hash).await
+    }
+
+    async fn has(&self, hash: Hash) -> Result<bool, DagCacheError> {
+        Ok(self.has_remote(&[hash]).await?[0])
+    }
+
+    async fn has_many(&self, hashes: &[Hash]) -> Result<Vec<bool>, DagCacheError> {
+        self.has_remote(hashes).await
+    }
+
+    async fn put(&self, v: Node) -> Result<Hash, DagCacheError> {
+        self.put_remote(v).await
+    }
+}
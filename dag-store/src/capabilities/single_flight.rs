@@ -0,0 +1,147 @@
+use dag_store_types::types::errors::DagCacheError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+use tracing::info;
+
+type Waiter<V> = oneshot::Sender<Result<V, DagCacheError>>;
+
+/// deduplicates concurrent computations keyed by K: while one is in flight, any other
+/// caller for the same key waits for and shares its result instead of running its own.
+/// errors are shared as-is, so waiters see eg NotFound just as the leader does
+pub struct SingleFlight<K, V> {
+    // presence of a key means a computation is in flight, value is everyone waiting on it
+    in_flight: Mutex<HashMap<K, Vec<Waiter<V>>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn run<F, Fut>(&self, k: K, f: F) -> Result<V, DagCacheError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, DagCacheError>>,
+    {
+        let waiting = {
+            // succeed or die. failure is unrecoverable (mutex poisoned)
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get_mut(&k) {
+                Some(waiters) => {
+                    let (send, recv) = oneshot::channel();
+                    waiters.push(send);
+                    Some(recv)
+                }
+                None => {
+                    in_flight.insert(k.clone(), Vec::new());
+                    None
+                }
+            }
+        };
+
+        match waiting {
+            Some(recv) => match recv.await {
+                Ok(res) => res,
+                // leader was dropped before completing, do the work ourselves
+                Err(_) => {
+                    info!("in-flight request abandoned, retrying");
+                    f().await
+                }
+            },
+            None => {
+                let guard = Leader {
+                    flight: self,
+                    k: Some(k),
+                };
+                let res = f().await;
+
+                let waiters = guard.complete();
+                for waiter in waiters.into_iter() {
+                    // receiver may have been dropped, that's fine
+                    let _ = waiter.send(res.clone());
+                }
+
+                res
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// removes the in-flight entry even if the leading future is dropped before completion,
+// which drops the waiters' senders and lets them retry
+struct Leader<'a, K: Hash + Eq, V> {
+    flight: &'a SingleFlight<K, V>,
+    k: Option<K>,
+}
+
+impl<'a, K: Hash + Eq, V> Leader<'a, K, V> {
+    fn complete(mut self) -> Vec<Waiter<V>> {
+        match self.k.take() {
+            Some(k) => self.remove(&k),
+            None => Vec::new(),
+        }
+    }
+
+    fn remove(&self, k: &K) -> Vec<Waiter<V>> {
+        let mut in_flight = self.flight.in_flight.lock().unwrap();
+        in_flight.remove(k).unwrap_or_default()
+    }
+}
+
+impl<'a, K: Hash + Eq, V> Drop for Leader<'a, K, V> {
+    fn drop(&mut self) {
+        if let Some(k) = self.k.take() {
+            self.remove(&k);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_coalesced() {
+        let flight = Arc::new(SingleFlight::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            let flight = flight.clone();
+            let calls = calls.clone();
+            handles.push(tokio::spawn(async move {
+                flight
+                    .run(1u8, || async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::delay_for(Duration::from_millis(100)).await;
+                        Ok(42u64)
+                    })
+                    .await
+                    .unwrap()
+            }));
+        }
+
+        for handle in handles.into_iter() {
+            assert_eq!(handle.await.unwrap(), 42);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // nothing left in flight, next call runs again
+        let res = flight.run(1u8, || async { Ok(7u64) }).await.unwrap();
+        assert_eq!(res, 7);
+    }
+}
@@ -1,20 +1,353 @@
 use crate::capabilities::{HashedBlobStore, MutableHashStore};
+use crate::metrics::{CAS_CONFLICTS, STORE_DURATION};
 use dag_store_types::types::domain::{Hash, Node};
 use dag_store_types::types::errors::DagCacheError;
+use dag_store_types::types::grpc;
 use prost::Message;
-use tracing::instrument;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{event, instrument, Level};
 
-/// store backed by local fs sled db (embedded)
-pub struct FileSystemStore(sled::Db);
+/// store backed by local fs sled db (embedded). blobs and cas keys are kept in separate trees,
+/// so no key can overwrite a blob
+pub struct FileSystemStore {
+    db: sled::Db,
+    /// encoded nodes, keyed by the raw bytes of their hash
+    blobs: sled::Tree,
+    /// cas keys, valued with the raw bytes of the hash they point to
+    keys: sled::Tree,
+}
+
+/// outcome of FileSystemStore::gc
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GcStats {
+    pub reachable_nodes: u64,
+    pub removed_nodes: u64,
+    /// encoded size of the removed nodes
+    pub removed_bytes: u64,
+}
+
+/// an entry FileSystemStore::fsck found a problem with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FsckProblem {
+    /// the blob's hash (or raw key, if it isn't a valid hash) or the cas key
+    pub entry: String,
+    pub reason: String,
+}
+
+/// outcome of FileSystemStore::fsck
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FsckReport {
+    pub blobs_checked: u64,
+    pub keys_checked: u64,
+    /// blobs that don't decode or don't hash to the key they're stored under
+    pub corrupt_blobs: Vec<FsckProblem>,
+    /// links from intact blobs to blobs that are missing or corrupt
+    pub dangling_links: Vec<FsckProblem>,
+    /// links recording a size other than that of the dag they point to
+    pub size_mismatches: Vec<FsckProblem>,
+    /// cas keys holding something other than a hash, or pointing to a missing or corrupt blob
+    pub dangling_keys: Vec<FsckProblem>,
+    /// corrupt blobs and dangling keys moved to the quarantine trees
+    pub quarantined: u64,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_blobs.is_empty()
+            && self.dangling_links.is_empty()
+            && self.size_mismatches.is_empty()
+            && self.dangling_keys.is_empty()
+    }
+}
+
+const BLOBS_TREE: &str = "blobs";
+const KEYS_TREE: &str = "cas_keys";
+// sled trees fsck moves bad entries to, valued as they were in the store and keyed by
+// quarantine_key
+const QUARANTINED_BLOBS_TREE: &str = "quarantined_blobs";
+const QUARANTINED_KEYS_TREE: &str = "quarantined_keys";
+// the single tree fsck quarantined to alongside the single tree layout, keyed as that layout was
+const LEGACY_QUARANTINE_TREE: &str = "quarantine";
 
 impl FileSystemStore {
     pub fn new(path: String) -> Self {
-        let db = sled::open(path).unwrap();
-        FileSystemStore(db)
+        Self::open(&path).unwrap()
+    }
+
+    /// open (or create) the sled db at path, migrating it from the single tree layout if needed
+    pub fn open(path: &str) -> Result<Self, DagCacheError> {
+        let db = sled::open(path).map_err(DagCacheError::unexpected)?;
+        let blobs = db
+            .open_tree(BLOBS_TREE)
+            .map_err(DagCacheError::unexpected)?;
+        let keys = db.open_tree(KEYS_TREE).map_err(DagCacheError::unexpected)?;
+        let store = FileSystemStore { db, blobs, keys };
+        store.migrate()?;
+        store.migrate_quarantine()?;
+        Ok(store)
+    }
+
+    /// move entries out of the default tree, where blobs keyed by `<base58>.blake3` and cas keys
+    /// used to share the same keyspace. each entry is copied before it's removed, so an
+    /// interrupted migration picks up where it left off on the next open. cas keys that look
+    /// like blob keys had already overwritten the blob (and weren't listed), so they're moved as
+    /// blobs, where fsck will report them as corrupt
+    fn migrate(&self) -> Result<(), DagCacheError> {
+        if self.db.is_empty() {
+            return Ok(());
+        }
+
+        let mut blobs = 0u64;
+        let mut keys = 0u64;
+        for kv in self.db.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            match legacy_blob_hash(&k) {
+                Some(hash) => {
+                    self.blobs
+                        .insert(encode(hash), v)
+                        .map_err(DagCacheError::unexpected)?;
+                    blobs += 1;
+                }
+                None => {
+                    self.keys.insert(&k, v).map_err(DagCacheError::unexpected)?;
+                    keys += 1;
+                }
+            }
+            self.db.remove(&k).map_err(DagCacheError::unexpected)?;
+        }
+        self.flush_db()?;
+
+        event!(
+            Level::INFO,
+            msg = "migrated sled db to separate blob and key trees",
+            blobs,
+            keys
+        );
+        Ok(())
+    }
+
+    /// move entries out of the quarantine tree fsck used with the single tree layout into the
+    /// blob and key quarantine trees, sorting them as migrate does, and drop it once empty. an
+    /// interrupted migration may leave an entry quarantined twice, but never loses one
+    fn migrate_quarantine(&self) -> Result<(), DagCacheError> {
+        let exists = self
+            .db
+            .tree_names()
+            .iter()
+            .any(|name| &**name == LEGACY_QUARANTINE_TREE.as_bytes());
+        if !exists {
+            return Ok(());
+        }
+
+        let legacy = self
+            .db
+            .open_tree(LEGACY_QUARANTINE_TREE)
+            .map_err(DagCacheError::unexpected)?;
+        let blobs = self
+            .db
+            .open_tree(QUARANTINED_BLOBS_TREE)
+            .map_err(DagCacheError::unexpected)?;
+        let keys = self
+            .db
+            .open_tree(QUARANTINED_KEYS_TREE)
+            .map_err(DagCacheError::unexpected)?;
+        let at = now_nanos()?;
+        let mut entries = 0u64;
+        for kv in legacy.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            match legacy_blob_hash(&k) {
+                Some(hash) => blobs.insert(quarantine_key(&encode(hash), at), v),
+                None => keys.insert(quarantine_key(&k, at), v),
+            }
+            .map_err(DagCacheError::unexpected)?;
+            legacy.remove(&k).map_err(DagCacheError::unexpected)?;
+            entries += 1;
+        }
+        drop(legacy);
+        self.db
+            .drop_tree(LEGACY_QUARANTINE_TREE.as_bytes())
+            .map_err(DagCacheError::unexpected)?;
+        self.flush_db()?;
+
+        event!(
+            Level::INFO,
+            msg = "migrated fsck quarantine to separate blob and key trees",
+            entries
+        );
+        Ok(())
     }
 
-    fn get_and_decode<X: Message + Default>(&self, k: &str) -> Result<Option<X>, DagCacheError> {
-        let res = self.0.get(k).map_err(DagCacheError::unexpected)?;
+    /// remove nodes that aren't reachable from any key, or just count them if dry_run is set.
+    /// only safe while no server has the store open (sled's lock enforces this), as a server may
+    /// be holding nodes written ahead of pointing a key at them
+    pub fn gc(&self, dry_run: bool) -> Result<GcStats, DagCacheError> {
+        let mut stats = GcStats::default();
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<Hash> = self.list_mhs()?.into_iter().map(|(_, h)| h).collect();
+        while let Some(hash) = stack.pop() {
+            if !reachable.insert(hash) {
+                continue;
+            }
+            // dangling links are left for fsck to report, but a node that can't be decoded
+            // aborts the gc as its links (which may be present) can't be marked
+            let node = self
+                .get_and_decode(hash)?
+                .map(Node::from_proto)
+                .transpose()?;
+            if let Some(node) = node {
+                stats.reachable_nodes += 1;
+                stack.extend(node.links.iter().map(|l| l.hash));
+            }
+        }
+
+        let mut unreachable = Vec::new();
+        for kv in self.blobs.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            match Hash::from_bytes(&k) {
+                Some(hash) if !reachable.contains(&hash) => {
+                    stats.removed_nodes += 1;
+                    stats.removed_bytes += v.len() as u64;
+                    unreachable.push(k);
+                }
+                _ => {}
+            }
+        }
+
+        if !dry_run {
+            for k in unreachable {
+                self.blobs.remove(k).map_err(DagCacheError::unexpected)?;
+            }
+            self.flush_db()?;
+        }
+        Ok(stats)
+    }
+
+    /// check every stored blob decodes and hashes to its key, every link points to an intact
+    /// blob of the recorded size and every cas key points to an intact blob. if quarantine is
+    /// set, corrupt blobs and dangling keys are moved out of the store into separate trees. as
+    /// with gc, only run this while no server has the store open
+    pub fn fsck(&self, quarantine: bool) -> Result<FsckReport, DagCacheError> {
+        let mut report = FsckReport::default();
+        // dag size of each intact blob, and the links out of them
+        let mut sizes = HashMap::new();
+        let mut links = Vec::new();
+        let mut bad_blobs = Vec::new();
+        let mut bad_keys = Vec::new();
+
+        for kv in self.blobs.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            report.blobs_checked += 1;
+            let node = match Hash::from_bytes(&k) {
+                None => Err("key is not a valid hash".to_string()),
+                Some(hash) => check_blob(hash, &v),
+            };
+            match node {
+                Ok(node) => {
+                    let hash = node.canonical_hash();
+                    sizes.insert(hash, node.dag_size());
+                    links.extend(node.links.into_iter().map(|l| (hash, l)));
+                }
+                Err(reason) => {
+                    let entry = match Hash::from_bytes(&k) {
+                        Some(hash) => hash.to_string(),
+                        None => format!("{:?}", &*k),
+                    };
+                    report.corrupt_blobs.push(FsckProblem { entry, reason });
+                    bad_blobs.push(k);
+                }
+            }
+        }
+
+        for (parent, link) in links {
+            match sizes.get(&link.hash) {
+                None => report.dangling_links.push(FsckProblem {
+                    entry: parent.to_string(),
+                    reason: format!("link {} to missing or corrupt blob {}", link.id, link.hash),
+                }),
+                Some(size) if *size != link.size => report.size_mismatches.push(FsckProblem {
+                    entry: parent.to_string(),
+                    reason: format!(
+                        "link {} to {} records size {}, actual size {}",
+                        link.id, link.hash, link.size, size
+                    ),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for kv in self.keys.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            report.keys_checked += 1;
+            let reason = match Hash::from_bytes(&v) {
+                None => Some(format!("value is {} bytes, not a hash", v.len())),
+                Some(hash) if !sizes.contains_key(&hash) => {
+                    Some(format!("points to missing or corrupt blob {}", hash))
+                }
+                Some(_) => None,
+            };
+            if let Some(reason) = reason {
+                report.dangling_keys.push(FsckProblem {
+                    entry: String::from_utf8_lossy(&k).into_owned(),
+                    reason,
+                });
+                bad_keys.push(k);
+            }
+        }
+
+        if quarantine {
+            let at = now_nanos()?;
+            report.quarantined +=
+                self.quarantine(&self.blobs, QUARANTINED_BLOBS_TREE, bad_blobs, at)?;
+            report.quarantined +=
+                self.quarantine(&self.keys, QUARANTINED_KEYS_TREE, bad_keys, at)?;
+            self.flush_db()?;
+        }
+        Ok(report)
+    }
+
+    // move entries from tree to the named quarantine tree, returning the number moved. each
+    // entry is copied before it's removed, so an interrupted fsck leaves it in place to be found
+    // (and quarantined) again
+    fn quarantine(
+        &self,
+        tree: &sled::Tree,
+        quarantine_tree: &str,
+        keys: Vec<sled::IVec>,
+        at: u128,
+    ) -> Result<u64, DagCacheError> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let quarantine_tree = self
+            .db
+            .open_tree(quarantine_tree)
+            .map_err(DagCacheError::unexpected)?;
+        let mut moved = 0;
+        for k in keys {
+            if let Some(v) = tree.get(&k).map_err(DagCacheError::unexpected)? {
+                quarantine_tree
+                    .insert(quarantine_key(&k, at), v)
+                    .map_err(DagCacheError::unexpected)?;
+                tree.remove(&k).map_err(DagCacheError::unexpected)?;
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+
+    fn flush_db(&self) -> Result<(), DagCacheError> {
+        self.db.flush().map_err(DagCacheError::unexpected)?;
+        Ok(())
+    }
+
+    fn get_and_decode<X: Message + Default>(&self, hash: Hash) -> Result<Option<X>, DagCacheError> {
+        let _timer = STORE_DURATION.with_label_values(&["read"]).start_timer();
+        let res = self
+            .blobs
+            .get(encode(hash))
+            .map_err(DagCacheError::unexpected)?;
         let proto: Option<X> = res
             .map(std::io::Cursor::new)
             .map(Message::decode)
@@ -26,15 +359,23 @@ impl FileSystemStore {
 
     #[instrument(skip(self))]
     fn get_blob(&self, hash: Hash) -> Result<Node, DagCacheError> {
-        let proto = self.get_and_decode(&hash.to_string_canonical())?;
+        let proto = self.get_and_decode(hash)?;
         let proto = proto
             .ok_or_else(|| DagCacheError::UnexpectedError("broken link in sled db!".to_string()))?;
         let res = Node::from_proto(proto)?;
         Ok(res)
     }
 
+    #[instrument(skip(self))]
+    fn has_blob(&self, hash: Hash) -> Result<bool, DagCacheError> {
+        self.blobs
+            .contains_key(encode(hash))
+            .map_err(DagCacheError::unexpected)
+    }
+
     #[instrument(skip(self, v))]
     fn put_blob(&self, v: Node) -> Result<Hash, DagCacheError> {
+        let _timer = STORE_DURATION.with_label_values(&["write"]).start_timer();
         let hash = v.canonical_hash();
 
         let mut buf = vec![];
@@ -42,8 +383,8 @@ impl FileSystemStore {
             .encode(&mut buf)
             .map_err(DagCacheError::unexpected)?;
 
-        self.0
-            .insert(hash.to_string_canonical(), buf)
+        self.blobs
+            .insert(encode(hash), buf)
             .map_err(DagCacheError::unexpected)?;
 
         Ok(hash)
@@ -51,8 +392,19 @@ impl FileSystemStore {
 
     #[instrument(skip(self))]
     fn get_mhs(&self, k: &str) -> Result<Option<Hash>, DagCacheError> {
-        let res = self.0.get(k).map_err(DagCacheError::unexpected)?;
-        let res = res.map(decode);
+        let res = self.keys.get(k).map_err(DagCacheError::unexpected)?;
+        res.map(|v| decode(k.as_bytes(), v)).transpose()
+    }
+
+    #[instrument(skip(self))]
+    fn list_mhs(&self) -> Result<Vec<(String, Hash)>, DagCacheError> {
+        let mut res = Vec::new();
+        for kv in self.keys.iter() {
+            let (k, v) = kv.map_err(DagCacheError::unexpected)?;
+            let hash = decode(&k, v)?;
+            let k = String::from_utf8(k.to_vec()).map_err(DagCacheError::unexpected)?;
+            res.push((k, hash));
+        }
         Ok(res)
     }
 
@@ -64,21 +416,63 @@ impl FileSystemStore {
         proposed_hash: Hash,
     ) -> Result<(), DagCacheError> {
         let cas_res =
-            self.0
+            self.keys
                 .compare_and_swap(k, previous_hash.map(encode), Some(encode(proposed_hash)));
         let cas_res = cas_res.unwrap();
 
-        cas_res.map_err(
-            |e: sled::CompareAndSwapError| DagCacheError::CASViolationError {
-                actual_hash: e.current.map(decode),
-            },
-        )
+        match cas_res {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                CAS_CONFLICTS.inc();
+                Err(DagCacheError::CASViolationError {
+                    actual_hash: e.current.map(|v| decode(k.as_bytes(), v)).transpose()?,
+                })
+            }
+        }
     }
 }
 
-fn decode(hash: sled::IVec) -> Hash {
-    // FIXME/TODO: is this recoverable? not really, but I still don't like panic here
-    Hash::from_bytes(&hash).expect("invalid bytes for hash in CAS store, panic")
+// hash a blob was stored under in the default tree, None if k isn't a blob key
+fn legacy_blob_hash(k: &[u8]) -> Option<Hash> {
+    let b58 = std::str::from_utf8(k).ok()?.strip_suffix(".blake3")?;
+    Hash::from_base58(b58).ok()
+}
+
+fn now_nanos() -> Result<u128, DagCacheError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(DagCacheError::unexpected)?;
+    Ok(now.as_nanos())
+}
+
+// the original key followed by `.<unix nanos>` of the fsck run that quarantined it, so an entry
+// quarantined again (a key reset to another missing blob, say) doesn't overwrite the first
+fn quarantine_key(k: &[u8], at: u128) -> Vec<u8> {
+    let mut res = k.to_vec();
+    res.extend_from_slice(format!(".{}", at).as_bytes());
+    res
+}
+
+// decode a stored blob, checking it hashes to the hash it's stored under
+fn check_blob(hash: Hash, v: &[u8]) -> Result<Node, String> {
+    let proto = grpc::Node::decode(v).map_err(|e| format!("blob doesn't decode: {:?}", e))?;
+    let node = Node::from_proto(proto).map_err(|e| format!("invalid blob: {:?}", e))?;
+    let actual = node.canonical_hash();
+    if actual != hash {
+        return Err(format!("blob hashes to {}", actual));
+    }
+    Ok(node)
+}
+
+// the hash cas key k holds. fsck reports (and can quarantine) keys holding anything else
+fn decode(k: &[u8], hash: sled::IVec) -> Result<Hash, DagCacheError> {
+    Hash::from_bytes(&hash).ok_or_else(|| {
+        DagCacheError::UnexpectedError(format!(
+            "cas key {} holds {} bytes, not a hash",
+            String::from_utf8_lossy(k),
+            hash.len()
+        ))
+    })
 }
 
 fn encode(hash: Hash) -> Vec<u8> {
@@ -91,9 +485,17 @@ impl HashedBlobStore for FileSystemStore {
         self.get_blob(hash)
     }
 
+    async fn has(&self, hash: Hash) -> Result<bool, DagCacheError> {
+        self.has_blob(hash)
+    }
+
     async fn put(&self, v: Node) -> Result<Hash, DagCacheError> {
         self.put_blob(v)
     }
+
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        self.flush_db()
+    }
 }
 
 #[tonic::async_trait]
@@ -102,6 +504,10 @@ impl MutableHashStore for FileSystemStore {
         self.get_mhs(k)
     }
 
+    async fn list(&self) -> Result<Vec<(String, Hash)>, DagCacheError> {
+        self.list_mhs()
+    }
+
     async fn cas(
         &self,
         k: &str,
@@ -110,4 +516,213 @@ impl MutableHashStore for FileSystemStore {
     ) -> Result<(), DagCacheError> {
         self.cas_mhs(k, previous_hash, proposed_hash)
     }
+
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        self.flush_db()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dag_store_types::types::domain::{Header, Id};
+    use dag_store_types::types::encodings::Base64;
+
+    fn leaf(data: &[u8]) -> Node {
+        Node {
+            links: vec![],
+            data: Base64(data.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_gc() {
+        let dir = tempdir::TempDir::new("dag-store-gc").unwrap();
+        let store = FileSystemStore::open(dir.path().to_str().unwrap()).unwrap();
+
+        let child = store.put_blob(leaf(b"child")).unwrap();
+        let parent = Node {
+            links: vec![Header {
+                id: Id(0),
+                hash: child,
+                size: 5,
+            }],
+            data: Base64(vec![]),
+        };
+        let parent = store.put_blob(parent).unwrap();
+        let orphan = store.put_blob(leaf(b"orphan")).unwrap();
+        store.cas_mhs("root", None, parent).unwrap();
+
+        let stats = store.gc(true).unwrap();
+        assert_eq!(stats.reachable_nodes, 2);
+        assert_eq!(stats.removed_nodes, 1);
+        assert!(store.has_blob(orphan).unwrap());
+
+        assert_eq!(store.gc(false).unwrap(), stats);
+        assert!(!store.has_blob(orphan).unwrap());
+        assert!(store.has_blob(child).unwrap());
+        assert_eq!(store.get_mhs("root").unwrap(), Some(parent));
+        assert_eq!(store.gc(false).unwrap().removed_nodes, 0);
+    }
+
+    #[test]
+    fn test_fsck() {
+        let dir = tempdir::TempDir::new("dag-store-fsck").unwrap();
+        let store = FileSystemStore::open(dir.path().to_str().unwrap()).unwrap();
+
+        let child = store.put_blob(leaf(b"child")).unwrap();
+        let missing = leaf(b"missing").canonical_hash();
+        let parent = Node {
+            links: vec![
+                Header {
+                    id: Id(0),
+                    hash: child,
+                    size: 4,
+                },
+                Header {
+                    id: Id(1),
+                    hash: missing,
+                    size: 7,
+                },
+            ],
+            data: Base64(vec![]),
+        };
+        let parent = store.put_blob(parent).unwrap();
+        store.cas_mhs("parent", None, parent).unwrap();
+        store.cas_mhs("missing", None, missing).unwrap();
+
+        let corrupt = leaf(b"corrupt").canonical_hash();
+        let mut buf = vec![];
+        leaf(b"not corrupt").into_proto().encode(&mut buf).unwrap();
+        store.blobs.insert(encode(corrupt), buf).unwrap();
+        store.cas_mhs("corrupt", None, corrupt).unwrap();
+
+        let report = store.fsck(false).unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.blobs_checked, 3);
+        assert_eq!(report.keys_checked, 3);
+        assert_eq!(report.corrupt_blobs.len(), 1);
+        assert_eq!(report.corrupt_blobs[0].entry, corrupt.to_string());
+        assert_eq!(report.dangling_links.len(), 1);
+        assert_eq!(report.dangling_links[0].entry, parent.to_string());
+        assert_eq!(report.size_mismatches.len(), 1);
+        let dangling: Vec<&str> = report
+            .dangling_keys
+            .iter()
+            .map(|p| p.entry.as_str())
+            .collect();
+        assert_eq!(dangling, vec!["corrupt", "missing"]);
+        assert_eq!(report.quarantined, 0);
+
+        let report = store.fsck(true).unwrap();
+        assert_eq!(report.quarantined, 3);
+        assert!(!store.has_blob(corrupt).unwrap());
+        assert_eq!(store.get_mhs("corrupt").unwrap(), None);
+        let quarantine = store.db.open_tree(QUARANTINED_KEYS_TREE).unwrap();
+        assert_eq!(quarantine.scan_prefix("missing.").count(), 1);
+
+        // quarantining the same key again keeps both entries
+        store.cas_mhs("missing", None, missing).unwrap();
+        let report = store.fsck(true).unwrap();
+        assert_eq!(report.quarantined, 1);
+        assert_eq!(quarantine.scan_prefix("missing.").count(), 2);
+
+        // the dangling link is reported but left in place, its parent is intact
+        let report = store.fsck(true).unwrap();
+        assert_eq!(report.keys_checked, 1);
+        assert_eq!(report.dangling_links.len(), 1);
+        assert!(report.corrupt_blobs.is_empty());
+        assert!(report.dangling_keys.is_empty());
+        assert_eq!(report.quarantined, 0);
+    }
+
+    #[test]
+    fn test_malformed_key_is_an_error() {
+        let dir = tempdir::TempDir::new("dag-store-malformed").unwrap();
+        let store = FileSystemStore::open(dir.path().to_str().unwrap()).unwrap();
+
+        store.keys.insert("malformed", vec![1, 2, 3]).unwrap();
+        assert!(store.get_mhs("malformed").is_err());
+        assert!(store.list_mhs().is_err());
+        let hash = store.put_blob(leaf(b"blob")).unwrap();
+        assert!(store.cas_mhs("malformed", None, hash).is_err());
+        assert!(store.gc(true).is_err());
+    }
+
+    #[test]
+    fn test_keys_dont_collide_with_blobs() {
+        let dir = tempdir::TempDir::new("dag-store-keys").unwrap();
+        let store = FileSystemStore::open(dir.path().to_str().unwrap()).unwrap();
+
+        let blob = store.put_blob(leaf(b"blob")).unwrap();
+        let other = store.put_blob(leaf(b"other")).unwrap();
+        store
+            .cas_mhs(&blob.to_string_canonical(), None, other)
+            .unwrap();
+
+        assert_eq!(store.get_blob(blob).unwrap(), leaf(b"blob"));
+        assert_eq!(
+            store.list_mhs().unwrap(),
+            vec![(blob.to_string_canonical(), other)]
+        );
+    }
+
+    // sled's flusher thread can hold the lock for a moment after a db is dropped
+    fn reopen(path: &str) -> FileSystemStore {
+        for _ in 0..100 {
+            if let Ok(store) = FileSystemStore::open(path) {
+                return store;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        FileSystemStore::open(path).unwrap()
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempdir::TempDir::new("dag-store-migrate").unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let blob = leaf(b"blob");
+        let hash = blob.canonical_hash();
+        {
+            // the single tree layout
+            let db = sled::open(path).unwrap();
+            let mut buf = vec![];
+            blob.clone().into_proto().encode(&mut buf).unwrap();
+            db.insert(hash.to_string_canonical(), buf).unwrap();
+            db.insert("key", encode(hash)).unwrap();
+            // and its fsck quarantine
+            let quarantine = db.open_tree(LEGACY_QUARANTINE_TREE).unwrap();
+            quarantine
+                .insert(leaf(b"corrupt").canonical_hash().to_string_canonical(), "")
+                .unwrap();
+            quarantine.insert("dangling", "").unwrap();
+            db.flush().unwrap();
+        }
+
+        let store = reopen(path);
+        assert!(store.db.is_empty());
+        assert!(!store
+            .db
+            .tree_names()
+            .contains(&LEGACY_QUARANTINE_TREE.as_bytes().into()));
+        let quarantined = |tree, prefix: &[u8]| {
+            // a tree holds the db open, so dropped here for the reopen below to take the lock
+            let tree = store.db.open_tree(tree).unwrap();
+            tree.scan_prefix(prefix).count()
+        };
+        let corrupt = encode(leaf(b"corrupt").canonical_hash());
+        assert_eq!(quarantined(QUARANTINED_BLOBS_TREE, &corrupt), 1);
+        assert_eq!(quarantined(QUARANTINED_KEYS_TREE, b"dangling."), 1);
+        assert_eq!(store.get_blob(hash).unwrap(), blob);
+        assert_eq!(store.list_mhs().unwrap(), vec![("key".to_string(), hash)]);
+        assert!(store.fsck(false).unwrap().is_clean());
+        drop(store);
+
+        // nothing left to migrate on reopening
+        let store = reopen(path);
+        assert_eq!(store.get_mhs("key").unwrap(), Some(hash));
+        assert_eq!(store.fsck(false).unwrap().blobs_checked, 1);
+    }
 }
@@ -0,0 +1,293 @@
+use crate::capabilities::MutableHashStore;
+use dag_store_types::types::domain::Hash;
+use dag_store_types::types::errors::DagCacheError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// metadata field carrying the tenant id, requests without it use the default tenant
+pub const TENANT_ID_META_FIELD: &str = "tenant-id";
+
+// keys belonging to named tenants, reserved in the default tenant's keyspace
+const TENANT_KEY_PREFIX: &str = "tenants/";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tenant {
+    /// unscoped keyspace used by clients that don't identify a tenant, all such clients share a
+    /// single quota
+    Default,
+    Named(String),
+}
+
+impl Tenant {
+    pub fn parse(id: &str) -> Result<Self, DagCacheError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if id.is_empty() || !id.chars().all(valid) {
+            return Err(DagCacheError::InvalidArgument(format!(
+                "invalid tenant id {:?}, must be non-empty and contain only [a-zA-Z0-9_-]",
+                id
+            )));
+        }
+        Ok(Tenant::Named(id.to_string()))
+    }
+
+    /// map a key as seen by this tenant to the underlying store key
+    pub fn scope_key(&self, key: &str) -> Result<String, DagCacheError> {
+        match self {
+            Tenant::Default if key.starts_with(TENANT_KEY_PREFIX) => {
+                Err(DagCacheError::PermissionDenied(format!(
+                    "key {} belongs to a tenant keyspace",
+                    key
+                )))
+            }
+            Tenant::Default => Ok(key.to_string()),
+            Tenant::Named(id) => Ok(format!("{}{}/{}", TENANT_KEY_PREFIX, id, key)),
+        }
+    }
+
+    /// inverse of scope_key, None for store keys not visible to this tenant
+    pub fn unscope_key<'a>(&self, key: &'a str) -> Option<&'a str> {
+        match self {
+            Tenant::Default if key.starts_with(TENANT_KEY_PREFIX) => None,
+            Tenant::Default => Some(key),
+            Tenant::Named(id) => key.strip_prefix(&format!("{}{}/", TENANT_KEY_PREFIX, id)),
+        }
+    }
+
+    /// a view of the store restricted to this tenant's keys
+    pub fn scope(&self, mhs: &Arc<dyn MutableHashStore>) -> Arc<dyn MutableHashStore> {
+        Arc::new(TenantHashStore {
+            inner: mhs.clone(),
+            tenant: self.clone(),
+        })
+    }
+}
+
+/// wraps a mutable hash store, prefixing all keys with the tenant's keyspace
+pub struct TenantHashStore {
+    inner: Arc<dyn MutableHashStore>,
+    tenant: Tenant,
+}
+
+#[tonic::async_trait]
+impl MutableHashStore for TenantHashStore {
+    async fn get(&self, k: &str) -> Result<Option<Hash>, DagCacheError> {
+        self.inner.get(&self.tenant.scope_key(k)?).await
+    }
+
+    async fn list(&self) -> Result<Vec<(String, Hash)>, DagCacheError> {
+        let keys = self.inner.list().await?;
+        Ok(keys
+            .into_iter()
+            .filter_map(|(k, h)| self.tenant.unscope_key(&k).map(|k| (k.to_string(), h)))
+            .collect())
+    }
+
+    async fn cas(
+        &self,
+        k: &str,
+        previous_hash: Option<Hash>,
+        proposed_hash: Hash,
+    ) -> Result<(), DagCacheError> {
+        let k = self.tenant.scope_key(k)?;
+        self.inner.cas(&k, previous_hash, proposed_hash).await
+    }
+
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        self.inner.flush().await
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub nodes: u64,
+    pub bytes: u64,
+}
+
+/// per-tenant limits on nodes and bytes written. blobs are deduplicated across tenants, so
+/// usage counts everything a tenant uploads, including nodes already present in the store.
+/// usage is only tracked in memory and resets on restart, so these are soft limits
+pub struct Quotas {
+    max_nodes: Option<u64>,
+    max_bytes: Option<u64>,
+    usage: Mutex<HashMap<Tenant, Usage>>,
+}
+
+impl Quotas {
+    pub fn new(max_nodes: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Quotas {
+            max_nodes,
+            max_bytes,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Quotas::new(None, None)
+    }
+
+    /// reserve usage for a write of nodes totalling bytes, failing without recording anything
+    /// if it would take the tenant over either limit. the reservation is refunded unless
+    /// committed once the write succeeds
+    pub fn charge(
+        &self,
+        tenant: &Tenant,
+        nodes: u64,
+        bytes: u64,
+    ) -> Result<Charge<'_>, DagCacheError> {
+        let id = match tenant {
+            Tenant::Default => "default",
+            Tenant::Named(id) => id.as_str(),
+        };
+
+        let mut usage = self.usage.lock().unwrap();
+        let current = usage.entry(tenant.clone()).or_default();
+        let proposed = Usage {
+            nodes: current.nodes + nodes,
+            bytes: current.bytes + bytes,
+        };
+
+        match (self.max_nodes, self.max_bytes) {
+            (Some(max), _) if proposed.nodes > max => Err(DagCacheError::QuotaExceeded(format!(
+                "tenant {} node quota of {} exceeded",
+                id, max
+            ))),
+            (_, Some(max)) if proposed.bytes > max => Err(DagCacheError::QuotaExceeded(format!(
+                "tenant {} byte quota of {} exceeded",
+                id, max
+            ))),
+            _ => {
+                *current = proposed;
+                Ok(Charge {
+                    quotas: self,
+                    tenant: tenant.clone(),
+                    usage: Usage { nodes, bytes },
+                    committed: false,
+                })
+            }
+        }
+    }
+
+    pub fn usage(&self, tenant: &Tenant) -> Usage {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(tenant)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn refund(&self, tenant: &Tenant, refund: Usage) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(current) = usage.get_mut(tenant) {
+            current.nodes = current.nodes.saturating_sub(refund.nodes);
+            current.bytes = current.bytes.saturating_sub(refund.bytes);
+        }
+    }
+}
+
+/// usage reserved by `Quotas::charge`, refunded on drop unless committed
+#[must_use]
+pub struct Charge<'a> {
+    quotas: &'a Quotas,
+    tenant: Tenant,
+    usage: Usage,
+    committed: bool,
+}
+
+impl<'a> Charge<'a> {
+    /// keep the reserved usage, call once the write it was charged for has succeeded
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl<'a> Drop for Charge<'a> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.quotas.refund(&self.tenant, self.usage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, test_runtime};
+    use dag_store_types::types::domain::Node;
+    use dag_store_types::types::encodings::Base64;
+
+    #[tokio::test]
+    async fn test_tenant_isolation() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let node = Node {
+            links: vec![],
+            data: Base64(vec![1]),
+        };
+        let hash = runtime.hashed_blob_store.put(node).await.unwrap();
+
+        let alice = Tenant::parse("alice")
+            .unwrap()
+            .scope(&runtime.mutable_hash_store);
+        let bob = Tenant::parse("bob")
+            .unwrap()
+            .scope(&runtime.mutable_hash_store);
+        let default = Tenant::Default.scope(&runtime.mutable_hash_store);
+
+        alice.cas("notes", None, hash).await.unwrap();
+        assert_eq!(alice.get("notes").await.unwrap(), Some(hash));
+        assert_eq!(bob.get("notes").await.unwrap(), None);
+        assert_eq!(default.get("notes").await.unwrap(), None);
+
+        // same key name, separate keyspaces
+        bob.cas("notes", None, hash).await.unwrap();
+        assert_eq!(bob.list().await.unwrap(), vec![("notes".to_string(), hash)]);
+        assert_eq!(default.list().await.unwrap(), vec![]);
+
+        // the default tenant can't reach into tenant keyspaces
+        assert!(default.get("tenants/alice/notes").await.is_err());
+        assert!(default.cas("tenants/bob/notes", None, hash).await.is_err());
+    }
+
+    #[test]
+    fn test_quotas() {
+        let quotas = Quotas::new(Some(2), Some(100));
+        let alice = Tenant::parse("alice").unwrap();
+
+        quotas.charge(&alice, 1, 60).unwrap().commit();
+        assert!(quotas.charge(&alice, 1, 60).is_err());
+        assert!(quotas.charge(&alice, 2, 10).is_err());
+
+        // refunded if the write fails
+        drop(quotas.charge(&alice, 1, 40).unwrap());
+        assert_eq!(
+            quotas.usage(&alice),
+            Usage {
+                nodes: 1,
+                bytes: 60
+            }
+        );
+
+        quotas.charge(&alice, 1, 40).unwrap().commit();
+        assert_eq!(
+            quotas.usage(&alice),
+            Usage {
+                nodes: 2,
+                bytes: 100
+            }
+        );
+
+        // other tenants are unaffected, clients without a tenant share the default's quota
+        quotas
+            .charge(&Tenant::parse("bob").unwrap(), 2, 100)
+            .unwrap()
+            .commit();
+        quotas.charge(&Tenant::Default, 2, 100).unwrap().commit();
+        assert!(quotas.charge(&Tenant::Default, 1, 0).is_err());
+
+        match Tenant::parse("not/valid") {
+            Err(DagCacheError::InvalidArgument(_)) => {}
+            other => panic!("expected InvalidArgument, got {:?}", other),
+        }
+    }
+}
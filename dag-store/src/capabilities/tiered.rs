@@ -0,0 +1,125 @@
+use crate::capabilities::HashedBlobStore;
+use dag_store_types::types::domain::{Hash, Node};
+use dag_store_types::types::errors::DagCacheError;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+/// read-through store: reads are served from local if possible, otherwise from remote
+/// (with the result written to local). writes go to both, remote first.
+/// safe because nodes are immutable and content-addressed.
+pub struct TieredStore {
+    pub local: Arc<dyn HashedBlobStore>,
+    pub remote: Arc<dyn HashedBlobStore>,
+}
+
+impl TieredStore {
+    #[instrument(skip(self))]
+    async fn get_tiered(&self, hash: Hash) -> Result<Node, DagCacheError> {
+        if self.local.has(hash).await? {
+            return self.local.get(hash).await;
+        }
+
+        info!("local store miss, reading from remote");
+        let node = self.remote.get(hash).await?;
+        self.local.put(node.clone()).await?;
+        Ok(node)
+    }
+
+    #[instrument(skip(self, v))]
+    async fn put_tiered(&self, v: Node) -> Result<Hash, DagCacheError> {
+        let hash = self.remote.put(v.clone()).await?;
+        self.local.put(v).await?;
+        Ok(hash)
+    }
+}
+
+#[tonic::async_trait]
+impl HashedBlobStore for TieredStore {
+    async fn get(&self, hash: Hash) -> Result<Node, DagCacheError> {
+        self.get_tiered(hash).await
+    }
+
+    async fn has(&self, hash: Hash) -> Result<bool, DagCacheError> {
+        if self.local.has(hash).await? {
+            Ok(true)
+        } else {
+            self.remote.has(hash).await
+        }
+    }
+
+    async fn put(&self, v: Node) -> Result<Hash, DagCacheError> {
+        self.put_tiered(v).await
+    }
+
+    // only the hashes missing locally are checked remotely
+    async fn has_many(&self, hashes: &[Hash]) -> Result<Vec<bool>, DagCacheError> {
+        let mut present = self.local.has_many(hashes).await?;
+        let (missing_idx, missing): (Vec<usize>, Vec<Hash>) = present
+            .iter()
+            .zip(hashes.iter())
+            .enumerate()
+            .filter(|(_, (p, _))| !**p)
+            .map(|(i, (_, h))| (i, *h))
+            .unzip();
+        if !missing.is_empty() {
+            let remote = self.remote.has_many(&missing).await?;
+            for (i, p) in missing_idx.into_iter().zip(remote) {
+                present[i] = p;
+            }
+        }
+        Ok(present)
+    }
+
+    // the remote store persists its own writes
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        self.local.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::remote::RemoteStore;
+    use crate::test_utils::{init_test_env, spawn_dag_store, test_runtime};
+    use dag_store_types::types::domain::{Header, Id};
+    use dag_store_types::types::encodings::Base64;
+
+    #[tokio::test]
+    async fn test_read_through() {
+        init_test_env();
+
+        let (origin, _origin_dir) = test_runtime();
+        let (origin, origin_url) = spawn_dag_store(origin).await;
+        let (edge, _edge_dir) = test_runtime();
+
+        let tiered = TieredStore {
+            local: edge.hashed_blob_store.clone(),
+            remote: Arc::new(RemoteStore::new(origin_url, None, None).unwrap()),
+        };
+
+        let leaf = Node {
+            links: vec![],
+            data: Base64(vec![1, 3, 3, 7]),
+        };
+        let leaf_hash = origin.hashed_blob_store.put(leaf.clone()).await.unwrap();
+
+        // only present in origin, read through and persisted locally
+        assert!(!edge.hashed_blob_store.has(leaf_hash).await.unwrap());
+        assert!(tiered.has(leaf_hash).await.unwrap());
+        assert_eq!(tiered.get(leaf_hash).await.unwrap(), leaf);
+        assert!(edge.hashed_blob_store.has(leaf_hash).await.unwrap());
+
+        // writes land in both tiers
+        let root = Node {
+            links: vec![Header {
+                id: Id(1),
+                hash: leaf_hash,
+                size: 4,
+            }],
+            data: Base64(vec![3, 1, 4]),
+        };
+        let root_hash = tiered.put(root).await.unwrap();
+        assert!(origin.hashed_blob_store.has(root_hash).await.unwrap());
+        assert!(edge.hashed_blob_store.has(root_hash).await.unwrap());
+    }
+}
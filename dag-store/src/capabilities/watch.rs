@@ -0,0 +1,55 @@
+use crate::capabilities::MutableHashStore;
+use dag_store_types::types::domain::Hash;
+use dag_store_types::types::errors::DagCacheError;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// number of key updates buffered per subscriber before it starts lagging
+pub const KEY_UPDATE_BUFFER: usize = 64;
+
+pub fn key_updates_channel() -> broadcast::Sender<(String, Hash)> {
+    let (send, _receive) = broadcast::channel(KEY_UPDATE_BUFFER);
+    send
+}
+
+/// wraps a mutable hash store, publishing every successful CAS to subscribers
+pub struct WatchedHashStore {
+    inner: Arc<dyn MutableHashStore>,
+    updates: broadcast::Sender<(String, Hash)>,
+}
+
+impl WatchedHashStore {
+    pub fn new(
+        inner: Arc<dyn MutableHashStore>,
+        updates: broadcast::Sender<(String, Hash)>,
+    ) -> Self {
+        WatchedHashStore { inner, updates }
+    }
+}
+
+#[tonic::async_trait]
+impl MutableHashStore for WatchedHashStore {
+    async fn get(&self, k: &str) -> Result<Option<Hash>, DagCacheError> {
+        self.inner.get(k).await
+    }
+
+    async fn list(&self) -> Result<Vec<(String, Hash)>, DagCacheError> {
+        self.inner.list().await
+    }
+
+    async fn cas(
+        &self,
+        k: &str,
+        previous_hash: Option<Hash>,
+        proposed_hash: Hash,
+    ) -> Result<(), DagCacheError> {
+        self.inner.cas(k, previous_hash, proposed_hash).await?;
+        // send only fails if there are no subscribers, which is fine
+        let _ = self.updates.send((k.to_string(), proposed_hash));
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), DagCacheError> {
+        self.inner.flush().await
+    }
+}
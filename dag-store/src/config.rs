@@ -0,0 +1,91 @@
+// shared by the dag-store and notes-server configs: each server reads a toml file into its
+// Config, overrides it with any command line flags (or env vars) that were set and validates the
+// result before building its runtime
+use crate::server::auth::bearer_header;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// why a server's config couldn't be loaded or is invalid
+#[derive(Debug)]
+pub enum ConfigError {
+    /// the config file, or a file it names (eg a key file), couldn't be read
+    Read { path: String, error: std::io::Error },
+    /// the config file isn't valid toml or has unknown or mistyped settings
+    Parse {
+        path: String,
+        error: toml::de::Error,
+    },
+    /// a setting is missing, has an invalid value or conflicts with another setting
+    Invalid { field: &'static str, reason: String },
+}
+
+impl ConfigError {
+    pub fn invalid<S: Into<String>>(field: &'static str, reason: S) -> Self {
+        ConfigError::Invalid {
+            field,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn required(field: &'static str) -> Self {
+        ConfigError::invalid(field, "is required")
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "failed reading {}: {}", path, error),
+            ConfigError::Parse { path, error } => write!(f, "failed parsing {}: {}", path, error),
+            ConfigError::Invalid { field, reason } => write!(f, "{} {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// read a file named by the config
+pub fn read_file(path: &str) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path).map_err(|error| ConfigError::Read {
+        path: path.to_string(),
+        error,
+    })
+}
+
+/// read a bearer token file named by the config setting field, ignoring surrounding whitespace
+pub fn read_token_file(field: &'static str, path: &str) -> Result<String, ConfigError> {
+    let token = read_file(path)?.trim().to_string();
+    if let Err(e) = bearer_header(&token) {
+        return Err(ConfigError::invalid(field, format!("{:?}", e)));
+    }
+    Ok(token)
+}
+
+/// parse a toml config file, settings missing from it take their defaults
+pub fn load_file<T: DeserializeOwned>(path: &str) -> Result<T, ConfigError> {
+    toml::from_str(&read_file(path)?).map_err(|error| ConfigError::Parse {
+        path: path.to_string(),
+        error,
+    })
+}
+
+/// render a config as toml, in the format load_file reads
+pub fn to_toml<T: Serialize>(config: &T) -> String {
+    // ASSERTION: configs are flat structs of plain values, which always serialize
+    toml::to_string(config).expect("failed serializing config")
+}
+
+/// both or neither of a pair of settings (eg a certificate and its key) must be set
+pub fn check_pair<A, B>(
+    a_name: &'static str,
+    a: &Option<A>,
+    b_name: &'static str,
+    b: &Option<B>,
+) -> Result<(), ConfigError> {
+    match (a, b) {
+        (Some(_), None) => Err(ConfigError::invalid(a_name, format!("requires {}", b_name))),
+        (None, Some(_)) => Err(ConfigError::invalid(b_name, format!("requires {}", a_name))),
+        _ => Ok(()),
+    }
+}
@@ -1,22 +1,116 @@
 #![deny(warnings)]
+pub mod cache_snapshot;
 pub mod capabilities;
+pub mod config;
+pub mod metrics;
 pub mod opts;
+pub mod replication;
 pub mod server;
+pub mod telemetry;
+// also used by the cli and notes server to serve a dag store in their tests
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod tls;
 
 use crate::server::app::Runtime;
+use crate::server::auth;
+use crate::server::health::proto::health_server::HealthServer;
+use crate::server::health::HealthService;
 use dag_store_types::types::grpc::dag_store_server::DagStoreServer;
 pub use opts::Opt;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::time::Duration;
+use tls::ServerTls;
+use tokio::sync::oneshot;
 use tonic::transport::Server;
+use tracing::{info, warn};
 
+/// how long in-flight requests are given to complete once shutdown begins
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn service(runtime: Runtime) -> DagStoreServer<Runtime> {
+    match runtime.auth.clone() {
+        Some(authenticator) => {
+            DagStoreServer::with_interceptor(runtime, auth::interceptor(&authenticator))
+        }
+        None => DagStoreServer::new(runtime),
+    }
+}
+
+fn server(tls: Option<ServerTls>) -> Result<Server, Box<dyn std::error::Error + 'static>> {
+    match tls {
+        Some(tls) => Ok(tls
+            .configure(Server::builder())
+            .map_err(|e| format!("failed configuring tls: {:?}", e))?),
+        None => Ok(Server::builder()),
+    }
+}
+
+/// serve until the process exits
 pub async fn run(
     runtime: Runtime,
     addr: SocketAddr,
+    tls: Option<ServerTls>,
 ) -> Result<(), Box<dyn std::error::Error + 'static>> {
-    Server::builder()
-        .add_service(DagStoreServer::new(runtime))
-        .serve(addr)
-        .await?;
+    run_with_shutdown(runtime, addr, tls, futures::future::pending()).await
+}
+
+/// serve until shutdown resolves, then stop accepting new requests, give in-flight requests
+/// (eg bulk puts) up to DRAIN_TIMEOUT to complete, flush the store and return
+pub async fn run_with_shutdown<F: Future<Output = ()>>(
+    runtime: Runtime,
+    addr: SocketAddr,
+    tls: Option<ServerTls>,
+    shutdown: F,
+) -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let mutable_hash_store = runtime.mutable_hash_store.clone();
+    let hashed_blob_store = runtime.hashed_blob_store.clone();
+    let (serving, health) = HealthService::new(mutable_hash_store.clone());
+
+    let (draining, drain_started) = oneshot::channel();
+    let shutdown = async move {
+        shutdown.await;
+        info!("shutting down, draining in-flight requests");
+        // health checks report NOT_SERVING from here on so load balancers stop routing to us
+        let _ = serving.broadcast(false);
+        let _ = draining.send(());
+    };
+
+    // built outside of the serve expression so the error isn't held across the await
+    let mut server = server(tls)?;
+    let serve = server
+        .add_service(service(runtime))
+        .add_service(HealthServer::new(health))
+        .serve_with_shutdown(addr, shutdown);
+
+    // streams (eg key watches) only end when their client goes away, so stop waiting eventually
+    let drain_timeout = async {
+        match drain_started.await {
+            Ok(()) => tokio::time::delay_for(DRAIN_TIMEOUT).await,
+            // the server failed before shutdown was requested
+            Err(_) => futures::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        res = serve => res?,
+        _ = drain_timeout => warn!(
+            "requests still in flight after {:?}, shutting down anyway",
+            DRAIN_TIMEOUT
+        ),
+    }
+
+    // sled only flushes periodically, flush now so writes acknowledged before shutdown persist
+    mutable_hash_store
+        .flush()
+        .await
+        .map_err(|e| format!("failed flushing store: {:?}", e))?;
+    hashed_blob_store
+        .flush()
+        .await
+        .map_err(|e| format!("failed flushing store: {:?}", e))?;
+    info!("store flushed, shutdown complete");
 
     Ok(())
 }
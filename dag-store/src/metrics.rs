@@ -0,0 +1,186 @@
+use crate::capabilities::cache::Cache;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, TextEncoder,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::info;
+
+lazy_static! {
+    pub static ref RPC_DURATION: HistogramVec = register_histogram_vec!(
+        "dag_store_rpc_duration_seconds",
+        "time taken to handle each rpc, by method",
+        &["method"]
+    )
+    .unwrap();
+    pub static ref RPC_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "dag_store_rpc_errors_total",
+        "failed rpcs, by method and error (DagCacheError variant or grpc status code)",
+        &["method", "error"]
+    )
+    .unwrap();
+    pub static ref STORE_DURATION: HistogramVec = register_histogram_vec!(
+        "dag_store_store_duration_seconds",
+        "time taken by local store reads and writes",
+        &["op"],
+        exponential_buckets(0.0001, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref BULK_PUT_NODES: Histogram = register_histogram!(
+        "dag_store_bulk_put_nodes",
+        "nodes uploaded per bulk put",
+        exponential_buckets(1.0, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref CAS_CONFLICTS: IntCounter = register_int_counter!(
+        "dag_store_cas_conflicts_total",
+        "key updates rejected because the key's current hash didn't match"
+    )
+    .unwrap();
+    pub static ref OTLP_DROPPED_SPANS: IntCounter = register_int_counter!(
+        "dag_store_otlp_dropped_spans_total",
+        "spans dropped because the otlp export queue was full"
+    )
+    .unwrap();
+}
+
+/// reports cache stats as of each scrape, the cache tracks them itself
+struct CacheCollector {
+    cache: Arc<Cache>,
+    descs: Vec<Desc>,
+}
+
+// (name, help) for each cache stat, in the order they're collected
+const CACHE_METRICS: [(&str, &str); 6] = [
+    (
+        "dag_store_cache_hits_total",
+        "cache lookups that found the node",
+    ),
+    (
+        "dag_store_cache_misses_total",
+        "cache lookups that didn't find the node",
+    ),
+    (
+        "dag_store_cache_evictions_total",
+        "nodes evicted to stay under max bytes",
+    ),
+    ("dag_store_cache_rejected_total", "nodes too large to cache"),
+    ("dag_store_cache_entries", "nodes currently cached"),
+    ("dag_store_cache_bytes", "approximate size of cached nodes"),
+];
+
+impl CacheCollector {
+    fn new(cache: Arc<Cache>) -> Self {
+        let descs = CACHE_METRICS
+            .iter()
+            .map(|(name, help)| IntGauge::new(*name, *help).unwrap().desc()[0].clone())
+            .collect();
+        CacheCollector { cache, descs }
+    }
+}
+
+impl Collector for CacheCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let stats = self.cache.stats();
+        let values = [
+            stats.hits,
+            stats.misses,
+            stats.evictions,
+            stats.rejected,
+            stats.entries,
+            stats.bytes,
+        ];
+
+        let mut families = Vec::new();
+        for ((name, help), value) in CACHE_METRICS.iter().zip(values.iter()) {
+            // counters can't be set directly, so build a fresh one holding the current value
+            if name.ends_with("_total") {
+                let counter = IntCounter::new(*name, *help).unwrap();
+                counter.inc_by(*value as i64);
+                families.extend(counter.collect());
+            } else {
+                let gauge = IntGauge::new(*name, *help).unwrap();
+                gauge.set(*value as i64);
+                families.extend(gauge.collect());
+            }
+        }
+        families
+    }
+}
+
+/// all registered metrics in the prometheus text format
+pub fn render() -> Vec<u8> {
+    let mut buf = Vec::new();
+    // encoding to a vec can't fail
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buf)
+        .expect("metrics encoding failed");
+    buf
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    let response = if request.uri().path() == "/metrics" {
+        Response::builder()
+            .header(CONTENT_TYPE, TextEncoder::new().format_type())
+            .body(Body::from(render()))
+    } else {
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+    };
+    // ASSERTION: builder will never fail, all headers are static
+    Ok(response.unwrap())
+}
+
+/// serve metrics at http://addr/metrics until the process exits
+pub async fn serve(addr: SocketAddr, cache: Arc<Cache>) -> Result<(), Box<dyn std::error::Error>> {
+    prometheus::register(Box::new(CacheCollector::new(cache)))?;
+    // metrics are registered on first use, register them now so they're reported from startup
+    lazy_static::initialize(&RPC_DURATION);
+    lazy_static::initialize(&RPC_ERRORS);
+    lazy_static::initialize(&STORE_DURATION);
+    lazy_static::initialize(&BULK_PUT_NODES);
+    lazy_static::initialize(&CAS_CONFLICTS);
+    lazy_static::initialize(&OTLP_DROPPED_SPANS);
+
+    let make_service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(handle)) });
+    info!("serving metrics on {}", addr);
+    Server::bind(&addr).serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn test_cache_collector() {
+        let cache = Arc::new(Cache::new(1024));
+        let registry = Registry::new();
+        registry
+            .register(Box::new(CacheCollector::new(cache)))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buf)
+            .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("# TYPE dag_store_cache_hits_total counter"));
+        assert!(text.contains("dag_store_cache_misses_total 0"));
+        assert!(text.contains("# TYPE dag_store_cache_entries gauge"));
+    }
+}
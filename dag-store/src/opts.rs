@@ -1,90 +1,633 @@
 use crate::capabilities::cache::Cache;
+use crate::capabilities::remote::RemoteStore;
 use crate::capabilities::store::FileSystemStore;
+use crate::capabilities::tenant::Quotas;
+use crate::capabilities::tiered::TieredStore;
+use crate::capabilities::watch::{key_updates_channel, WatchedHashStore};
+use crate::capabilities::{HashedBlobStore, MutableHashStore};
+use crate::config::{self, check_pair, ConfigError};
+use crate::replication::Replicator;
 use crate::server::app::Runtime;
-use std::fs::File;
-use std::io::prelude::*;
+use crate::server::auth::Authenticator;
+use crate::server::limits::{Limits, LimitsConfig};
+use crate::server::prefetch::PrefetchConfig;
+use crate::telemetry::{self, TelemetryConfig};
+use crate::tls::{ClientTls, ServerTls};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use structopt::StructOpt;
-use tracing_honeycomb::{new_blackhole_telemetry_layer, new_honeycomb_telemetry_layer};
-use tracing_subscriber::filter::LevelFilter;
-use tracing_subscriber::layer::Layer;
-use tracing_subscriber::registry;
 
+/// command line flags, each of which can also be set via an env var or in the config file.
+/// flags take precedence over env vars, which take precedence over the config file
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "dag cache",
     about = "ipfs wrapper, provides bulk put and bulk get via LRU cache"
 )]
 pub struct Opt {
-    #[structopt(short = "p", long = "port", default_value = "8088")]
-    pub port: u64,
+    /// toml file to read settings from, named as the flags below are (eg `fs_path = "..."`)
+    #[structopt(short = "c", long = "config", env = "DAG_STORE_CONFIG")]
+    pub config: Option<String>,
 
-    #[structopt(short = "f", long = "fs_path")]
-    pub fs_path: String,
+    /// print the config resulting from the config file, env vars and flags, then exit
+    #[structopt(long = "print-config")]
+    pub print_config: bool,
 
-    #[structopt(short = "n", long = "max_cache_entries", default_value = "1024")]
-    pub max_cache_entries: usize,
+    /// address to serve grpc on, defaults to 0.0.0.0:8088
+    #[structopt(long = "bind_addr", env = "DAG_STORE_BIND_ADDR")]
+    pub bind_addr: Option<SocketAddr>,
 
-    #[structopt(short = "h", long = "honeycomb_key_file")]
+    /// port to serve grpc on, overrides the port in bind_addr
+    #[structopt(short = "p", long = "port", env = "DAG_STORE_PORT")]
+    pub port: Option<u16>,
+
+    #[structopt(short = "f", long = "fs_path", env = "DAG_STORE_FS_PATH")]
+    pub fs_path: Option<String>,
+
+    /// upper bound on the total size of cached nodes (data plus link headers), in bytes.
+    /// split across 16 shards, nodes larger than 1/16th of this are never cached. defaults to 64MiB
+    #[structopt(long = "max_cache_bytes", env = "DAG_STORE_MAX_CACHE_BYTES")]
+    pub max_cache_bytes: Option<usize>,
+
+    /// no longer supported, the cache is bounded by max_cache_bytes instead of by entry count.
+    /// rejected rather than ignored so existing deployments don't silently change cache size
+    #[structopt(short = "n", long = "max_cache_entries", raw(hidden = "true"))]
+    pub max_cache_entries: Option<usize>,
+
+    /// file to save cached hashes to on shutdown and rehydrate the cache from on startup
+    #[structopt(long = "cache_snapshot", env = "DAG_STORE_CACHE_SNAPSHOT")]
+    pub cache_snapshot: Option<String>,
+
+    /// after serving a node, load this many levels below it into the cache (0, the default,
+    /// disables)
+    #[structopt(long = "prefetch_depth", env = "DAG_STORE_PREFETCH_DEPTH")]
+    pub prefetch_depth: Option<u32>,
+
+    /// max links followed per node when prefetching, defaults to 16
+    #[structopt(long = "prefetch_fan_out", env = "DAG_STORE_PREFETCH_FAN_OUT")]
+    pub prefetch_fan_out: Option<usize>,
+
+    /// where to publish traces: none, stdout (as json), honeycomb or otlp. defaults to honeycomb
+    /// if honeycomb_key_file is set, otherwise none
+    #[structopt(long = "telemetry", env = "DAG_STORE_TELEMETRY")]
+    pub telemetry: Option<String>,
+
+    #[structopt(
+        short = "h",
+        long = "honeycomb_key_file",
+        env = "DAG_STORE_HONEYCOMB_KEY_FILE"
+    )]
     pub honeycomb_key_file: Option<String>,
+
+    /// otlp grpc endpoint of an opentelemetry collector, defaults to http://localhost:4317
+    #[structopt(long = "otlp_endpoint", env = "DAG_STORE_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// serve prometheus metrics at http://<bind_addr ip>:<metrics_port>/metrics
+    #[structopt(long = "metrics_port", env = "DAG_STORE_METRICS_PORT")]
+    pub metrics_port: Option<u16>,
+
+    /// url of an origin dag-store to read nodes missing locally from
+    #[structopt(long = "remote_url", env = "DAG_STORE_REMOTE_URL")]
+    pub remote_url: Option<String>,
+
+    /// url of an upstream dag-store to replicate keys from
+    #[structopt(long = "replicate_from", env = "DAG_STORE_REPLICATE_FROM")]
+    pub replicate_from: Option<String>,
+
+    /// key to follow on the upstream dag-store (may be repeated, or comma separated in the env
+    /// var). replaces any keys listed in the config file
+    #[structopt(
+        long = "replicate_key",
+        env = "DAG_STORE_REPLICATE_KEYS",
+        raw(use_delimiter = "true")
+    )]
+    pub replicate_keys: Vec<String>,
+
+    /// max nodes each tenant may write (requests without a tenant id share one limit). usage is
+    /// only tracked in memory and resets on restart, so this is a soft limit
+    #[structopt(long = "tenant_max_nodes", env = "DAG_STORE_TENANT_MAX_NODES")]
+    pub tenant_max_nodes: Option<u64>,
+
+    /// max bytes each tenant may write, counted as in max_cache_bytes. a soft limit, as above
+    #[structopt(long = "tenant_max_bytes", env = "DAG_STORE_TENANT_MAX_BYTES")]
+    pub tenant_max_bytes: Option<u64>,
+
+    /// file of accepted bearer tokens, one `<token> <capabilities>` per line, eg
+    /// `s3cret read,write,cas:notes-app/,tenant:alice`. tokens without a tenant use the default
+    /// tenant and `admin` allows cache stats and warming. setting this or auth_signing_key_file
+    /// requires all requests to be authenticated
+    #[structopt(long = "auth_token_file", env = "DAG_STORE_AUTH_TOKEN_FILE")]
+    pub auth_token_file: Option<String>,
+
+    /// file containing the HMAC-SHA256 key used to verify signed (expiring) bearer tokens
+    #[structopt(
+        long = "auth_signing_key_file",
+        env = "DAG_STORE_AUTH_SIGNING_KEY_FILE"
+    )]
+    pub auth_signing_key_file: Option<String>,
+
+    /// max nodes accepted in a single bulk put
+    #[structopt(long = "max_bulk_put_nodes", env = "DAG_STORE_MAX_BULK_PUT_NODES")]
+    pub max_bulk_put_nodes: Option<u64>,
+
+    /// max bytes accepted in a single bulk put, counted as in max_cache_bytes
+    #[structopt(long = "max_bulk_put_bytes", env = "DAG_STORE_MAX_BULK_PUT_BYTES")]
+    pub max_bulk_put_bytes: Option<u64>,
+
+    /// max encoded size of a single request message, in bytes
+    #[structopt(long = "max_message_bytes", env = "DAG_STORE_MAX_MESSAGE_BYTES")]
+    pub max_message_bytes: Option<usize>,
+
+    /// max requests each client (token, or address if unauthenticated) may have in flight
+    #[structopt(
+        long = "max_concurrent_requests",
+        env = "DAG_STORE_MAX_CONCURRENT_REQUESTS"
+    )]
+    pub max_concurrent_requests: Option<usize>,
+
+    /// requests per second allowed per client
+    #[structopt(long = "rate_limit", env = "DAG_STORE_RATE_LIMIT")]
+    pub rate_limit: Option<f64>,
+
+    /// requests a client may make in a burst before rate_limit applies, defaults to 10
+    #[structopt(long = "rate_limit_burst", env = "DAG_STORE_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+
+    /// pem encoded certificate to serve grpc over tls with (requires the tls feature)
+    #[structopt(long = "tls_cert_file", env = "DAG_STORE_TLS_CERT_FILE")]
+    pub tls_cert_file: Option<String>,
+
+    /// pem encoded private key for tls_cert_file
+    #[structopt(long = "tls_key_file", env = "DAG_STORE_TLS_KEY_FILE")]
+    pub tls_key_file: Option<String>,
+
+    /// require clients to present a certificate signed by this pem encoded ca (mutual tls)
+    #[structopt(long = "tls_client_ca_file", env = "DAG_STORE_TLS_CLIENT_CA_FILE")]
+    pub tls_client_ca_file: Option<String>,
+
+    /// pem encoded ca used to verify remote_url and replicate_from, enables tls for both
+    #[structopt(long = "upstream_tls_ca_file", env = "DAG_STORE_UPSTREAM_TLS_CA_FILE")]
+    pub upstream_tls_ca_file: Option<String>,
+
+    /// client certificate presented to remote_url and replicate_from (mutual tls)
+    #[structopt(
+        long = "upstream_tls_cert_file",
+        env = "DAG_STORE_UPSTREAM_TLS_CERT_FILE"
+    )]
+    pub upstream_tls_cert_file: Option<String>,
+
+    /// private key for upstream_tls_cert_file
+    #[structopt(
+        long = "upstream_tls_key_file",
+        env = "DAG_STORE_UPSTREAM_TLS_KEY_FILE"
+    )]
+    pub upstream_tls_key_file: Option<String>,
+
+    /// file containing a bearer token presented to remote_url and replicate_from
+    #[structopt(long = "upstream_token_file", env = "DAG_STORE_UPSTREAM_TOKEN_FILE")]
+    pub upstream_token_file: Option<String>,
+}
+
+/// settings as read from the config file, see Opt for what each does
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub fs_path: Option<String>,
+    pub max_cache_bytes: usize,
+    pub cache_snapshot: Option<String>,
+    pub prefetch_depth: u32,
+    pub prefetch_fan_out: usize,
+    pub telemetry: Option<String>,
+    pub honeycomb_key_file: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub metrics_port: Option<u16>,
+    pub remote_url: Option<String>,
+    pub replicate_from: Option<String>,
+    pub replicate_keys: Vec<String>,
+    pub tenant_max_nodes: Option<u64>,
+    pub tenant_max_bytes: Option<u64>,
+    pub auth_token_file: Option<String>,
+    pub auth_signing_key_file: Option<String>,
+    pub max_bulk_put_nodes: Option<u64>,
+    pub max_bulk_put_bytes: Option<u64>,
+    pub max_message_bytes: Option<usize>,
+    pub max_concurrent_requests: Option<usize>,
+    pub rate_limit: Option<f64>,
+    pub rate_limit_burst: u32,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub tls_client_ca_file: Option<String>,
+    pub upstream_tls_ca_file: Option<String>,
+    pub upstream_tls_cert_file: Option<String>,
+    pub upstream_tls_key_file: Option<String>,
+    pub upstream_token_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addr: ([0, 0, 0, 0], 8088).into(),
+            fs_path: None,
+            max_cache_bytes: 64 * 1024 * 1024,
+            cache_snapshot: None,
+            prefetch_depth: 0,
+            prefetch_fan_out: 16,
+            telemetry: None,
+            honeycomb_key_file: None,
+            otlp_endpoint: None,
+            metrics_port: None,
+            remote_url: None,
+            replicate_from: None,
+            replicate_keys: vec![],
+            tenant_max_nodes: None,
+            tenant_max_bytes: None,
+            auth_token_file: None,
+            auth_signing_key_file: None,
+            max_bulk_put_nodes: None,
+            max_bulk_put_bytes: None,
+            max_message_bytes: None,
+            max_concurrent_requests: None,
+            rate_limit: None,
+            rate_limit_burst: 10,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            upstream_tls_ca_file: None,
+            upstream_tls_cert_file: None,
+            upstream_tls_key_file: None,
+            upstream_token_file: None,
+        }
+    }
 }
 
 impl Opt {
-    /// parse opts into capabilities object, will panic if not configured correctly (TODO: FIXME)
-    pub fn into_runtime(self) -> Runtime {
-        let store = Arc::new(FileSystemStore::new(self.fs_path));
-
-        match self.honeycomb_key_file {
-            Some(honeycomb_key_file) => {
-                let mut file =
-                    File::open(honeycomb_key_file).expect("failed opening honeycomb key file");
-                let mut honeycomb_key = String::new();
-                file.read_to_string(&mut honeycomb_key)
-                    .expect("failed reading honeycomb key file");
-
-                let honeycomb_config = libhoney::Config {
-                    options: libhoney::client::Options {
-                        api_key: honeycomb_key,
-                        dataset: "dag-cache".to_string(), // todo rename
-                        ..libhoney::client::Options::default()
-                    },
-                    transmission_options: libhoney::transmission::Options {
-                        max_batch_size: 1,
-                        ..libhoney::transmission::Options::default()
-                    },
-                };
-
-                let telemetry_layer = new_honeycomb_telemetry_layer("dag-store", honeycomb_config);
-
-                let subscriber =
-                    telemetry_layer // publish to tracing
-                        .and_then(tracing_subscriber::fmt::Layer::builder().finish()) // log to stdout
-                        .and_then(LevelFilter::INFO) // omit low-level debug tracing (eg tokio executor)
-                        .with_subscriber(registry::Registry::default()); // provide underlying span data store
-
-                tracing::subscriber::set_global_default(subscriber)
-                    .expect("setting global default failed");
+    /// read the config file (if any), override it with the flags and env vars that were set and
+    /// validate the result
+    pub fn into_config(self) -> Result<Config, ConfigError> {
+        if self.max_cache_entries.is_some() {
+            return Err(ConfigError::invalid(
+                "max_cache_entries",
+                "is no longer supported, set max_cache_bytes instead",
+            ));
+        }
+
+        let mut config: Config = match &self.config {
+            Some(path) => config::load_file(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(bind_addr) = self.bind_addr {
+            config.bind_addr = bind_addr;
+        }
+        if let Some(port) = self.port {
+            config.bind_addr.set_port(port);
+        }
+        config.fs_path = self.fs_path.or(config.fs_path);
+        if let Some(max_cache_bytes) = self.max_cache_bytes {
+            config.max_cache_bytes = max_cache_bytes;
+        }
+        config.cache_snapshot = self.cache_snapshot.or(config.cache_snapshot);
+        if let Some(prefetch_depth) = self.prefetch_depth {
+            config.prefetch_depth = prefetch_depth;
+        }
+        if let Some(prefetch_fan_out) = self.prefetch_fan_out {
+            config.prefetch_fan_out = prefetch_fan_out;
+        }
+        config.telemetry = self.telemetry.or(config.telemetry);
+        config.honeycomb_key_file = self.honeycomb_key_file.or(config.honeycomb_key_file);
+        config.otlp_endpoint = self.otlp_endpoint.or(config.otlp_endpoint);
+        config.metrics_port = self.metrics_port.or(config.metrics_port);
+        config.remote_url = self.remote_url.or(config.remote_url);
+        config.replicate_from = self.replicate_from.or(config.replicate_from);
+        if !self.replicate_keys.is_empty() {
+            config.replicate_keys = self.replicate_keys;
+        }
+        config.tenant_max_nodes = self.tenant_max_nodes.or(config.tenant_max_nodes);
+        config.tenant_max_bytes = self.tenant_max_bytes.or(config.tenant_max_bytes);
+        config.auth_token_file = self.auth_token_file.or(config.auth_token_file);
+        config.auth_signing_key_file = self.auth_signing_key_file.or(config.auth_signing_key_file);
+        config.max_bulk_put_nodes = self.max_bulk_put_nodes.or(config.max_bulk_put_nodes);
+        config.max_bulk_put_bytes = self.max_bulk_put_bytes.or(config.max_bulk_put_bytes);
+        config.max_message_bytes = self.max_message_bytes.or(config.max_message_bytes);
+        config.max_concurrent_requests = self
+            .max_concurrent_requests
+            .or(config.max_concurrent_requests);
+        config.rate_limit = self.rate_limit.or(config.rate_limit);
+        if let Some(rate_limit_burst) = self.rate_limit_burst {
+            config.rate_limit_burst = rate_limit_burst;
+        }
+        config.tls_cert_file = self.tls_cert_file.or(config.tls_cert_file);
+        config.tls_key_file = self.tls_key_file.or(config.tls_key_file);
+        config.tls_client_ca_file = self.tls_client_ca_file.or(config.tls_client_ca_file);
+        config.upstream_tls_ca_file = self.upstream_tls_ca_file.or(config.upstream_tls_ca_file);
+        config.upstream_tls_cert_file = self
+            .upstream_tls_cert_file
+            .or(config.upstream_tls_cert_file);
+        config.upstream_tls_key_file = self.upstream_tls_key_file.or(config.upstream_tls_key_file);
+        config.upstream_token_file = self.upstream_token_file.or(config.upstream_token_file);
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// checks settings that don't require reading files, those are checked by into_runtime
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.fs_path.is_none() {
+            return Err(ConfigError::required("fs_path"));
+        }
+        if self.max_cache_bytes == 0 {
+            return Err(ConfigError::invalid("max_cache_bytes", "must be positive"));
+        }
+        if self.prefetch_depth > 0 && self.prefetch_fan_out == 0 {
+            return Err(ConfigError::invalid(
+                "prefetch_fan_out",
+                "must be positive when prefetching",
+            ));
+        }
+        if !self.replicate_keys.is_empty() && self.replicate_from.is_none() {
+            return Err(ConfigError::invalid(
+                "replicate_keys",
+                "requires replicate_from",
+            ));
+        }
+        match self.rate_limit {
+            Some(rate) if !(rate > 0.0 && rate.is_finite()) => {
+                return Err(ConfigError::invalid("rate_limit", "must be positive"))
             }
-            None => {
-                let telemetry_layer = new_blackhole_telemetry_layer();
+            _ => {}
+        }
+        if self.rate_limit_burst == 0 {
+            return Err(ConfigError::invalid("rate_limit_burst", "must be positive"));
+        }
+        check_pair(
+            "tls_cert_file",
+            &self.tls_cert_file,
+            "tls_key_file",
+            &self.tls_key_file,
+        )?;
+        if self.tls_client_ca_file.is_some() && self.tls_cert_file.is_none() {
+            return Err(ConfigError::invalid(
+                "tls_client_ca_file",
+                "requires tls_cert_file",
+            ));
+        }
+        check_pair(
+            "upstream_tls_cert_file",
+            &self.upstream_tls_cert_file,
+            "upstream_tls_key_file",
+            &self.upstream_tls_key_file,
+        )?;
+        Ok(())
+    }
+
+    pub fn server_tls(&self) -> Result<Option<ServerTls>, ConfigError> {
+        ServerTls::from_opts(
+            self.tls_cert_file.clone(),
+            self.tls_key_file.clone(),
+            self.tls_client_ca_file.clone(),
+        )
+    }
 
-                let subscriber =
-                    telemetry_layer // publish to tracing
-                        .and_then(tracing_subscriber::fmt::Layer::builder().finish()) // log to stdout
-                        .and_then(LevelFilter::INFO) // omit low-level debug tracing (eg tokio executor)
-                        .with_subscriber(registry::Registry::default()); // provide underlying span data store
+    pub fn upstream_tls(&self) -> Option<ClientTls> {
+        ClientTls::from_opts(
+            self.upstream_tls_ca_file.clone(),
+            self.upstream_tls_cert_file.clone(),
+            self.upstream_tls_key_file.clone(),
+        )
+    }
 
-                tracing::subscriber::set_global_default(subscriber)
-                    .expect("setting global default failed");
+    pub fn telemetry_config(&self) -> Result<TelemetryConfig, ConfigError> {
+        TelemetryConfig::from_opts(
+            self.telemetry.clone(),
+            self.honeycomb_key_file.clone(),
+            self.otlp_endpoint.clone(),
+        )
+    }
+
+    /// address to serve metrics on, if enabled
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_port
+            .map(|port| SocketAddr::new(self.bind_addr.ip(), port))
+    }
+
+    /// build the capabilities object, opening the store and reading key files. if
+    /// replicate_from is set, also returns a replicator to spawn once serving
+    pub fn into_runtime(self) -> Result<(Runtime, Option<Replicator>), ConfigError> {
+        self.validate()?;
+        telemetry::init("dag-store", self.telemetry_config()?);
+
+        let upstream_tls = self.upstream_tls();
+        let upstream_token = match &self.upstream_token_file {
+            Some(path) => Some(config::read_token_file("upstream_token_file", path)?),
+            None => None,
+        };
+        // ASSERTION: validate checks fs_path is set
+        let fs_path = self.fs_path.unwrap();
+        let store = FileSystemStore::open(&fs_path).map_err(|e| {
+            ConfigError::invalid("fs_path", format!("failed opening store: {:?}", e))
+        })?;
+        let store = Arc::new(store);
+
+        let hashed_blob_store: Arc<dyn HashedBlobStore> = match self.remote_url {
+            Some(remote_url) => {
+                let remote =
+                    RemoteStore::new(remote_url, upstream_tls.as_ref(), upstream_token.clone())
+                        .map_err(|e| ConfigError::invalid("remote_url", format!("{:?}", e)))?;
+                Arc::new(TieredStore {
+                    local: store.clone(),
+                    remote: Arc::new(remote),
+                })
             }
+            None => store.clone(),
+        };
+
+        let auth = if self.auth_token_file.is_some() || self.auth_signing_key_file.is_some() {
+            let static_tokens = match self.auth_token_file {
+                Some(path) => Authenticator::read_token_file(Path::new(&path))
+                    .map_err(|e| ConfigError::invalid("auth_token_file", format!("{:?}", e)))?,
+                None => HashMap::new(),
+            };
+            let signing_key = match self.auth_signing_key_file {
+                Some(path) => Some(
+                    Authenticator::read_signing_key_file(Path::new(&path)).map_err(|e| {
+                        ConfigError::invalid("auth_signing_key_file", format!("{:?}", e))
+                    })?,
+                ),
+                None => None,
+            };
+            Some(Arc::new(Authenticator::new(static_tokens, signing_key)))
+        } else {
+            None
+        };
+
+        let limits = LimitsConfig {
+            max_bulk_put_nodes: self.max_bulk_put_nodes,
+            max_bulk_put_bytes: self.max_bulk_put_bytes,
+            max_message_bytes: self.max_message_bytes,
+            max_concurrent_requests: self.max_concurrent_requests,
+            rate_limit: self.rate_limit,
+            rate_limit_burst: self.rate_limit_burst,
+        };
+
+        let cache = Arc::new(Cache::new(self.max_cache_bytes));
+        let key_updates = key_updates_channel();
+
+        let prefetch = if self.prefetch_depth > 0 {
+            Some(PrefetchConfig {
+                depth: self.prefetch_depth,
+                fan_out: self.prefetch_fan_out,
+            })
+        } else {
+            None
         };
 
-        let cache = Arc::new(Cache::new(self.max_cache_entries));
+        let mutable_hash_store: Arc<dyn MutableHashStore> =
+            Arc::new(WatchedHashStore::new(store.clone(), key_updates.clone()));
+
+        // replicated nodes are written to the local store directly: through a tiered store,
+        // nodes present only remotely would be taken as present and their subtrees skipped,
+        // leaving the local store with nodes whose children are missing
+        let replicator = self.replicate_from.map(|url| {
+            let local: Arc<dyn HashedBlobStore> = store.clone();
+            Replicator::new(
+                url,
+                upstream_tls,
+                upstream_token,
+                local,
+                mutable_hash_store.clone(),
+            )
+        });
 
-        Runtime {
+        let runtime = Runtime {
             cache: cache,
-            mutable_hash_store: store.clone(),
-            hashed_blob_store: store,
+            mutable_hash_store,
+            hashed_blob_store,
+            key_updates,
+            prefetch,
+            quotas: Arc::new(Quotas::new(self.tenant_max_nodes, self.tenant_max_bytes)),
+            auth,
+            limits: Arc::new(Limits::new(limits)),
+        };
+        Ok((runtime, replicator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str, flags: &[&str]) -> Result<Config, ConfigError> {
+        let dir = tempdir::TempDir::new("dag-store-config").unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, toml).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut args = vec!["dag-store", "--config", &path];
+        args.extend_from_slice(flags);
+        Opt::from_iter(args).into_config()
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let file = r#"
+            bind_addr = "127.0.0.1:9000"
+            fs_path = "/var/lib/dag-store"
+            max_cache_bytes = 1024
+            replicate_from = "http://upstream:8088"
+            replicate_keys = ["a", "b"]
+        "#;
+
+        let config = load(file, &[]).unwrap();
+        assert_eq!(config.bind_addr, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.max_cache_bytes, 1024);
+        assert_eq!(config.replicate_keys, vec!["a", "b"]);
+        // unset settings take their defaults
+        assert_eq!(config.prefetch_fan_out, 16);
+
+        let flags = [
+            "-p",
+            "9001",
+            "--max_cache_bytes",
+            "2048",
+            "--replicate_key",
+            "c",
+        ];
+        let config = load(file, &flags).unwrap();
+        assert_eq!(config.bind_addr, "127.0.0.1:9001".parse().unwrap());
+        assert_eq!(config.max_cache_bytes, 2048);
+        assert_eq!(config.replicate_keys, vec!["c"]);
+        assert_eq!(config.fs_path, Some("/var/lib/dag-store".to_string()));
+
+        // printed configs load back unchanged
+        let printed = config::to_toml(&config);
+        assert_eq!(load(&printed, &[]).unwrap(), config);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let invalid_field = |toml: &str| match load(toml, &[]) {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("expected invalid config, got {:?}", other),
+        };
+
+        assert_eq!(invalid_field(""), "fs_path");
+        assert_eq!(
+            invalid_field("fs_path = \"db\"\ntls_cert_file = \"cert.pem\""),
+            "tls_cert_file"
+        );
+        assert_eq!(
+            invalid_field("fs_path = \"db\"\nrate_limit = 0.0"),
+            "rate_limit"
+        );
+        assert_eq!(
+            invalid_field("fs_path = \"db\"\nreplicate_keys = [\"a\"]"),
+            "replicate_keys"
+        );
+
+        match load("fs_path = \"db\"\nmax_cache_byte = 1", &[]) {
+            Err(ConfigError::Parse { .. }) => {}
+            other => panic!("expected unknown setting to be rejected, got {:?}", other),
+        }
+        // flags can fix an invalid file
+        assert!(load("", &["-f", "db"]).is_ok());
+
+        // configs built without validating report a half-configured pair rather than panicking
+        let config = Config {
+            tls_key_file: Some("key.pem".to_string()),
+            ..Config::default()
+        };
+        match config.server_tls() {
+            Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, "tls_key_file"),
+            other => panic!("expected tls_key_file to be rejected, got {:?}", other),
+        }
+
+        // token files are checked when read, rather than when the first request is made
+        let dir = tempdir::TempDir::new("dag-store-token").unwrap();
+        let path = dir.path().join("token");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "token\n").unwrap();
+        assert_eq!(
+            config::read_token_file("upstream_token_file", path).unwrap(),
+            "token"
+        );
+        std::fs::write(path, "two\nlines").unwrap();
+        match config::read_token_file("upstream_token_file", path) {
+            Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, "upstream_token_file"),
+            other => panic!("expected the token to be rejected, got {:?}", other),
+        }
+
+        // the cache used to be bounded by entry count, that flag is rejected rather than being
+        // taken as a byte count
+        match load("fs_path = \"db\"", &["-n", "1024"]) {
+            Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, "max_cache_entries"),
+            other => panic!("expected max_cache_entries to be rejected, got {:?}", other),
         }
     }
 }
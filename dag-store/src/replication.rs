@@ -0,0 +1,307 @@
+use crate::capabilities::{HashedBlobStore, MutableHashStore};
+use crate::tls::{self, ClientTls};
+use dag_store_types::types::{
+    api,
+    domain::{Hash, Node},
+    errors::DagCacheError,
+    grpc::{dag_store_client::DagStoreClient, GetHashForKeyReq},
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tracing::{error, info, instrument};
+
+// delay before reconnecting to upstream after a failure
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// follows keys on an upstream dag-store, pulling any nodes missing locally
+/// and then fast-forwarding the local key to match upstream. store must be the local store
+/// (not eg a tiered store reading through to a remote), as nodes it reports present are
+/// assumed to have their entire subtree present
+#[derive(Clone)]
+pub struct Replicator {
+    upstream_url: String,
+    upstream_tls: Option<ClientTls>,
+    upstream_token: Option<String>,
+    store: Arc<dyn HashedBlobStore>,
+    mhs: Arc<dyn MutableHashStore>,
+}
+
+impl Replicator {
+    pub fn new(
+        upstream_url: String,
+        upstream_tls: Option<ClientTls>,
+        upstream_token: Option<String>,
+        store: Arc<dyn HashedBlobStore>,
+        mhs: Arc<dyn MutableHashStore>,
+    ) -> Self {
+        Replicator {
+            upstream_url,
+            upstream_tls,
+            upstream_token,
+            store,
+            mhs,
+        }
+    }
+
+    /// spawn one long-lived replication task per key
+    pub fn spawn(self, keys: Vec<String>) {
+        for key in keys.into_iter() {
+            let replicator = self.clone();
+            tokio::spawn(async move { replicator.follow(key).await });
+        }
+    }
+
+    async fn follow(self, key: String) {
+        loop {
+            if let Err(e) = self.follow_once(&key).await {
+                error!("replication of key {} failed: {:?}", &key, e);
+            }
+            tokio::time::delay_for(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn follow_once(&self, key: &str) -> Result<(), DagCacheError> {
+        let mut client = tls::connect_with_token(
+            self.upstream_url.clone(),
+            self.upstream_tls.as_ref(),
+            self.upstream_token.as_deref(),
+        )
+        .await?;
+
+        let request = tonic::Request::new(GetHashForKeyReq {
+            key: key.to_string(),
+        });
+        let mut updates = client
+            .watch_key(request)
+            .await
+            .map_err(DagCacheError::unexpected)?
+            .into_inner();
+
+        info!("following key {} on {}", key, &self.upstream_url);
+        while let Some(update) = updates.message().await.map_err(DagCacheError::unexpected)? {
+            if let Some(hash) = update.hash {
+                let hash = Hash::from_proto(hash)?;
+                self.replicate(&mut client, key, hash).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, client))]
+    pub async fn replicate(
+        &self,
+        client: &mut DagStoreClient<Channel>,
+        key: &str,
+        hash: Hash,
+    ) -> Result<(), DagCacheError> {
+        let written = self.pull(client, hash).await?;
+        info!("pulled {} nodes for {}", written, hash);
+        self.fast_forward(key, hash).await
+    }
+
+    /// fetch every node reachable from root that isn't already present locally. nodes are
+    /// immutable and only ever written after their children, so a node being present
+    /// locally implies its entire subtree is also present. the dag is walked depth first and
+    /// each node written as soon as its children are, so only the nodes on the path being
+    /// walked are held in memory rather than the whole missing subtree
+    async fn pull(
+        &self,
+        client: &mut DagStoreClient<Channel>,
+        root: Hash,
+    ) -> Result<u64, DagCacheError> {
+        if self.store.has(root).await? {
+            return Ok(0);
+        }
+
+        // nodes upstream returned alongside those requested, saving round trips
+        let mut prefetched: HashMap<Hash, Node> = HashMap::new();
+        // nodes reachable by more than one path are only written once
+        let mut written = HashSet::new();
+        // None: not yet fetched, Some: fetched, missing children already pushed above it
+        let mut stack: Vec<(Hash, Option<Node>)> = vec![(root, None)];
+
+        while let Some((hash, node)) = stack.pop() {
+            match node {
+                Some(node) => {
+                    self.store.put(node).await?;
+                    written.insert(hash);
+                }
+                None => {
+                    if written.contains(&hash) {
+                        continue;
+                    }
+                    let node = match prefetched.remove(&hash) {
+                        Some(node) => node,
+                        None => fetch(client, hash, &mut prefetched).await?,
+                    };
+                    let links: Vec<Hash> = node
+                        .links
+                        .iter()
+                        .map(|l| l.hash)
+                        .filter(|h| !written.contains(h))
+                        .collect();
+                    // one presence check per node, covering all of its children
+                    let present = self.store.has_many(&links).await?;
+
+                    stack.push((hash, Some(node)));
+                    for (link, present) in links.into_iter().zip(present) {
+                        if present {
+                            prefetched.remove(&link);
+                        } else {
+                            stack.push((link, None));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(written.len() as u64)
+    }
+
+    /// point local key at hash, overwriting whatever it pointed to before
+    async fn fast_forward(&self, key: &str, hash: Hash) -> Result<(), DagCacheError> {
+        let mut current = self.mhs.get(key).await?;
+        loop {
+            if current == Some(hash) {
+                return Ok(());
+            }
+            match self.mhs.cas(key, current, hash).await {
+                Ok(()) => {
+                    info!("fast-forwarded key {} to {}", key, hash);
+                    return Ok(());
+                }
+                // raced with a concurrent local write, retry against its result
+                Err(DagCacheError::CASViolationError { actual_hash }) => current = actual_hash,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// fetch hash from upstream, keeping any extra nodes returned with it
+async fn fetch(
+    client: &mut DagStoreClient<Channel>,
+    hash: Hash,
+    prefetched: &mut HashMap<Hash, Node>,
+) -> Result<Node, DagCacheError> {
+    let resp = client
+        .get_node(tonic::Request::new(hash.into_proto()))
+        .await
+        .map_err(DagCacheError::unexpected)?;
+    let resp = api::get::Resp::from_proto(resp.into_inner())?;
+
+    for extra in resp.extra_nodes.into_iter() {
+        verify(extra.header.hash, &extra.node)?;
+        prefetched.insert(extra.header.hash, extra.node);
+    }
+    verify(hash, &resp.requested_node)?;
+    Ok(resp.requested_node)
+}
+
+// nodes are content-addressed, so anything returned by upstream can be checked
+fn verify(hash: Hash, node: &Node) -> Result<(), DagCacheError> {
+    let actual = node.canonical_hash();
+    if actual != hash {
+        return Err(DagCacheError::UnexpectedError(format!(
+            "upstream returned node with hash {} for {}",
+            actual, hash
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, spawn_dag_store, test_runtime};
+    use dag_store_types::types::api::bulk_put;
+    use dag_store_types::types::domain::Id;
+    use dag_store_types::types::encodings::Base64;
+    use dag_store_types::types::validated_tree::ValidatedTree;
+
+    fn tree(data: Vec<u8>) -> ValidatedTree {
+        let leaf = bulk_put::Node {
+            links: vec![],
+            data: Base64(vec![1, 3, 3, 7]),
+        };
+        let root = bulk_put::Node {
+            links: vec![bulk_put::NodeLink::Local(Id(1))],
+            data: Base64(data),
+        };
+        let mut nodes = HashMap::new();
+        nodes.insert(Id(1), leaf);
+        ValidatedTree::validate(root, nodes).expect("static test invalid")
+    }
+
+    async fn wait_for_key(mhs: &Arc<dyn MutableHashStore>, key: &str, hash: Hash) {
+        for _ in 0..100 {
+            if mhs.get(key).await.unwrap() == Some(hash) {
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+        }
+        panic!("key {} never replicated", key);
+    }
+
+    #[tokio::test]
+    async fn test_follow_upstream() {
+        init_test_env();
+
+        let (upstream, _upstream_dir) = test_runtime();
+        let (upstream, upstream_url) = spawn_dag_store(upstream).await;
+        let (downstream, _downstream_dir) = test_runtime();
+        let (downstream, _) = spawn_dag_store(downstream).await;
+
+        Replicator::new(
+            upstream_url.clone(),
+            None,
+            None,
+            downstream.hashed_blob_store.clone(),
+            downstream.mutable_hash_store.clone(),
+        )
+        .spawn(vec!["replicated".to_string()]);
+
+        let mut prev = None;
+        for i in 0..2 {
+            let req = bulk_put::Req {
+                validated_tree: tree(vec![i]),
+                cas: Some(bulk_put::CAS {
+                    required_previous_hash: prev,
+                    cas_key: "replicated".to_string(),
+                }),
+            };
+            let mut client = DagStoreClient::connect(upstream_url.clone()).await.unwrap();
+            let resp = client
+                .put_nodes(tonic::Request::new(req.into_proto()))
+                .await
+                .unwrap();
+            let resp = bulk_put::Resp::from_proto(resp.into_inner()).unwrap();
+
+            wait_for_key(&downstream.mutable_hash_store, "replicated", resp.root_hash).await;
+
+            let root = downstream
+                .hashed_blob_store
+                .get(resp.root_hash)
+                .await
+                .unwrap();
+            assert_eq!(
+                root,
+                upstream
+                    .hashed_blob_store
+                    .get(resp.root_hash)
+                    .await
+                    .unwrap()
+            );
+            assert!(downstream
+                .hashed_blob_store
+                .has(root.links[0].hash)
+                .await
+                .unwrap());
+
+            prev = Some(resp.root_hash);
+        }
+    }
+}
@@ -1,31 +1,123 @@
+use crate::capabilities::cache::node_size;
 use crate::capabilities::put_and_cache;
+use crate::capabilities::tenant::{Quotas, Tenant, TENANT_ID_META_FIELD};
 use crate::capabilities::{Cache, HashedBlobStore, MutableHashStore};
+use crate::metrics;
+use crate::server::archive;
+use crate::server::auth::{self, Access, Authenticator, Grant};
+use crate::server::batch_get;
 use crate::server::batch_put;
+use crate::server::diff;
+use crate::server::limits::{self, Limits, Permit, Permitted};
 use crate::server::opportunistic_get;
+use crate::server::prefetch::{self, PrefetchConfig};
+use crate::server::resolve;
+use crate::server::watch_key;
+use crate::telemetry::{register_dist_tracing_root, TraceId, TraceParent, TRACEPARENT_META_FIELD};
 use dag_store_types::types::{
     api, domain,
+    errors::{DagCacheError, ProtoDecodingError},
     grpc::{
-        dag_store_server::DagStore, BulkPutReq, BulkPutResp, GetHashForKeyReq, GetHashForKeyResp,
-        GetResp, Hash, Node,
+        dag_store_server::DagStore, ArchiveChunk, BulkPutReq, BulkPutResp, DiffEntry, DiffReq,
+        ExportRootReq, GetCacheStatsReq, GetCacheStatsResp, GetHashForKeyReq, GetHashForKeyResp,
+        GetResp, HasNodesReq, HasNodesResp, Hash, ImportArchiveResp, ListKeysReq, ListKeysResp,
+        Node, ResolveReq, ResolveResp, WarmCacheReq, WarmCacheResp,
     },
 };
+use futures::{Future, StreamExt};
 use std::{str::FromStr, sync::Arc};
+use tokio::sync::{broadcast, mpsc};
 use tonic::{Code, Request, Response, Status};
 use tracing::{event, info, instrument, Level};
-use tracing_honeycomb::{register_dist_tracing_root, SpanId, TraceId};
+
+pub type ExportRootStream = Permitted<
+    futures::stream::Map<
+        mpsc::Receiver<Result<Vec<u8>, DagCacheError>>,
+        fn(Result<Vec<u8>, DagCacheError>) -> Result<ArchiveChunk, Status>,
+    >,
+>;
+
+pub type DiffStream = Permitted<
+    futures::stream::Map<
+        mpsc::Receiver<Result<api::diff::Entry, DagCacheError>>,
+        fn(Result<api::diff::Entry, DagCacheError>) -> Result<DiffEntry, Status>,
+    >,
+>;
+
+pub type WatchKeyStream = Permitted<
+    futures::stream::Map<
+        mpsc::Receiver<Result<domain::Hash, DagCacheError>>,
+        fn(Result<domain::Hash, DagCacheError>) -> Result<GetHashForKeyResp, Status>,
+    >,
+>;
 
 // TODO (maybe): parameterize over E where E is the underlying error type (different for txn vs. main scope)
 pub struct Runtime {
     pub cache: Arc<Cache>,
     pub mutable_hash_store: Arc<dyn MutableHashStore>,
     pub hashed_blob_store: Arc<dyn HashedBlobStore>,
+    // published to by mutable_hash_store on every successful cas
+    pub key_updates: broadcast::Sender<(String, domain::Hash)>,
+    // if set, load the subtree below each requested node into the cache after responding
+    pub prefetch: Option<PrefetchConfig>,
+    // limits on nodes and bytes written per tenant
+    pub quotas: Arc<Quotas>,
+    // if set, requests must carry a bearer token granting the access they need
+    pub auth: Option<Arc<Authenticator>>,
+    // request size, rate and concurrency limits
+    pub limits: Arc<Limits>,
 }
 
 impl Runtime {
+    // NOTE: the interceptor has already verified the token and passed on its grant
+    fn authorize(
+        &self,
+        meta: &tonic::metadata::MetadataMap,
+        access: Access,
+    ) -> Result<Grant, Status> {
+        let grant = match &self.auth {
+            Some(_) => auth::verified_grant(meta)?,
+            None => Grant::all(),
+        };
+        grant.check(access)?;
+        Ok(grant)
+    }
+
+    // with auth enabled the tenant is bound to the token, a tenant id in metadata must match it
+    fn tenant(&self, meta: &tonic::metadata::MetadataMap, grant: &Grant) -> Result<Tenant, Status> {
+        let requested = extract_tenant(meta)?;
+        if self.auth.is_none() {
+            return Ok(requested);
+        }
+        let granted = grant.tenant();
+        if meta.get(TENANT_ID_META_FIELD).is_some() && requested != granted {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                format!("token is not valid for tenant {:?}", requested),
+            ));
+        }
+        Ok(granted)
+    }
+
+    // the returned permit counts against the client's concurrency limit until dropped
+    fn admit<T>(&self, request: &Request<T>) -> Result<Permit, Status> {
+        let authenticated = match &self.auth {
+            Some(_) => auth::verified_client(request.metadata()),
+            None => None,
+        };
+        let client = limits::client_id(authenticated, request.remote_addr());
+        Ok(self.limits.admit(client)?)
+    }
+
     #[instrument(skip(self))]
-    async fn get_node_handler(&self, request: Request<Hash>) -> Result<Response<GetResp>, Status> {
+    async fn get_node_handler(
+        &self,
+        request: Request<Hash>,
+    ) -> Result<Response<GetResp>, HandlerError> {
         // extract explicit tracing id (if any)
-        extract_tracing_id_and_record(request.metadata())?;
+        extract_tracing_id_and_record(request.metadata());
+        self.authorize(request.metadata(), Access::Read)?;
+        let _permit = self.admit(&request)?;
 
         let request = domain::Hash::from_proto(request.into_inner()).map_err( |e| {
             event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
@@ -36,21 +128,35 @@ impl Runtime {
             opportunistic_get::get(&self.hashed_blob_store, &self.cache, request)
                 .await?;
 
+        if let Some(config) = self.prefetch {
+            let links = resp.requested_node.links.clone();
+            prefetch::spawn(&self.hashed_blob_store, &self.cache, links, config);
+        }
+
         let resp = resp.into_proto();
         let resp = Response::new(resp);
         Ok(resp)
     }
 
     #[instrument(skip(self, request))] // skip potentially-large request (TODO record stats w/o full message body)
-    async fn put_node_handler(&self, request: Request<Node>) -> Result<Response<Hash>, Status> {
+    async fn put_node_handler(
+        &self,
+        request: Request<Node>,
+    ) -> Result<Response<Hash>, HandlerError> {
         // extract explicit tracing id (if any)
-        extract_tracing_id_and_record(request.metadata())?;
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Write)?;
+        let _permit = self.admit(&request)?;
+        self.limits.check_message_size(request.get_ref())?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
 
         let domain_node = domain::Node::from_proto(request.into_inner()).map_err( |e| {
             event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
             e
         })?;
 
+        let charge = self.quotas.charge(&tenant, 1, node_size(&domain_node) as u64)?;
+
         info!("dag cache put handler"); //TODO,, better log msgs
 
         let hash = put_and_cache(
@@ -59,33 +165,129 @@ impl Runtime {
             domain_node,
         )
         .await?;
+        charge.commit();
         let proto_hash = hash.into_proto();
         let resp = Response::new(proto_hash);
         Ok(resp)
     }
 
+    #[instrument(skip(self))]
+    async fn resolve_handler(
+        &self,
+        request: Request<ResolveReq>,
+    ) -> Result<Response<ResolveResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Read)?;
+        let _permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
+
+        let request = api::resolve::Req::from_proto(request.into_inner()).map_err( |e| {
+            event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
+            e
+        })?;
+
+        let resp = resolve::resolve(
+            &self.hashed_blob_store,
+            &self.cache,
+            &tenant.scope(&self.mutable_hash_store),
+            request,
+        )
+        .await?;
+
+        Ok(Response::new(resp.into_proto()))
+    }
+
+    #[instrument(skip(self))]
+    async fn diff_handler(
+        &self,
+        request: Request<DiffReq>,
+    ) -> Result<Response<DiffStream>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        self.authorize(request.metadata(), Access::Read)?;
+        let permit = self.admit(&request)?;
+
+        let request = api::diff::Req::from_proto(request.into_inner()).map_err( |e| {
+            event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
+            e
+        })?;
+
+        let entries = diff::diff(&self.hashed_blob_store, &self.cache, request);
+
+        fn to_proto(entry: Result<api::diff::Entry, DagCacheError>) -> Result<DiffEntry, Status> {
+            entry.map(|e| e.into_proto()).map_err(Status::from)
+        }
+        let to_proto: fn(_) -> _ = to_proto;
+
+        Ok(Response::new(Permitted::new(entries.map(to_proto), permit)))
+    }
+
+    #[instrument(skip(self, request))] // skip potentially-large request (TODO record stats w/o full message body)
+    async fn has_nodes_handler(
+        &self,
+        request: Request<HasNodesReq>,
+    ) -> Result<Response<HasNodesResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        self.authorize(request.metadata(), Access::Read)?;
+        let _permit = self.admit(&request)?;
+        self.limits.check_message_size(request.get_ref())?;
+
+        let hashes = request
+            .into_inner()
+            .hashes
+            .into_iter()
+            .map(domain::Hash::from_proto)
+            .collect::<Result<Vec<_>, _>>()?;
+        let present = self.hashed_blob_store.has_many(&hashes).await?;
+
+        let resp = HasNodesResp { present };
+        Ok(Response::new(resp))
+    }
+
     #[instrument(skip(self, request))] // skip potentially-large request (TODO record stats w/o full message body)
     async fn put_nodes_handler(
         &self,
         request: Request<BulkPutReq>,
-    ) -> Result<Response<BulkPutResp>, Status> {
+    ) -> Result<Response<BulkPutResp>, HandlerError> {
         // extract explicit tracing id (if any)
-        extract_tracing_id_and_record(request.metadata())?;
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Write)?;
+        let _permit = self.admit(&request)?;
+        self.limits.check_message_size(request.get_ref())?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
 
         let request = api::bulk_put::Req::from_proto(request.into_inner()).map_err( |e| {
             event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
             e
         })?;
 
+        let tree = &request.validated_tree;
+        let nodes = std::iter::once(&tree.root_node).chain(tree.nodes.values());
+        let (node_count, bytes) = nodes.fold((0, 0), |(count, bytes), node| {
+            (count + 1, bytes + bulk_put_node_size(node) as u64)
+        });
+        self.limits.check_bulk_put(node_count, bytes)?;
+        metrics::BULK_PUT_NODES.observe(node_count as f64);
+
+        if let Some(cas) = &request.cas {
+            grant.check_cas_key(&cas.cas_key)?;
+        }
+
+        // refunded if the write or cas fails
+        let charge = self.quotas.charge(&tenant, node_count, bytes)?;
+
         info!("dag cache put handler request, cas: {:?}", &request.cas);
         let resp = batch_put::batch_put_cata_with_cas(
-            &self.mutable_hash_store,
+            &tenant.scope(&self.mutable_hash_store),
             &self.hashed_blob_store,
             &self.cache,
             request.validated_tree,
             request.cas,
         )
         .await?;
+        charge.commit();
 
         let resp = Response::new(resp.into_proto());
         Ok(resp)
@@ -95,12 +297,15 @@ impl Runtime {
     async fn get_hash_for_key_handler(
         &self,
         request: Request<GetHashForKeyReq>,
-    ) -> Result<Response<GetHashForKeyResp>, Status> {
+    ) -> Result<Response<GetHashForKeyResp>, HandlerError> {
         // extract explicit tracing id (if any)
-        extract_tracing_id_and_record(request.metadata())?;
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Read)?;
+        let _permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
 
-        let hash = self
-            .mutable_hash_store
+        let hash = tenant
+            .scope(&self.mutable_hash_store)
             .get(&request.into_inner().key)
             .await?
             .map(|h| h.into_proto());
@@ -108,6 +313,187 @@ impl Runtime {
         let resp = GetHashForKeyResp { hash };
         Ok(Response::new(resp))
     }
+
+    #[instrument(skip(self))]
+    async fn list_keys_handler(
+        &self,
+        request: Request<ListKeysReq>,
+    ) -> Result<Response<ListKeysResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Read)?;
+        let _permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
+
+        let request = api::list_keys::Req::from_proto(request.into_inner());
+
+        let mut keys: Vec<(String, domain::Hash)> = tenant
+            .scope(&self.mutable_hash_store)
+            .list()
+            .await?
+            .into_iter()
+            .filter(|(k, _)| k.starts_with(&request.prefix))
+            .collect();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let resp = api::list_keys::Resp { keys };
+        Ok(Response::new(resp.into_proto()))
+    }
+
+    #[instrument(skip(self))]
+    async fn watch_key_handler(
+        &self,
+        request: Request<GetHashForKeyReq>,
+    ) -> Result<Response<WatchKeyStream>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Read)?;
+        let permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
+
+        // updates are published with unscoped keys, so watch the scoped key on the full store
+        let key = tenant.scope_key(&request.into_inner().key)?;
+        let updates =
+            watch_key::watch_key(&self.mutable_hash_store, &self.key_updates, key).await?;
+
+        fn to_proto(
+            hash: Result<domain::Hash, DagCacheError>,
+        ) -> Result<GetHashForKeyResp, Status> {
+            hash.map(|h| GetHashForKeyResp {
+                hash: Some(h.into_proto()),
+            })
+            .map_err(Status::from)
+        }
+        let to_proto: fn(_) -> _ = to_proto;
+
+        Ok(Response::new(Permitted::new(updates.map(to_proto), permit)))
+    }
+
+    #[instrument(skip(self))]
+    async fn export_root_handler(
+        &self,
+        request: Request<ExportRootReq>,
+    ) -> Result<Response<ExportRootStream>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Read)?;
+        let permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
+
+        let request = api::export_root::Req::from_proto(request.into_inner()).map_err( |e| {
+            event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
+            e
+        })?;
+
+        let mhs = tenant.scope(&self.mutable_hash_store);
+        let chunks = archive::export(&self.hashed_blob_store, &mhs, request).await?;
+
+        fn to_proto(chunk: Result<Vec<u8>, DagCacheError>) -> Result<ArchiveChunk, Status> {
+            chunk.map(|data| ArchiveChunk { data }).map_err(Status::from)
+        }
+        let to_proto: fn(_) -> _ = to_proto;
+
+        Ok(Response::new(Permitted::new(chunks.map(to_proto), permit)))
+    }
+
+    #[instrument(skip(self, request))] // skip potentially-large request (TODO record stats w/o full message body)
+    async fn import_archive_handler(
+        &self,
+        request: Request<tonic::Streaming<ArchiveChunk>>,
+    ) -> Result<Response<ImportArchiveResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        let grant = self.authorize(request.metadata(), Access::Write)?;
+        let _permit = self.admit(&request)?;
+        let tenant = self.tenant(request.metadata(), &grant)?;
+
+        let mut stream = request.into_inner();
+        let mhs = tenant.scope(&self.mutable_hash_store);
+        let mut importer = archive::Importer::new(&self.hashed_blob_store, &mhs)
+            .with_limits(&self.limits)
+            .with_quota(&self.quotas, tenant)
+            .with_grant(grant);
+
+        while let Some(chunk) = stream.message().await? {
+            self.limits.check_message_size(&chunk)?;
+            importer.push(&chunk.data).await?;
+        }
+
+        let resp = importer.finish().await?;
+        Ok(Response::new(resp.into_proto()))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_cache_stats_handler(
+        &self,
+        request: Request<GetCacheStatsReq>,
+    ) -> Result<Response<GetCacheStatsResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        self.authorize(request.metadata(), Access::Admin)?;
+        let _permit = self.admit(&request)?;
+
+        let top_n = request.into_inner().top_n as usize;
+        let stats = self.cache.stats();
+        let requests = stats.hits + stats.misses;
+        let hit_ratio = if requests == 0 {
+            0.0
+        } else {
+            stats.hits as f64 / requests as f64
+        };
+
+        let most_accessed = self
+            .cache
+            .most_accessed(top_n)
+            .into_iter()
+            .map(|(hash, accesses)| api::cache_stats::AccessCount { hash, accesses })
+            .collect();
+
+        let resp = api::cache_stats::Resp {
+            entry_count: stats.entries,
+            byte_usage: stats.bytes,
+            max_bytes: stats.max_bytes,
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_ratio,
+            evictions: stats.evictions,
+            rejected: stats.rejected,
+            most_accessed,
+        };
+        Ok(Response::new(resp.into_proto()))
+    }
+
+    #[instrument(skip(self))]
+    async fn warm_cache_handler(
+        &self,
+        request: Request<WarmCacheReq>,
+    ) -> Result<Response<WarmCacheResp>, HandlerError> {
+        // extract explicit tracing id (if any)
+        extract_tracing_id_and_record(request.metadata());
+        self.authorize(request.metadata(), Access::Admin)?;
+        let _permit = self.admit(&request)?;
+
+        let request = api::warm_cache::Req::from_proto(request.into_inner()).map_err( |e| {
+            event!(Level::ERROR, msg = "unable to parse request proto as valid domain object", error = ?e);
+            e
+        })?;
+
+        let mut nodes = batch_get::batch_get(
+            &self.hashed_blob_store,
+            &self.cache,
+            request.root,
+            Some(request.depth),
+        );
+
+        let mut node_count = 0;
+        while let Some(node) = nodes.recv().await {
+            node?;
+            node_count += 1;
+        }
+
+        info!("warmed cache with {} nodes", node_count);
+        Ok(Response::new(WarmCacheResp { node_count }))
+    }
 }
 
 // NOTE: async_trait and instrument are mutually incompatible, so use non-async-trait fns and async trait stubs
@@ -117,83 +503,260 @@ impl DagStore for Runtime {
         &self,
         request: Request<GetHashForKeyReq>,
     ) -> Result<Response<GetHashForKeyResp>, Status> {
-        self.get_hash_for_key_handler(request).await
+        observe("get_hash_for_key", self.get_hash_for_key_handler(request)).await
+    }
+
+    type WatchKeyStream = WatchKeyStream;
+
+    async fn watch_key(
+        &self,
+        request: Request<GetHashForKeyReq>,
+    ) -> Result<Response<Self::WatchKeyStream>, Status> {
+        observe("watch_key", self.watch_key_handler(request)).await
+    }
+
+    async fn list_keys(
+        &self,
+        request: Request<ListKeysReq>,
+    ) -> Result<Response<ListKeysResp>, Status> {
+        observe("list_keys", self.list_keys_handler(request)).await
     }
 
     async fn get_node(&self, request: Request<Hash>) -> Result<Response<GetResp>, Status> {
-        self.get_node_handler(request).await
+        observe("get_node", self.get_node_handler(request)).await
     }
 
     async fn put_node(&self, request: Request<Node>) -> Result<Response<Hash>, Status> {
-        self.put_node_handler(request).await
+        observe("put_node", self.put_node_handler(request)).await
+    }
+
+    async fn resolve(&self, request: Request<ResolveReq>) -> Result<Response<ResolveResp>, Status> {
+        observe("resolve", self.resolve_handler(request)).await
+    }
+
+    type DiffStream = DiffStream;
+
+    async fn diff(&self, request: Request<DiffReq>) -> Result<Response<Self::DiffStream>, Status> {
+        observe("diff", self.diff_handler(request)).await
+    }
+
+    async fn has_nodes(
+        &self,
+        request: Request<HasNodesReq>,
+    ) -> Result<Response<HasNodesResp>, Status> {
+        observe("has_nodes", self.has_nodes_handler(request)).await
     }
 
     async fn put_nodes(
         &self,
         request: Request<BulkPutReq>,
     ) -> Result<Response<BulkPutResp>, Status> {
-        self.put_nodes_handler(request).await
+        observe("put_nodes", self.put_nodes_handler(request)).await
+    }
+
+    type ExportRootStream = ExportRootStream;
+
+    async fn export_root(
+        &self,
+        request: Request<ExportRootReq>,
+    ) -> Result<Response<Self::ExportRootStream>, Status> {
+        observe("export_root", self.export_root_handler(request)).await
+    }
+
+    async fn import_archive(
+        &self,
+        request: Request<tonic::Streaming<ArchiveChunk>>,
+    ) -> Result<Response<ImportArchiveResp>, Status> {
+        observe("import_archive", self.import_archive_handler(request)).await
+    }
+
+    async fn get_cache_stats(
+        &self,
+        request: Request<GetCacheStatsReq>,
+    ) -> Result<Response<GetCacheStatsResp>, Status> {
+        observe("get_cache_stats", self.get_cache_stats_handler(request)).await
+    }
+
+    async fn warm_cache(
+        &self,
+        request: Request<WarmCacheReq>,
+    ) -> Result<Response<WarmCacheResp>, Status> {
+        observe("warm_cache", self.warm_cache_handler(request)).await
     }
 }
 
-/// Extract a tracing id from the provided metadata
-fn extract_tracing_id_and_record(meta: &tonic::metadata::MetadataMap) -> Result<(), Status> {
-    match (
-        meta.get(TraceId::meta_field_name()),
-        meta.get(SpanId::meta_field_name()),
-    ) {
-        (Some(trace_id), Some(parent_span_id)) => {
-            let trace_id = trace_id.to_str().map_err(|e| {
-                event!(Level::ERROR, msg = "trace id metadata not valid ascii", error = ?e);
-                Status::new(
-                    Code::InvalidArgument,
-                    format!("trace id metadata not valid ascii, {:?}", e),
-                )
-            })?;
-            let trace_id = TraceId::from_str(trace_id).map_err(|e| {
-                event!(Level::ERROR, msg = "died parsing trace id from metadata", error = ?e);
-                Status::new(
-                    Code::InvalidArgument,
-                    format!("died parsing trace id from metadata, {:?}", e),
-                )
-            })?;
+/// handler error, DagCacheErrors are kept unconverted so metrics can record their variant
+pub enum HandlerError {
+    Status(Status),
+    DagCache(DagCacheError),
+}
 
-            let parent_span_id = parent_span_id.to_str().map_err(|e| {
-                event!(Level::ERROR, msg = "parent span id metadata not valid ascii", error = ?e);
-                Status::new(
-                    Code::InvalidArgument,
-                    format!("parent span id metadata not valid ascii, {:?}", e),
-                )
-            })?;
-            let parent_span_id = SpanId::from_str(parent_span_id).map_err(|e| {
-                event!(Level::ERROR, msg = "died parsing span id from metadata", error = ?e);
+impl From<Status> for HandlerError {
+    fn from(status: Status) -> Self {
+        HandlerError::Status(status)
+    }
+}
+
+impl From<DagCacheError> for HandlerError {
+    fn from(error: DagCacheError) -> Self {
+        HandlerError::DagCache(error)
+    }
+}
+
+impl From<ProtoDecodingError> for HandlerError {
+    fn from(error: ProtoDecodingError) -> Self {
+        HandlerError::DagCache(error.into())
+    }
+}
+
+impl From<HandlerError> for Status {
+    fn from(error: HandlerError) -> Self {
+        match error {
+            HandlerError::Status(status) => status,
+            HandlerError::DagCache(error) => error.into(),
+        }
+    }
+}
+
+/// record latency and failures for an rpc, converting handler errors to statuses
+async fn observe<T, F>(method: &'static str, handler: F) -> Result<T, Status>
+where
+    F: Future<Output = Result<T, HandlerError>>,
+{
+    let timer = metrics::RPC_DURATION
+        .with_label_values(&[method])
+        .start_timer();
+    let res = handler.await;
+    timer.observe_duration();
+
+    res.map_err(|e| {
+        let error = match &e {
+            HandlerError::Status(status) => format!("{:?}", status.code()),
+            HandlerError::DagCache(error) => error.kind().to_string(),
+        };
+        metrics::RPC_ERRORS
+            .with_label_values(&[method, &error])
+            .inc();
+        e.into()
+    })
+}
+
+/// Extract the tenant (if any) from the provided metadata
+fn extract_tenant(meta: &tonic::metadata::MetadataMap) -> Result<Tenant, Status> {
+    match meta.get(TENANT_ID_META_FIELD) {
+        Some(tenant_id) => {
+            let tenant_id = tenant_id.to_str().map_err(|e| {
+                event!(Level::ERROR, msg = "tenant id metadata not valid ascii", error = ?e);
                 Status::new(
                     Code::InvalidArgument,
-                    format!("died parsing span id from metadata, {:?}", e),
+                    format!("tenant id metadata not valid ascii, {:?}", e),
                 )
             })?;
+            let tenant = Tenant::parse(tenant_id)?;
+            info!("request scoped to tenant {:?}", &tenant);
+            Ok(tenant)
+        }
+        None => Ok(Tenant::Default),
+    }
+}
 
-            register_dist_tracing_root(trace_id, Some(parent_span_id)).unwrap();
+// same accounting as the cache, links are counted as headers regardless of type
+fn bulk_put_node_size(node: &api::bulk_put::Node) -> usize {
+    node.data.0.len() + node.links.len() * std::mem::size_of::<domain::Header>()
+}
 
-            Ok(())
+/// Extract the w3c trace context (traceparent) from the provided metadata
+/// a malformed traceparent is logged and ignored, starting a new trace, as the w3c trace
+/// context spec requires
+fn extract_tracing_id_and_record(meta: &tonic::metadata::MetadataMap) {
+    let traceparent = meta.get(TRACEPARENT_META_FIELD).and_then(|traceparent| {
+        let parsed = traceparent
+            .to_str()
+            .map_err(|e| format!("not valid ascii, {:?}", e))
+            .and_then(|t| TraceParent::from_str(t).map_err(|e| format!("{:?}", e)));
+        match parsed {
+            Ok(traceparent) => Some(traceparent),
+            Err(e) => {
+                event!(Level::WARN, msg = "ignoring malformed traceparent metadata", error = %e);
+                None
+            }
         }
-        (Some(_), None) | (None, Some(_)) => {
-            event!(
-                Level::ERROR,
-                msg = "provided trace id but not span id or vice versa"
-            );
-            let err = Status::new(
-                Code::InvalidArgument,
-                format!("metadata included trace id but not span id or vice versa"),
-            );
-            Err(err)
+    });
+
+    match traceparent {
+        Some(traceparent) => {
+            register_dist_tracing_root(traceparent.trace_id, Some(traceparent.parent_id)).unwrap();
         }
-        (None, None) => {
+        None => {
             // register as top-level trace root
             let trace_id = TraceId::generate();
             register_dist_tracing_root(trace_id, None).unwrap();
-
-            Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, spawn_dag_store, test_runtime};
+    use dag_store_types::types::grpc::dag_store_client::DagStoreClient;
+    use std::collections::HashMap;
+    use tonic::metadata::{MetadataMap, MetadataValue};
+
+    #[test]
+    fn test_tenant_bound_to_grant() {
+        let (mut runtime, _dir) = test_runtime();
+        let alice = Grant::parse("read,tenant:alice").unwrap();
+        let mut bob = MetadataMap::new();
+        bob.insert(
+            TENANT_ID_META_FIELD,
+            MetadataValue::from_str("bob").unwrap(),
+        );
+
+        // without auth, the tenant is whatever the client asks for
+        assert_eq!(
+            runtime.tenant(&bob, &Grant::all()).unwrap(),
+            Tenant::parse("bob").unwrap()
+        );
+
+        runtime.auth = Some(Arc::new(Authenticator::new(HashMap::new(), None)));
+        let alice_tenant = Tenant::parse("alice").unwrap();
+        assert_eq!(
+            runtime.tenant(&MetadataMap::new(), &alice).unwrap(),
+            alice_tenant
+        );
+        let mut alice_meta = MetadataMap::new();
+        alice_meta.insert(
+            TENANT_ID_META_FIELD,
+            MetadataValue::from_str("alice").unwrap(),
+        );
+        assert_eq!(runtime.tenant(&alice_meta, &alice).unwrap(), alice_tenant);
+        assert_eq!(
+            runtime.tenant(&bob, &alice).unwrap_err().code(),
+            Code::PermissionDenied
+        );
+        // a token without a tenant can't reach named tenants either
+        let default = Grant::parse("read").unwrap();
+        assert_eq!(
+            runtime.tenant(&bob, &default).unwrap_err().code(),
+            Code::PermissionDenied
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_traceparent_ignored() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let (_runtime, url) = spawn_dag_store(runtime).await;
+
+        let mut client = DagStoreClient::connect(url).await.unwrap();
+        let mut request = Request::new(ListKeysReq {
+            prefix: String::new(),
+        });
+        request.metadata_mut().insert(
+            TRACEPARENT_META_FIELD,
+            MetadataValue::from_str("not-a-traceparent").unwrap(),
+        );
+        client.list_keys(request).await.unwrap();
+    }
+}
@@ -0,0 +1,415 @@
+use crate::capabilities::cache::node_size;
+use crate::capabilities::tenant::{Quotas, Tenant};
+use crate::capabilities::{HashedBlobStore, MutableHashStore};
+use crate::server::auth::Grant;
+use crate::server::limits::Limits;
+use dag_store_types::types::api::{export_root, import_archive};
+use dag_store_types::types::archive::{
+    self, ArchiveError, ArchiveHeader, ArchiveReader, Item, Root,
+};
+use dag_store_types::types::domain::{Hash, Node};
+use dag_store_types::types::errors::DagCacheError;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+// nodes are buffered into chunks of roughly this size before being sent
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub async fn export<'a>(
+    store: &'a Arc<dyn HashedBlobStore>,
+    mhs: &'a Arc<dyn MutableHashStore>,
+    req: export_root::Req,
+) -> Result<mpsc::Receiver<Result<Vec<u8>, DagCacheError>>, DagCacheError> {
+    let roots = match req {
+        export_root::Req::Hash(hash) => vec![Root { key: None, hash }],
+        export_root::Req::Key(key) => {
+            let hash = mhs
+                .get(&key)
+                .await?
+                .ok_or_else(|| DagCacheError::NotFound(format!("no hash for key {}", key)))?;
+            vec![Root {
+                key: Some(key),
+                hash,
+            }]
+        }
+        export_root::Req::AllKeys => mhs
+            .list()
+            .await?
+            .into_iter()
+            .map(|(key, hash)| Root {
+                key: Some(key),
+                hash,
+            })
+            .collect(),
+    };
+
+    // checked up front so a missing root is reported as such, rather than failing the stream
+    for root in roots.iter() {
+        if !store.has(root.hash).await? {
+            return Err(DagCacheError::NotFound(format!(
+                "no node with hash {}",
+                root.hash
+            )));
+        }
+    }
+
+    info!("starting export for {} roots", roots.len());
+    let (send, receive) = mpsc::channel(16);
+    let store = store.clone();
+    tokio::spawn(async move { export_worker(store, ArchiveHeader { roots }, send).await });
+
+    Ok(receive)
+}
+
+async fn export_worker(
+    store: Arc<dyn HashedBlobStore>,
+    header: ArchiveHeader,
+    mut resp_chan: mpsc::Sender<Result<Vec<u8>, DagCacheError>>,
+) {
+    if let Err(e) = export_nodes(store, header, &mut resp_chan).await {
+        let sr = resp_chan.send(Err(e)).await;
+        if let Err(e) = sr {
+            error!("failed sending resp via mpsc due to {:?}", e);
+        };
+    }
+}
+
+// post-order traversal, a node is only written after all of its children have been written
+async fn export_nodes(
+    store: Arc<dyn HashedBlobStore>,
+    header: ArchiveHeader,
+    resp_chan: &mut mpsc::Sender<Result<Vec<u8>, DagCacheError>>,
+) -> Result<(), DagCacheError> {
+    let mut buf = Vec::new();
+    archive::encode_header(&header, &mut buf);
+
+    let mut visited = HashSet::new();
+    let mut node_count = 0;
+    // None: not yet fetched, Some: fetched, children already pushed above it on the stack
+    let mut stack: Vec<(Hash, Option<Node>)> =
+        header.roots.iter().rev().map(|r| (r.hash, None)).collect();
+
+    while let Some((hash, node)) = stack.pop() {
+        match node {
+            Some(node) => {
+                archive::encode_node(&node, &mut buf);
+                node_count += 1;
+
+                if buf.len() >= CHUNK_SIZE {
+                    let chunk = std::mem::take(&mut buf);
+                    resp_chan
+                        .send(Ok(chunk))
+                        .await
+                        .map_err(DagCacheError::unexpected)?;
+                }
+            }
+            None => {
+                // acyclic, so an already-visited node has already been written
+                if !visited.insert(hash) {
+                    continue;
+                }
+                let node = store.get(hash).await?;
+                let links: Vec<Hash> = node.links.iter().map(|l| l.hash).collect();
+                stack.push((hash, Some(node)));
+                for link in links.into_iter().rev() {
+                    if !visited.contains(&link) {
+                        stack.push((link, None));
+                    }
+                }
+            }
+        }
+    }
+
+    archive::encode_end(node_count, &mut buf);
+    resp_chan
+        .send(Ok(buf))
+        .await
+        .map_err(DagCacheError::unexpected)?;
+
+    info!("export complete, wrote {} nodes", node_count);
+    Ok(())
+}
+
+/// consumes archive bytes as they arrive, writing each verified node to the store
+pub struct Importer {
+    store: Arc<dyn HashedBlobStore>,
+    mhs: Arc<dyn MutableHashStore>,
+    reader: ArchiveReader,
+    roots: Vec<Root>,
+    node_count: u64,
+    // bytes imported so far, counted as in the cache
+    byte_count: u64,
+    // dag size of each node imported so far, so links within the archive are checked without
+    // reading the node back from the store
+    sizes: HashMap<Hash, u64>,
+    // the whole import counts as one bulk put against these, if set
+    limits: Option<Arc<Limits>>,
+    // charged for each imported node, if set
+    quota: Option<(Arc<Quotas>, Tenant)>,
+    // keys in the archive are only written if this permits it
+    grant: Grant,
+}
+
+impl Importer {
+    pub fn new(store: &Arc<dyn HashedBlobStore>, mhs: &Arc<dyn MutableHashStore>) -> Self {
+        Importer {
+            store: store.clone(),
+            mhs: mhs.clone(),
+            reader: ArchiveReader::new(),
+            roots: Vec::new(),
+            node_count: 0,
+            byte_count: 0,
+            sizes: HashMap::new(),
+            limits: None,
+            quota: None,
+            grant: Grant::all(),
+        }
+    }
+
+    pub fn with_grant(self, grant: Grant) -> Self {
+        Importer { grant, ..self }
+    }
+
+    pub fn with_limits(self, limits: &Arc<Limits>) -> Self {
+        Importer {
+            limits: Some(limits.clone()),
+            ..self
+        }
+    }
+
+    pub fn with_quota(self, quotas: &Arc<Quotas>, tenant: Tenant) -> Self {
+        Importer {
+            quota: Some((quotas.clone(), tenant)),
+            ..self
+        }
+    }
+
+    pub async fn push(&mut self, bytes: &[u8]) -> Result<(), DagCacheError> {
+        self.reader.push(bytes);
+        while let Some(item) = self.reader.next_item()? {
+            match item {
+                Item::Header(header) => self.roots = header.roots,
+                Item::Node(hash, node) => {
+                    // archives are in topological order, so children are either earlier in the
+                    // archive (and so already written) or were already in the store
+                    let links: Vec<Hash> = node.links.iter().map(|l| l.hash).collect();
+                    let present = self.store.has_many(&links).await?;
+                    if let Some((link, _)) = links.iter().zip(present).find(|(_, p)| !p) {
+                        let msg = format!(
+                            "node {} links to {}, which is neither earlier in the archive nor \
+                             in the store",
+                            hash, link
+                        );
+                        return Err(ArchiveError(msg).into());
+                    }
+                    for link in node.links.iter() {
+                        let size = match self.sizes.get(&link.hash) {
+                            Some(size) => *size,
+                            None => self.store.get(link.hash).await?.dag_size(),
+                        };
+                        if link.size != size {
+                            let msg = format!(
+                                "node {} link {} to {} records size {}, actual size {}",
+                                hash, link.id, link.hash, link.size, size
+                            );
+                            return Err(ArchiveError(msg).into());
+                        }
+                    }
+                    let size = node_size(&node) as u64;
+                    if let Some(limits) = &self.limits {
+                        limits.check_bulk_put(self.node_count + 1, self.byte_count + size)?;
+                    }
+                    let charge = match &self.quota {
+                        Some((quotas, tenant)) => Some(quotas.charge(tenant, 1, size)?),
+                        None => None,
+                    };
+                    let dag_size = node.dag_size();
+                    self.store.put(node).await?;
+                    if let Some(charge) = charge {
+                        charge.commit();
+                    }
+                    self.sizes.insert(hash, dag_size);
+                    self.node_count += 1;
+                    self.byte_count += size;
+                }
+                Item::End { .. } => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// verify the archive was complete and point any archived keys at their roots.
+    /// keys that already exist with a different value are left untouched
+    pub async fn finish(self) -> Result<import_archive::Resp, DagCacheError> {
+        self.reader.finish()?;
+
+        let mut keys = Vec::new();
+        for root in self.roots.into_iter() {
+            // fails if root was neither included in the archive nor already present
+            self.store.get(root.hash).await?;
+
+            if let Some(key) = root.key {
+                self.grant.check_cas_key(&key)?;
+                let written = match self.mhs.get(&key).await? {
+                    Some(current) => current == root.hash,
+                    None => match self.mhs.cas(&key, None, root.hash).await {
+                        Ok(()) => true,
+                        Err(DagCacheError::CASViolationError { .. }) => false,
+                        Err(e) => return Err(e),
+                    },
+                };
+                if !written {
+                    info!("not overwriting existing key {} during import", &key);
+                }
+                keys.push(import_archive::ImportedKey {
+                    key,
+                    hash: root.hash,
+                    written,
+                });
+            }
+        }
+
+        info!("import complete, read {} nodes", self.node_count);
+        Ok(import_archive::Resp {
+            node_count: self.node_count,
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::limits::LimitsConfig;
+    use crate::test_utils::test_runtime;
+    use dag_store_types::types::domain::{Header, Id};
+    use dag_store_types::types::encodings::Base64;
+
+    fn leaf(data: &[u8]) -> Node {
+        Node {
+            links: vec![],
+            data: Base64(data.to_vec()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_requires_children() {
+        let (runtime, _dir) = test_runtime();
+        let store = runtime.hashed_blob_store.clone();
+        let mhs = runtime.mutable_hash_store.clone();
+
+        let child = leaf(b"child");
+        let parent = Node {
+            links: vec![Header {
+                id: Id(0),
+                hash: child.canonical_hash(),
+                size: 5,
+            }],
+            data: Base64(vec![]),
+        };
+        let header = ArchiveHeader {
+            roots: vec![Root {
+                key: None,
+                hash: parent.canonical_hash(),
+            }],
+        };
+
+        // parent without its child
+        let mut buf = Vec::new();
+        archive::encode_header(&header, &mut buf);
+        archive::encode_node(&parent, &mut buf);
+        archive::encode_end(1, &mut buf);
+        let mut importer = Importer::new(&store, &mhs);
+        match importer.push(&buf).await {
+            Err(DagCacheError::ArchiveError(_)) => {}
+            res => panic!("expected archive error, got {:?}", res),
+        }
+        assert!(!store.has(parent.canonical_hash()).await.unwrap());
+
+        // fine once the child is in the store
+        store.put(child).await.unwrap();
+        let mut importer = Importer::new(&store, &mhs);
+        importer.push(&buf).await.unwrap();
+        let resp = importer.finish().await.unwrap();
+        assert_eq!(resp.node_count, 1);
+
+        // exporting a node that isn't there fails as not found
+        let missing = leaf(b"missing").canonical_hash();
+        match export(&store, &mhs, export_root::Req::Hash(missing)).await {
+            Err(DagCacheError::NotFound(_)) => {}
+            Err(e) => panic!("expected not found, got {:?}", e),
+            Ok(_) => panic!("expected not found"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_checks_link_sizes() {
+        let (runtime, _dir) = test_runtime();
+        let store = runtime.hashed_blob_store.clone();
+        let mhs = runtime.mutable_hash_store.clone();
+
+        let child = leaf(b"child");
+        let parent = |size| Node {
+            links: vec![Header {
+                id: Id(0),
+                hash: child.canonical_hash(),
+                size,
+            }],
+            data: Base64(vec![]),
+        };
+        let archive = |parent: &Node| {
+            let header = ArchiveHeader {
+                roots: vec![Root {
+                    key: None,
+                    hash: parent.canonical_hash(),
+                }],
+            };
+            let mut buf = Vec::new();
+            archive::encode_header(&header, &mut buf);
+            archive::encode_node(&child, &mut buf);
+            archive::encode_node(parent, &mut buf);
+            archive::encode_end(2, &mut buf);
+            buf
+        };
+
+        let mut importer = Importer::new(&store, &mhs);
+        match importer.push(&archive(&parent(4))).await {
+            Err(DagCacheError::ArchiveError(_)) => {}
+            res => panic!("expected archive error, got {:?}", res),
+        }
+        assert!(!store.has(parent(4).canonical_hash()).await.unwrap());
+
+        let mut importer = Importer::new(&store, &mhs);
+        importer.push(&archive(&parent(5))).await.unwrap();
+        assert_eq!(importer.finish().await.unwrap().node_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_counts_as_one_bulk_put() {
+        let (runtime, _dir) = test_runtime();
+        let store = runtime.hashed_blob_store.clone();
+        let mhs = runtime.mutable_hash_store.clone();
+        let limits = Arc::new(Limits::new(LimitsConfig {
+            max_bulk_put_nodes: Some(2),
+            ..LimitsConfig::default()
+        }));
+
+        let header = ArchiveHeader { roots: vec![] };
+        let mut buf = Vec::new();
+        archive::encode_header(&header, &mut buf);
+        for data in [b"a", b"b", b"c"].iter() {
+            archive::encode_node(&leaf(*data), &mut buf);
+        }
+        archive::encode_end(3, &mut buf);
+
+        // each node is within the limit, the archive as a whole isn't
+        let mut importer = Importer::new(&store, &mhs).with_limits(&limits);
+        match importer.push(&buf).await {
+            Err(DagCacheError::LimitExceeded(_)) => {}
+            res => panic!("expected limit exceeded, got {:?}", res),
+        }
+        assert!(!store.has(leaf(b"c").canonical_hash()).await.unwrap());
+    }
+}
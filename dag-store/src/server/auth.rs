@@ -0,0 +1,397 @@
+use crate::capabilities::tenant::Tenant;
+use dag_store_types::types::errors::DagCacheError;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::{Code, Interceptor, Request, Status};
+use tracing::{event, Level};
+
+/// metadata field carrying the bearer token, as in http
+pub const AUTHORIZATION_META_FIELD: &str = "authorization";
+
+// metadata field the interceptor passes the verified grant to handlers in. tonic 0.1 doesn't
+// expose request extensions, and the interceptor overwrites any value sent by the client
+const GRANT_META_FIELD: &str = "x-dag-store-verified-grant";
+
+// metadata field the interceptor passes a digest of the verified token in, as above
+const CLIENT_META_FIELD: &str = "x-dag-store-verified-client";
+
+// prefix of signed tokens, distinguishes them from static tokens
+const SIGNED_TOKEN_PREFIX: &str = "v2.";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// cache inspection and control (GetCacheStats, WarmCache)
+    Admin,
+}
+
+/// what a token is allowed to do. written as a comma separated list, eg
+/// `read,write,cas:notes-app/,tenant:alice`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    pub read: bool,
+    pub write: bool,
+    #[serde(default)]
+    pub admin: bool,
+    /// keys this token may update, by prefix (an empty prefix allows all keys). keys are
+    /// relative to the token's tenant
+    pub cas_key_prefixes: Vec<String>,
+    /// tenant every request made with this token is scoped to, the default tenant if None
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+impl Grant {
+    /// used when auth is disabled
+    pub fn all() -> Self {
+        Grant {
+            read: true,
+            write: true,
+            admin: true,
+            cas_key_prefixes: vec![String::new()],
+            tenant: None,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, DagCacheError> {
+        let mut grant = Grant::default();
+        for capability in s.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            match capability.split_once(':') {
+                None if capability == "read" => grant.read = true,
+                None if capability == "write" => grant.write = true,
+                None if capability == "admin" => grant.admin = true,
+                Some(("cas", prefix)) => grant.cas_key_prefixes.push(prefix.to_string()),
+                Some(("tenant", id)) => {
+                    if grant.tenant.is_some() {
+                        return Err(DagCacheError::InvalidArgument(
+                            "at most one tenant may be granted".to_string(),
+                        ));
+                    }
+                    Tenant::parse(id)?;
+                    grant.tenant = Some(id.to_string());
+                }
+                _ => {
+                    return Err(DagCacheError::InvalidArgument(format!(
+                        "unknown capability {:?}, expected read, write, admin, cas:<key prefix> \
+                         or tenant:<id>",
+                        capability
+                    )))
+                }
+            }
+        }
+        Ok(grant)
+    }
+
+    /// the tenant requests made with this token are scoped to
+    pub fn tenant(&self) -> Tenant {
+        match &self.tenant {
+            Some(id) => Tenant::Named(id.clone()),
+            None => Tenant::Default,
+        }
+    }
+
+    pub fn check(&self, access: Access) -> Result<(), Status> {
+        let allowed = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Admin => self.admin,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(Status::new(
+                Code::PermissionDenied,
+                format!("token does not grant {:?} access", access),
+            ))
+        }
+    }
+
+    pub fn check_cas_key(&self, key: &str) -> Result<(), DagCacheError> {
+        if self.cas_key_prefixes.iter().any(|p| key.starts_with(p)) {
+            Ok(())
+        } else {
+            Err(DagCacheError::PermissionDenied(format!(
+                "token does not grant cas access to key {}",
+                key
+            )))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Claims {
+    grant: Grant,
+    /// seconds since the unix epoch
+    expires_at: u64,
+}
+
+/// verifies bearer tokens, either listed in a static token file or signed with a shared key.
+/// signed tokens are MACed with HMAC-SHA256
+pub struct Authenticator {
+    static_tokens: HashMap<String, Grant>,
+    signing_key: Option<Vec<u8>>,
+}
+
+impl Authenticator {
+    pub fn new(static_tokens: HashMap<String, Grant>, signing_key: Option<Vec<u8>>) -> Self {
+        Authenticator {
+            static_tokens,
+            signing_key,
+        }
+    }
+
+    /// parse a token file, one `<token> <capabilities>` pair per line. blank lines and lines
+    /// starting with # are ignored
+    pub fn read_token_file(path: &Path) -> Result<HashMap<String, Grant>, DagCacheError> {
+        let contents = fs::read_to_string(path).map_err(DagCacheError::unexpected)?;
+        let mut tokens = HashMap::new();
+        for line in contents.lines().map(|l| l.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let token = parts.next().unwrap_or_default();
+            let grant = Grant::parse(parts.next().unwrap_or_default())?;
+            tokens.insert(token.to_string(), grant);
+        }
+        Ok(tokens)
+    }
+
+    /// read the HMAC key from a key file, used as is
+    pub fn read_signing_key_file(path: &Path) -> Result<Vec<u8>, DagCacheError> {
+        let key = fs::read(path).map_err(DagCacheError::unexpected)?;
+        if key.is_empty() {
+            return Err(DagCacheError::InvalidArgument(format!(
+                "signing key file {} is empty",
+                path.display()
+            )));
+        }
+        Ok(key)
+    }
+
+    /// mint a token granting grant until expires_at (seconds since the unix epoch)
+    pub fn sign(signing_key: &[u8], grant: Grant, expires_at: u64) -> String {
+        let claims = Claims { grant, expires_at };
+        // serializing a struct of plain fields can't fail
+        let claims = serde_json::to_vec(&claims).expect("claims serialization failed");
+        let mut mac = hmac_sha256(signing_key);
+        mac.input(&claims);
+        format!(
+            "{}{}.{}",
+            SIGNED_TOKEN_PREFIX,
+            base64::encode_config(&claims, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(&mac.result().code(), base64::URL_SAFE_NO_PAD),
+        )
+    }
+
+    pub fn authenticate(&self, meta: &MetadataMap) -> Result<Grant, Status> {
+        let unauthenticated = |msg: &str| {
+            event!(Level::INFO, msg = "rejected request", reason = msg);
+            Status::new(Code::Unauthenticated, msg)
+        };
+
+        let header = meta
+            .get(AUTHORIZATION_META_FIELD)
+            .ok_or_else(|| unauthenticated("no authorization metadata"))?;
+        let header = header
+            .to_str()
+            .map_err(|_| unauthenticated("authorization metadata not valid ascii"))?;
+        let token = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| unauthenticated("authorization metadata is not a bearer token"))?;
+
+        if let Some(grant) = self.static_tokens.get(token) {
+            return Ok(grant.clone());
+        }
+
+        match (token.strip_prefix(SIGNED_TOKEN_PREFIX), &self.signing_key) {
+            (Some(signed), Some(key)) => {
+                let claims = verify(key, signed).ok_or_else(|| unauthenticated("invalid token"))?;
+                if claims.expires_at <= now_secs() {
+                    return Err(unauthenticated("token expired"));
+                }
+                Ok(claims.grant)
+            }
+            _ => Err(unauthenticated("invalid token")),
+        }
+    }
+}
+
+/// rejects requests without a valid token before their bodies are decoded, passing the grant
+/// on to the handler (see `verified_grant`), which knows what the request does
+pub fn interceptor(auth: &Arc<Authenticator>) -> Interceptor {
+    let auth = auth.clone();
+    Interceptor::new(move |request| pass_grant(&auth, request))
+}
+
+fn pass_grant(auth: &Authenticator, mut request: Request<()>) -> Result<Request<()>, Status> {
+    let grant = auth.authenticate(request.metadata())?;
+    // serializing a struct of plain fields can't fail
+    let grant = serde_json::to_vec(&grant).expect("grant serialization failed");
+    let grant = base64::encode_config(&grant, base64::URL_SAFE_NO_PAD);
+    // ASSERTION: url safe base64 is always a valid metadata value
+    let grant = MetadataValue::from_str(&grant).unwrap();
+
+    // ASSERTION: authenticate checked the authorization header is present
+    let token = request.metadata().get(AUTHORIZATION_META_FIELD).unwrap();
+    let digest = Sha256::digest(token.as_bytes());
+    let client = base64::encode_config(&digest[..12], base64::URL_SAFE_NO_PAD);
+    // ASSERTION: as above
+    let client = MetadataValue::from_str(&client).unwrap();
+
+    request.metadata_mut().insert(GRANT_META_FIELD, grant);
+    request.metadata_mut().insert(CLIENT_META_FIELD, client);
+    Ok(request)
+}
+
+/// the grant the interceptor verified for this request
+pub fn verified_grant(meta: &MetadataMap) -> Result<Grant, Status> {
+    let grant = meta
+        .get(GRANT_META_FIELD)
+        .and_then(|grant| grant.to_str().ok())
+        .and_then(|grant| base64::decode_config(grant, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|grant| serde_json::from_slice(&grant).ok());
+    grant.ok_or_else(|| Status::new(Code::Unauthenticated, "request was not authenticated"))
+}
+
+/// identifies the token the interceptor verified for this request, without revealing it
+pub fn verified_client(meta: &MetadataMap) -> Option<String> {
+    let client = meta.get(CLIENT_META_FIELD)?.to_str().ok()?;
+    Some(client.to_string())
+}
+
+/// the authorization metadata presenting token, checked when a client's token is configured
+pub fn bearer_header(token: &str) -> Result<MetadataValue<Ascii>, DagCacheError> {
+    MetadataValue::from_str(&format!("Bearer {}", token)).map_err(|_| {
+        DagCacheError::InvalidArgument("token contains characters not allowed in a header".into())
+    })
+}
+
+/// client side counterpart of `interceptor`, presenting token on every request
+pub fn bearer_interceptor(token: &str) -> Result<Interceptor, DagCacheError> {
+    let token = bearer_header(token)?;
+    Ok(Interceptor::new(move |mut request: Request<()>| {
+        request
+            .metadata_mut()
+            .insert(AUTHORIZATION_META_FIELD, token.clone());
+        Ok(request)
+    }))
+}
+
+fn hmac_sha256(key: &[u8]) -> HmacSha256 {
+    // ASSERTION: hmac accepts keys of any length
+    HmacSha256::new_varkey(key).expect("hmac rejected key")
+}
+
+fn verify(key: &[u8], signed: &str) -> Option<Claims> {
+    let mut parts = signed.splitn(2, '.');
+    let claims = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+    let mac = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+
+    // constant time comparison
+    let mut expected = hmac_sha256(key);
+    expected.input(&claims);
+    expected.verify(&mac).ok()?;
+    serde_json::from_slice(&claims).ok()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::metadata::MetadataValue;
+
+    fn meta(token: &str) -> MetadataMap {
+        let mut meta = MetadataMap::new();
+        let value = MetadataValue::from_str(&format!("Bearer {}", token)).unwrap();
+        meta.insert(AUTHORIZATION_META_FIELD, value);
+        meta
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let mut static_tokens = HashMap::new();
+        static_tokens.insert("reader".to_string(), Grant::parse("read").unwrap());
+        let key = vec![7; 32];
+        let auth = Authenticator::new(static_tokens, Some(key.clone()));
+
+        let grant = auth.authenticate(&meta("reader")).unwrap();
+        assert!(grant.check(Access::Read).is_ok());
+        assert!(grant.check(Access::Write).is_err());
+
+        let writer = Grant::parse("read, write, cas:notes-app/, tenant:alice").unwrap();
+        let token = Authenticator::sign(&key, writer.clone(), now_secs() + 60);
+        let grant = auth.authenticate(&meta(&token)).unwrap();
+        assert_eq!(grant, writer);
+        assert!(grant.check_cas_key("notes-app/main").is_ok());
+        assert!(grant.check_cas_key("other").is_err());
+        assert!(grant.check(Access::Admin).is_err());
+        assert_eq!(grant.tenant(), Tenant::parse("alice").unwrap());
+
+        // expired, signed with another key, unknown and missing tokens are all rejected
+        let expired = Authenticator::sign(&key, writer.clone(), now_secs() - 1);
+        let forged = Authenticator::sign(&[8; 32], writer, now_secs() + 60);
+        for token in [expired, forged, "unknown".to_string()].iter() {
+            let err = auth.authenticate(&meta(token)).unwrap_err();
+            assert_eq!(err.code(), Code::Unauthenticated);
+        }
+        assert!(auth.authenticate(&MetadataMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_grant() {
+        let grant = Grant::parse("admin,tenant:bob").unwrap();
+        assert!(grant.check(Access::Admin).is_ok());
+        assert!(grant.check(Access::Read).is_err());
+        assert_eq!(grant.tenant(), Tenant::parse("bob").unwrap());
+
+        for invalid in ["reed", "tenant:a/b", "tenant:a,tenant:b"].iter() {
+            match Grant::parse(invalid) {
+                Err(DagCacheError::InvalidArgument(_)) => {}
+                other => panic!("expected InvalidArgument for {}, got {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_interceptor_passes_grant() {
+        let mut static_tokens = HashMap::new();
+        static_tokens.insert("reader".to_string(), Grant::parse("read").unwrap());
+        let auth = Authenticator::new(static_tokens, None);
+
+        let mut request = Request::new(());
+        *request.metadata_mut() = meta("reader");
+        // a client can't claim a grant of its own
+        let forged = base64::encode_config(
+            &serde_json::to_vec(&Grant::all()).unwrap(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        request
+            .metadata_mut()
+            .insert(GRANT_META_FIELD, MetadataValue::from_str(&forged).unwrap());
+
+        let request = pass_grant(&auth, request).unwrap();
+        assert_eq!(
+            verified_grant(request.metadata()).unwrap(),
+            Grant::parse("read").unwrap()
+        );
+        let client = verified_client(request.metadata()).unwrap();
+        assert!(!client.contains("reader"));
+    }
+}
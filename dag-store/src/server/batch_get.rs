@@ -3,22 +3,27 @@ use crate::capabilities::{Cache, HashedBlobStore};
 use chashmap::CHashMap;
 use dag_store_types::types::domain::{Hash, Node};
 use dag_store_types::types::errors::DagCacheError;
+use std::cell::Cell;
 use std::sync::Arc;
 use tokio;
 use tokio::sync::mpsc;
 use tracing::{error, info};
 
-// NOTE: currently not exposed via GRPC, can re-enable if it becomes useful
+// NOTE: only used to warm the cache, not exposed directly via GRPC
+// max_depth bounds traversal (0 fetches only the root). nodes reachable via multiple paths
+// are only sent once, but are re-expanded if later reached with more depth remaining, so the
+// nodes returned don't depend on which path reaches them first
 pub fn batch_get<'a>(
     store: &'a Arc<dyn HashedBlobStore>,
     cache: &'a Arc<Cache>,
     hash: Hash,
-) -> mpsc::Receiver<Result<Node, DagCacheError>> {
+    max_depth: Option<u32>,
+) -> mpsc::Receiver<Result<Arc<Node>, DagCacheError>> {
     info!("starting recursive fetch for root hash {:?}", &hash);
     let (send, receive) = mpsc::channel(128); // randomly chose this channel buffer size..
     let memoizer = Arc::new(CHashMap::new());
 
-    batch_get_ana_internal(store, cache, hash, send, memoizer);
+    batch_get_ana_internal(store, cache, hash, max_depth, send, memoizer);
 
     receive
 }
@@ -28,20 +33,51 @@ fn batch_get_ana_internal<'a>(
     store: &'a Arc<dyn HashedBlobStore>,
     cache: &'a Arc<Cache>,
     hash: Hash,
-    resp_chan: mpsc::Sender<Result<Node, DagCacheError>>, // used to send completed nodes (eagerly)
-    to_populate: Arc<CHashMap<Hash, ()>>,                 // used to memoize async fetches
+    remaining_depth: Option<u32>,
+    resp_chan: mpsc::Sender<Result<Arc<Node>, DagCacheError>>, // used to send completed nodes (eagerly)
+    to_populate: Arc<CHashMap<Hash, Option<u32>>>, // most depth remaining each node was expanded with
 ) {
-    let store = store.clone();
-    let cache = cache.clone();
-    to_populate.clone().upsert(
+    // Some(true): first visit, send and expand. Some(false): already sent, expand deeper
+    let visit = Cell::new(None);
+    to_populate.upsert(
         hash,
         || {
-            tokio::spawn(async move {
-                batch_get_worker(store, cache, hash, resp_chan, to_populate).await
-            });
+            visit.set(Some(true));
+            remaining_depth
+        },
+        |expanded| {
+            if deeper(remaining_depth, *expanded) {
+                *expanded = remaining_depth;
+                visit.set(Some(false));
+            }
         },
-        |()| (),
     );
+
+    if let Some(send) = visit.get() {
+        let store = store.clone();
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            batch_get_worker(
+                store,
+                cache,
+                hash,
+                remaining_depth,
+                send,
+                resp_chan,
+                to_populate,
+            )
+            .await
+        });
+    }
+}
+
+// None is unbounded, deeper than any fixed depth
+fn deeper(remaining_depth: Option<u32>, expanded: Option<u32>) -> bool {
+    match (remaining_depth, expanded) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(d), Some(e)) => d > e,
+    }
 }
 
 // worker thread - uses one-shot channel to return result to avoid unbounded stack growth
@@ -49,18 +85,33 @@ async fn batch_get_worker(
     store: Arc<dyn HashedBlobStore>,
     cache: Arc<Cache>,
     hash: Hash,
-    mut resp_chan: mpsc::Sender<Result<Node, DagCacheError>>,
-    to_populate: Arc<CHashMap<Hash, ()>>, // used to memoize async fetches
+    remaining_depth: Option<u32>,
+    send: bool,
+    mut resp_chan: mpsc::Sender<Result<Arc<Node>, DagCacheError>>,
+    to_populate: Arc<CHashMap<Hash, Option<u32>>>,
 ) {
-    let res = get_and_cache(&store, &cache, hash).await;
+    // re-expanded nodes were already fetched and sent, avoid counting them as cache hits twice
+    let cached = if send { None } else { cache.peek(hash) };
+    let res = match cached {
+        Some(node) => Ok(node),
+        None => get_and_cache(&store, &cache, hash).await,
+    };
     match res {
         Ok(node) => {
-            let links = node.links.clone();
+            let links = match remaining_depth {
+                Some(0) => Vec::new(),
+                _ => node.links.clone(),
+            };
+            let remaining_depth = remaining_depth.map(|d| d.saturating_sub(1));
             // this way will only recurse on & traverse links if writing to channel doesn't fail
             // short circuit if failure
             // NOTE: should have some way to signal that this is an error instead of just failing?
             //       but the channel's broken so I can't.
-            let sr = resp_chan.send(Ok(node)).await;
+            let sr = if send {
+                resp_chan.send(Ok(node)).await
+            } else {
+                Ok(())
+            };
 
             // todo: weird type errors (async/await?), refactor later
             match sr {
@@ -70,6 +121,7 @@ async fn batch_get_worker(
                             &store,
                             &cache,
                             link.hash,
+                            remaining_depth,
                             resp_chan.clone(),
                             to_populate.clone(),
                         );
@@ -88,3 +140,52 @@ async fn batch_get_worker(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, test_runtime};
+    use dag_store_types::types::domain::{Header, Id};
+    use dag_store_types::types::encodings::Base64;
+
+    fn node(links: Vec<Hash>, data: u8) -> Node {
+        Node {
+            links: links
+                .into_iter()
+                .enumerate()
+                .map(|(i, hash)| Header {
+                    id: Id(i as u128),
+                    hash,
+                    size: 1,
+                })
+                .collect(),
+            data: Base64(vec![data]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reexpand_when_reached_shallower() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let store = runtime.hashed_blob_store.clone();
+
+        let leaf = store.put(node(vec![], 0)).await.unwrap();
+        let mid = store.put(node(vec![leaf], 1)).await.unwrap();
+        let root = store.put(node(vec![mid], 2)).await.unwrap();
+
+        // as if mid had already been reached via a deeper path, with no depth left to expand
+        let memo = Arc::new(CHashMap::new());
+        memo.insert(mid, Some(0));
+        let (send, mut receive) = mpsc::channel(128);
+        batch_get_ana_internal(&store, &runtime.cache, root, Some(2), send, memo);
+
+        let mut received = Vec::new();
+        while let Some(node) = receive.recv().await {
+            received.push(node.unwrap().canonical_hash());
+        }
+        // mid isn't sent again, but is re-expanded to reach leaf
+        assert_eq!(received.len(), 2);
+        assert!(received.contains(&root));
+        assert!(received.contains(&leaf));
+    }
+}
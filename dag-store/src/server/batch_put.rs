@@ -159,6 +159,11 @@ mod tests {
             Ok(v.clone())
         }
 
+        async fn has(&self, k: Hash) -> Result<bool, DagCacheError> {
+            let map = self.0.lock().unwrap();
+            Ok(map.contains_key(&k))
+        }
+
         async fn put(&self, node: Node) -> Result<Hash, DagCacheError> {
             let mut map = self.0.lock().unwrap(); // fail on mutex poisoned
 
@@ -209,7 +214,7 @@ mod tests {
 
         let store = Arc::new(MockStore(Mutex::new(HashMap::new())));
 
-        let cache = Arc::new(Cache::new(16));
+        let cache = Arc::new(Cache::new(1024 * 1024));
 
         fn shim(x: Arc<MockStore>) -> Arc<dyn HashedBlobStore> {
             x
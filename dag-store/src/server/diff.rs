@@ -0,0 +1,247 @@
+use crate::capabilities::get_and_cache;
+use crate::capabilities::{Cache, HashedBlobStore};
+use dag_store_types::types::api::diff::{Entry, Req};
+use dag_store_types::types::domain::{Hash, Header, Id};
+use dag_store_types::types::errors::DagCacheError;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+// used if the request doesn't specify a node budget
+const DEFAULT_NODE_BUDGET: u64 = 10_000;
+
+/// stream the differences between two DAGs, breadth-first. links are matched up between
+/// each pair of nodes by Header.id, and only pairs of matched links with different hashes
+/// are descended into, so identical subtrees are never fetched. a pair of subtrees reachable
+/// via multiple paths is only descended into once
+pub fn diff<'a>(
+    store: &'a Arc<dyn HashedBlobStore>,
+    cache: &'a Arc<Cache>,
+    req: Req,
+) -> mpsc::Receiver<Result<Entry, DagCacheError>> {
+    info!("starting diff of {} -> {}", req.old, req.new);
+    let (send, receive) = mpsc::channel(128);
+    let store = store.clone();
+    let cache = cache.clone();
+    tokio::spawn(async move {
+        let mut send = send;
+        if let Err(e) = diff_worker(store, cache, req, send.clone()).await {
+            // NOTE: failure to send just means the client went away
+            let _ = send.send(Err(e)).await;
+        }
+    });
+
+    receive
+}
+
+async fn diff_worker(
+    store: Arc<dyn HashedBlobStore>,
+    cache: Arc<Cache>,
+    req: Req,
+    mut resp_chan: mpsc::Sender<Result<Entry, DagCacheError>>,
+) -> Result<(), DagCacheError> {
+    let mut budget = match req.node_budget {
+        0 => DEFAULT_NODE_BUDGET,
+        n => n,
+    };
+
+    let mut frontier: VecDeque<(Hash, Hash)> = VecDeque::new();
+    // pairs already queued, shared subtrees would otherwise be re-fetched and re-diffed
+    let mut visited: HashSet<(Hash, Hash)> = HashSet::new();
+    if req.old != req.new {
+        frontier.push_back((req.old, req.new));
+        visited.insert((req.old, req.new));
+    }
+
+    while let Some((old, new)) = frontier.pop_front() {
+        if budget < 2 {
+            send(&mut resp_chan, Entry::BudgetExhausted).await?;
+            return Ok(());
+        }
+        budget -= 2;
+
+        // walk both sides at once
+        let (old, new) = futures::future::join(
+            get_and_cache(&store, &cache, old),
+            get_and_cache(&store, &cache, new),
+        )
+        .await;
+        let (old, new) = (old?, new?);
+
+        let old_links: HashMap<Id, Header> = old.links.iter().map(|h| (h.id, *h)).collect();
+        let new_ids: HashSet<Id> = new.links.iter().map(|h| h.id).collect();
+
+        for new_hdr in new.links.iter() {
+            match old_links.get(&new_hdr.id) {
+                None => send(&mut resp_chan, Entry::Added(*new_hdr)).await?,
+                Some(old_hdr) if old_hdr.hash != new_hdr.hash => {
+                    let entry = Entry::Changed {
+                        old: *old_hdr,
+                        new: *new_hdr,
+                    };
+                    send(&mut resp_chan, entry).await?;
+                    if visited.insert((old_hdr.hash, new_hdr.hash)) {
+                        frontier.push_back((old_hdr.hash, new_hdr.hash));
+                    }
+                }
+                Some(_) => {} // identical subtree
+            }
+        }
+
+        for old_hdr in old.links.iter() {
+            if !new_ids.contains(&old_hdr.id) {
+                send(&mut resp_chan, Entry::Removed(*old_hdr)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(
+    resp_chan: &mut mpsc::Sender<Result<Entry, DagCacheError>>,
+    entry: Entry,
+) -> Result<(), DagCacheError> {
+    resp_chan.send(Ok(entry)).await.map_err(|e| {
+        error!("failed sending resp via mpsc due to {:?}", e);
+        DagCacheError::UnexpectedError("diff receiver dropped".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, test_runtime};
+    use dag_store_types::types::domain::Node;
+    use dag_store_types::types::encodings::Base64;
+
+    async fn put(
+        store: &Arc<dyn HashedBlobStore>,
+        id: u128,
+        data: u8,
+        links: Vec<Header>,
+    ) -> Header {
+        let node = Node {
+            links,
+            data: Base64(vec![data]),
+        };
+        let hash = store.put(node).await.unwrap();
+        Header {
+            id: Id(id),
+            hash,
+            size: 1,
+        }
+    }
+
+    async fn collect(mut entries: mpsc::Receiver<Result<Entry, DagCacheError>>) -> Vec<Entry> {
+        let mut res = Vec::new();
+        while let Some(entry) = entries.recv().await {
+            res.push(entry.unwrap());
+        }
+        res
+    }
+
+    #[tokio::test]
+    async fn test_diff() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let store = &runtime.hashed_blob_store;
+
+        let unchanged = put(store, 1, 1, vec![]).await;
+        let removed = put(store, 2, 2, vec![]).await;
+        let leaf_old = put(store, 4, 4, vec![]).await;
+        let leaf_new = put(store, 4, 5, vec![]).await;
+        let added = put(store, 5, 6, vec![]).await;
+        let parent_old = put(store, 3, 3, vec![leaf_old]).await;
+        let parent_new = put(store, 3, 3, vec![leaf_new, added]).await;
+
+        let old = put(store, 0, 0, vec![unchanged, removed, parent_old]).await;
+        let new = put(store, 0, 0, vec![unchanged, parent_new]).await;
+
+        let req = Req {
+            old: old.hash,
+            new: new.hash,
+            node_budget: 0,
+        };
+        let entries = collect(diff(store, &runtime.cache, req)).await;
+        assert_eq!(
+            entries,
+            vec![
+                Entry::Changed {
+                    old: parent_old,
+                    new: parent_new
+                },
+                Entry::Removed(removed),
+                Entry::Changed {
+                    old: leaf_old,
+                    new: leaf_new
+                },
+                Entry::Added(added),
+            ]
+        );
+
+        // only enough budget for the root pair
+        let req = Req {
+            old: old.hash,
+            new: new.hash,
+            node_budget: 2,
+        };
+        let entries = collect(diff(store, &runtime.cache, req)).await;
+        assert_eq!(entries.last(), Some(&Entry::BudgetExhausted));
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shared_subtree_diffed_once() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let store = &runtime.hashed_blob_store;
+
+        let leaf_old = put(store, 0, 0, vec![]).await;
+        let leaf_new = put(store, 0, 1, vec![]).await;
+        let shared_old = put(store, 0, 2, vec![leaf_old]).await;
+        let shared_new = put(store, 0, 2, vec![leaf_new]).await;
+
+        // two distinct parents on each side, both linking to the same changed subtree
+        let old = put(
+            store,
+            0,
+            3,
+            vec![
+                put(store, 1, 4, vec![shared_old]).await,
+                put(store, 2, 5, vec![shared_old]).await,
+            ],
+        )
+        .await;
+        let new = put(
+            store,
+            0,
+            3,
+            vec![
+                put(store, 1, 4, vec![shared_new]).await,
+                put(store, 2, 5, vec![shared_new]).await,
+            ],
+        )
+        .await;
+
+        let req = Req {
+            old: old.hash,
+            new: new.hash,
+            node_budget: 0,
+        };
+        let entries = collect(diff(store, &runtime.cache, req)).await;
+        let leaf_changes = entries
+            .iter()
+            .filter(|e| {
+                **e == Entry::Changed {
+                    old: leaf_old,
+                    new: leaf_new,
+                }
+            })
+            .count();
+        assert_eq!(leaf_changes, 1);
+        // both parents and both links to the shared subtree changed
+        assert_eq!(entries.len(), 5);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::capabilities::MutableHashStore;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+pub mod proto {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+use proto::health_check_response::ServingStatus;
+use proto::health_server::Health;
+use proto::{HealthCheckRequest, HealthCheckResponse};
+
+/// name the dag store service is registered under, health may be checked for it or for the
+/// server as a whole (the empty string)
+pub const DAG_STORE_SERVICE_NAME: &str = "dagstore.DagStore";
+
+// key read to check the store is responsive, it doesn't need to exist
+const READINESS_PROBE_KEY: &str = "__readiness_probe";
+
+/// grpc health service. reports SERVING while the store can be read from and the server
+/// isn't shutting down
+pub struct HealthService {
+    mutable_hash_store: Arc<dyn MutableHashStore>,
+    serving: watch::Receiver<bool>,
+}
+
+impl HealthService {
+    /// send false on the returned sender once shutdown begins, dropping it ends health watches
+    pub fn new(mutable_hash_store: Arc<dyn MutableHashStore>) -> (watch::Sender<bool>, Self) {
+        let (send, serving) = watch::channel(true);
+        let service = HealthService {
+            mutable_hash_store,
+            serving,
+        };
+        (send, service)
+    }
+
+    async fn status(&self, serving: bool) -> ServingStatus {
+        status(&self.mutable_hash_store, serving).await
+    }
+}
+
+async fn status(mutable_hash_store: &Arc<dyn MutableHashStore>, serving: bool) -> ServingStatus {
+    if !serving {
+        return ServingStatus::NotServing;
+    }
+    match mutable_hash_store.get(READINESS_PROBE_KEY).await {
+        Ok(_) => ServingStatus::Serving,
+        Err(e) => {
+            error!("readiness check failed, store not readable: {:?}", e);
+            ServingStatus::NotServing
+        }
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+fn is_known_service(service: &str) -> bool {
+    service.is_empty() || service == DAG_STORE_SERVICE_NAME
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        if !is_known_service(&request.get_ref().service) {
+            return Err(Status::not_found("unknown service"));
+        }
+        let serving = *self.serving.borrow();
+        Ok(Response::new(response(self.status(serving).await)))
+    }
+
+    type WatchStream = mpsc::Receiver<Result<HealthCheckResponse, Status>>;
+
+    /// stream the current status and every change to it. the stream ends (after reporting
+    /// NOT_SERVING) when the server shuts down
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let (mut send, receive) = mpsc::channel(4);
+
+        if !is_known_service(&request.get_ref().service) {
+            // per the protocol, unknown services are reported rather than failing the call
+            let _ = send.try_send(Ok(response(ServingStatus::ServiceUnknown)));
+            return Ok(Response::new(receive));
+        }
+
+        let mutable_hash_store = self.mutable_hash_store.clone();
+        let mut serving = self.serving.clone();
+        tokio::spawn(async move {
+            let mut last_sent = None;
+            // the first recv yields the current value, later ones wait for a change
+            while let Some(is_serving) = serving.recv().await {
+                let status = status(&mutable_hash_store, is_serving).await;
+                if last_sent != Some(status) {
+                    if send.send(Ok(response(status))).await.is_err() {
+                        info!("health watch closed by client");
+                        return;
+                    }
+                    last_sent = Some(status);
+                }
+            }
+            if last_sent != Some(ServingStatus::NotServing) {
+                let _ = send.send(Ok(response(ServingStatus::NotServing))).await;
+            }
+        });
+
+        Ok(Response::new(receive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::health_client::HealthClient;
+    use super::*;
+    use crate::test_utils::{init_test_env, local_addr, test_runtime, wait_until_listening};
+    use dag_store_types::types::domain::Node;
+    use dag_store_types::types::encodings::Base64;
+    use dag_store_types::types::grpc::dag_store_client::DagStoreClient;
+    use futures::future::FutureExt;
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+    use tonic::Code;
+
+    fn check_req(service: &str) -> HealthCheckRequest {
+        HealthCheckRequest {
+            service: service.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_and_graceful_shutdown() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let addr = local_addr();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = stopped.map(|_| ());
+            crate::run_with_shutdown(runtime, addr, None, shutdown)
+                .await
+                .map_err(|e| e.to_string())
+        });
+        wait_until_listening(addr).await;
+
+        let url = format!("http://localhost:{}", addr.port());
+        let mut health = HealthClient::connect(url.clone()).await.unwrap();
+        for service in ["", DAG_STORE_SERVICE_NAME].iter() {
+            let resp = health.check(check_req(service)).await.unwrap();
+            assert_eq!(resp.into_inner().status, ServingStatus::Serving as i32);
+        }
+        let unknown = health.check(check_req("nope")).await.unwrap_err();
+        assert_eq!(unknown.code(), Code::NotFound);
+
+        let mut watch = health.watch(check_req("")).await.unwrap().into_inner();
+        let first = watch.next().await.unwrap().unwrap();
+        assert_eq!(first.status, ServingStatus::Serving as i32);
+
+        let mut client = DagStoreClient::connect(url).await.unwrap();
+        let node = Node {
+            links: vec![],
+            data: Base64(vec![1, 2, 3]),
+        };
+        client.put_node(node.into_proto()).await.unwrap();
+
+        // watchers see the server stop serving, then the stream ends
+        stop.send(()).unwrap();
+        let last = watch.next().await.unwrap().unwrap();
+        assert_eq!(last.status, ServingStatus::NotServing as i32);
+        assert!(watch.next().await.is_none());
+
+        // the server drains and flushes well within the drain timeout
+        let res = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server didn't shut down")
+            .unwrap();
+        assert_eq!(res, Ok(()));
+    }
+}
@@ -0,0 +1,270 @@
+use dag_store_types::types::errors::DagCacheError;
+use futures::task::{Context, Poll};
+use futures::Stream;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// suggested delay before retrying a request rejected for exceeding the concurrency limit
+const CONCURRENCY_RETRY_AFTER: Duration = Duration::from_millis(100);
+
+// client state is pruned once this many clients are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// per-request and per-client limits, all disabled by default
+#[derive(Clone, Debug, Default)]
+pub struct LimitsConfig {
+    /// max nodes in a single bulk put
+    pub max_bulk_put_nodes: Option<u64>,
+    /// max bytes in a single bulk put, counted as in the cache (data plus link headers)
+    pub max_bulk_put_bytes: Option<u64>,
+    /// max encoded size of a single request message
+    pub max_message_bytes: Option<usize>,
+    /// max requests each client may have in flight at once
+    pub max_concurrent_requests: Option<usize>,
+    /// requests per second each client may make, averaged over rate_limit_burst requests
+    pub rate_limit: Option<f64>,
+    pub rate_limit_burst: u32,
+}
+
+struct ClientState {
+    in_flight: usize,
+    // token bucket, refilled at config.rate_limit per second up to config.rate_limit_burst
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+pub struct Limits {
+    config: LimitsConfig,
+    clients: Mutex<HashMap<String, ClientState>>,
+}
+
+/// counts against a client's concurrent requests until dropped
+pub struct Permit {
+    limits: Arc<Limits>,
+    client: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut clients = self.limits.clients.lock().unwrap();
+        if let Some(state) = clients.get_mut(&self.client) {
+            state.in_flight -= 1;
+        }
+    }
+}
+
+/// a response stream holding its request's permit, so streaming requests count against the
+/// client's concurrency limit until the stream is finished or dropped
+pub struct Permitted<S> {
+    stream: S,
+    _permit: Permit,
+}
+
+impl<S> Permitted<S> {
+    pub fn new(stream: S, permit: Permit) -> Self {
+        Permitted {
+            stream,
+            _permit: permit,
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for Permitted<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+impl Limits {
+    pub fn new(config: LimitsConfig) -> Self {
+        Limits {
+            config,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Limits::new(LimitsConfig::default())
+    }
+
+    pub fn check_bulk_put(&self, nodes: u64, bytes: u64) -> Result<(), DagCacheError> {
+        match (
+            self.config.max_bulk_put_nodes,
+            self.config.max_bulk_put_bytes,
+        ) {
+            (Some(max), _) if nodes > max => Err(DagCacheError::LimitExceeded(format!(
+                "bulk put of {} nodes exceeds limit of {}",
+                nodes, max
+            ))),
+            (_, Some(max)) if bytes > max => Err(DagCacheError::LimitExceeded(format!(
+                "bulk put of {} bytes exceeds limit of {}",
+                bytes, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// NOTE: tonic has no way to cap message size before decoding, so this runs on the decoded
+    /// message. it stops oversized requests from doing any work, but not from being buffered
+    pub fn check_message_size<M: prost::Message>(&self, message: &M) -> Result<(), DagCacheError> {
+        match self.config.max_message_bytes {
+            Some(max) if message.encoded_len() > max => Err(DagCacheError::LimitExceeded(format!(
+                "message of {} bytes exceeds limit of {}",
+                message.encoded_len(),
+                max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// take a token from the client's bucket and count a request against its concurrency
+    /// limit, failing with a retry hint if either is exhausted
+    pub fn admit(self: &Arc<Self>, client: String) -> Result<Permit, DagCacheError> {
+        let now = Instant::now();
+        let burst = f64::from(self.config.rate_limit_burst.max(1));
+
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            self.prune(&mut clients, now);
+        }
+        let state = clients.entry(client.clone()).or_insert(ClientState {
+            in_flight: 0,
+            tokens: burst,
+            refilled_at: now,
+        });
+
+        if let Some(max) = self.config.max_concurrent_requests {
+            if state.in_flight >= max {
+                return Err(DagCacheError::RateLimited {
+                    reason: format!("client has {} requests in flight", state.in_flight),
+                    retry_after_millis: CONCURRENCY_RETRY_AFTER.as_millis() as u64,
+                });
+            }
+        }
+
+        if let Some(rate) = self.config.rate_limit {
+            let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(burst);
+            state.refilled_at = now;
+            if state.tokens < 1.0 {
+                let wait = Duration::from_secs_f64((1.0 - state.tokens) / rate);
+                return Err(DagCacheError::RateLimited {
+                    reason: format!("client exceeded {} requests per second", rate),
+                    // round up so retrying after the hint always succeeds
+                    retry_after_millis: wait.as_millis() as u64 + 1,
+                });
+            }
+            state.tokens -= 1.0;
+        }
+
+        state.in_flight += 1;
+        Ok(Permit {
+            limits: self.clone(),
+            client,
+        })
+    }
+
+    // forget clients with no requests in flight whose buckets have refilled
+    fn prune(&self, clients: &mut HashMap<String, ClientState>, now: Instant) {
+        let burst = f64::from(self.config.rate_limit_burst.max(1));
+        let rate = self.config.rate_limit.unwrap_or(f64::INFINITY);
+        clients.retain(|_, state| {
+            let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+            state.in_flight > 0 || state.tokens + elapsed * rate < burst
+        });
+    }
+}
+
+/// identifies the client a request is counted against: the token it was authenticated with
+/// (see `auth::verified_client`), otherwise its address. unverified credentials are ignored,
+/// as clients could otherwise send a new one with each request to escape their limits
+pub fn client_id(authenticated: Option<String>, remote_addr: Option<SocketAddr>) -> String {
+    match (authenticated, remote_addr) {
+        (Some(client), _) => format!("token:{}", client),
+        (None, Some(addr)) => format!("addr:{}", addr.ip()),
+        (None, None) => "anonymous".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dag_store_types::types::errors::retry_after;
+    use tonic::{Code, Status};
+
+    #[test]
+    fn test_admit() {
+        let limits = Arc::new(Limits::new(LimitsConfig {
+            max_concurrent_requests: Some(2),
+            rate_limit: Some(1.0),
+            rate_limit_burst: 3,
+            ..LimitsConfig::default()
+        }));
+        let alice = || "alice".to_string();
+
+        let first = limits.admit(alice()).unwrap();
+        let second = limits.admit(alice()).unwrap();
+        match limits.admit(alice()) {
+            Err(DagCacheError::RateLimited { .. }) => {}
+            _ => panic!("expected concurrency limit to be enforced"),
+        }
+
+        // completed requests free up concurrency, but only one token is left in the bucket
+        drop(first);
+        drop(second);
+        limits.admit(alice()).unwrap();
+        let status = Status::from(limits.admit(alice()).err().unwrap());
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        let wait = retry_after(&status).expect("expected a retry hint");
+        assert!(wait > Duration::from_millis(0) && wait <= Duration::from_millis(1001));
+
+        // other clients have their own buckets
+        limits.admit("bob".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_stream_holds_permit() {
+        let limits = Arc::new(Limits::new(LimitsConfig {
+            max_concurrent_requests: Some(1),
+            ..LimitsConfig::default()
+        }));
+        let alice = || "alice".to_string();
+
+        // the permit is released when the stream is, not when the handler returns
+        let stream = Permitted::new(
+            futures::stream::empty::<()>(),
+            limits.admit(alice()).unwrap(),
+        );
+        assert!(limits.admit(alice()).is_err());
+        drop(stream);
+        limits.admit(alice()).unwrap();
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let limits = Limits::new(LimitsConfig {
+            max_bulk_put_nodes: Some(10),
+            max_bulk_put_bytes: Some(1000),
+            max_message_bytes: Some(8),
+            ..LimitsConfig::default()
+        });
+
+        limits.check_bulk_put(10, 1000).unwrap();
+        assert!(limits.check_bulk_put(11, 0).is_err());
+        assert!(limits.check_bulk_put(1, 1001).is_err());
+
+        let small = dag_store_types::types::grpc::ListKeysReq {
+            prefix: "a".to_string(),
+        };
+        let large = dag_store_types::types::grpc::ListKeysReq {
+            prefix: "a".repeat(100),
+        };
+        limits.check_message_size(&small).unwrap();
+        assert!(limits.check_message_size(&large).is_err());
+    }
+}
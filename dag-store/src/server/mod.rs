@@ -1,4 +1,12 @@
 pub mod app;
+pub mod archive;
+pub mod auth;
 pub mod batch_get;
 pub mod batch_put;
+pub mod diff;
+pub mod health;
+pub mod limits;
 pub mod opportunistic_get;
+pub mod prefetch;
+pub mod resolve;
+pub mod watch_key;
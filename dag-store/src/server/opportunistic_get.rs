@@ -23,7 +23,7 @@ pub async fn get<'a>(
 }
 
 // TODO: figure out traversal termination strategy - don't want to return whole cache in one resp
-fn extend<'a>(cache: &'a Arc<Cache>, node: Node) -> api::get::Resp {
+fn extend<'a>(cache: &'a Arc<Cache>, node: Arc<Node>) -> api::get::Resp {
     let mut frontier = VecDeque::new();
     let mut res = Vec::new();
 
@@ -34,9 +34,9 @@ fn extend<'a>(cache: &'a Arc<Cache>, node: Node) -> api::get::Resp {
 
     // explore the frontier of potentially cached hash pointers
     while let Some(hp) = frontier.pop_front() {
-        // if a hash pointer is in the cache, grab the associated node and continue traversal
-        if let Some(dn) = cache.get(hp.hash.clone()) {
-            // clone :(
+        // if a hash pointer is in the cache, grab the associated node and continue traversal.
+        // peek, as these aren't requests for the node and shouldn't count as hits or misses
+        if let Some(dn) = cache.peek(hp.hash.clone()) {
             for hp in dn.links.iter() {
                 // iter over ref
                 frontier.push_back(hp.clone());
@@ -45,15 +45,16 @@ fn extend<'a>(cache: &'a Arc<Cache>, node: Node) -> api::get::Resp {
                 "add node with hash {:?} to opportunistic get result",
                 hp.clone()
             );
+            // only cloned once, when building the response
             res.push(NodeWithHeader {
                 header: hp,
-                node: dn,
+                node: (*dn).clone(),
             });
         }
     }
 
     api::get::Resp {
-        requested_node: node,
+        requested_node: (*node).clone(),
         extra_node_count: res.len() as u64,
         extra_nodes: res,
     }
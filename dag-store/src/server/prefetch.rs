@@ -0,0 +1,114 @@
+use crate::capabilities::get_and_cache;
+use crate::capabilities::{Cache, HashedBlobStore};
+use dag_store_types::types::domain::Header;
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+
+/// how far to speculatively load the subtree below a requested node into the cache
+#[derive(Clone, Copy, Debug)]
+pub struct PrefetchConfig {
+    /// levels below the requested node to load (1 = direct links only)
+    pub depth: u32,
+    /// max links followed per node, in link order
+    pub fan_out: usize,
+}
+
+/// prefetch in the background, so the response for the requested node isn't delayed
+pub fn spawn(
+    store: &Arc<dyn HashedBlobStore>,
+    cache: &Arc<Cache>,
+    links: Vec<Header>,
+    config: PrefetchConfig,
+) {
+    let store = store.clone();
+    let cache = cache.clone();
+    tokio::spawn(async move { prefetch(&store, &cache, links, config).await });
+}
+
+// breadth-first, so the next level the client expands is loaded first
+#[instrument(skip(store, cache, links))]
+pub async fn prefetch(
+    store: &Arc<dyn HashedBlobStore>,
+    cache: &Arc<Cache>,
+    links: Vec<Header>,
+    config: PrefetchConfig,
+) {
+    let mut frontier: Vec<Header> = links.into_iter().take(config.fan_out).collect();
+    let mut loaded = 0;
+
+    for _ in 0..config.depth {
+        let mut next = Vec::new();
+        for hp in frontier.into_iter() {
+            let node = match cache.peek(hp.hash) {
+                Some(node) => node,
+                None => match get_and_cache(store, cache, hp.hash).await {
+                    Ok(node) => {
+                        loaded += 1;
+                        node
+                    }
+                    Err(e) => {
+                        // best-effort, a failed prefetch is just a future cache miss
+                        error!("prefetch of {:?} failed: {:?}", hp.hash, e);
+                        continue;
+                    }
+                },
+            };
+            next.extend(node.links.iter().take(config.fan_out).cloned());
+        }
+        frontier = next;
+    }
+
+    info!("prefetched {} nodes", loaded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, test_runtime};
+    use dag_store_types::types::domain::{Hash, Id, Node};
+    use dag_store_types::types::encodings::Base64;
+
+    async fn put(store: &Arc<dyn HashedBlobStore>, data: u8, links: Vec<Hash>) -> Header {
+        let node = Node {
+            links: links
+                .into_iter()
+                .enumerate()
+                .map(|(i, hash)| Header {
+                    id: Id(i as u128),
+                    hash,
+                    size: 0,
+                })
+                .collect(),
+            data: Base64(vec![data]),
+        };
+        let hash = store.put(node).await.unwrap();
+        Header {
+            id: Id(0),
+            hash,
+            size: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_depth_and_fan_out() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let store = &runtime.hashed_blob_store;
+
+        let grandchild = put(store, 0, vec![]).await;
+        let a = put(store, 1, vec![grandchild.hash]).await;
+        let b = put(store, 2, vec![]).await;
+
+        let config = PrefetchConfig {
+            depth: 1,
+            fan_out: 1,
+        };
+        prefetch(store, &runtime.cache, vec![a, b], config).await;
+
+        assert!(runtime.cache.contains(a.hash));
+        // beyond fan-out
+        assert!(!runtime.cache.contains(b.hash));
+        // beyond depth
+        assert!(!runtime.cache.contains(grandchild.hash));
+    }
+}
@@ -0,0 +1,47 @@
+use crate::capabilities::get_and_cache;
+use crate::capabilities::{Cache, HashedBlobStore, MutableHashStore};
+use dag_store_types::types::api::resolve::{Req, Resp, Root, Segment};
+use dag_store_types::types::errors::DagCacheError;
+use std::sync::Arc;
+use tracing::instrument;
+
+/// follow each path segment from the root in turn, via the cache
+#[instrument(skip(store, cache, mhs))]
+pub async fn resolve(
+    store: &Arc<dyn HashedBlobStore>,
+    cache: &Arc<Cache>,
+    mhs: &Arc<dyn MutableHashStore>,
+    req: Req,
+) -> Result<Resp, DagCacheError> {
+    let root = match req.root {
+        Root::Hash(h) => h,
+        Root::Key(k) => mhs
+            .get(&k)
+            .await?
+            .ok_or_else(|| DagCacheError::NotFound(format!("no hash for key {}", k)))?,
+    };
+
+    let mut node = get_and_cache(store, cache, root).await?;
+    let mut path = Vec::with_capacity(req.path.len());
+
+    for (depth, segment) in req.path.into_iter().enumerate() {
+        let header = match segment {
+            Segment::Index(i) => node.links.get(i as usize),
+            Segment::Id(id) => node.links.iter().find(|h| h.id == id),
+        };
+        let header = *header.ok_or_else(|| {
+            DagCacheError::NotFound(format!(
+                "no link matching {:?} at depth {} of path",
+                segment, depth
+            ))
+        })?;
+
+        node = get_and_cache(store, cache, header.hash).await?;
+        path.push(header);
+    }
+
+    Ok(Resp {
+        node: (*node).clone(),
+        path,
+    })
+}
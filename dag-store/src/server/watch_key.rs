@@ -0,0 +1,67 @@
+use crate::capabilities::MutableHashStore;
+use dag_store_types::types::domain::Hash;
+use dag_store_types::types::errors::DagCacheError;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info};
+
+/// stream the hash associated with a key, starting with its current value (if any)
+/// and then emitting every subsequent change
+pub async fn watch_key<'a>(
+    mhs: &'a Arc<dyn MutableHashStore>,
+    updates: &'a broadcast::Sender<(String, Hash)>,
+    key: String,
+) -> Result<mpsc::Receiver<Result<Hash, DagCacheError>>, DagCacheError> {
+    // subscribe before reading the current value so no update can be missed in between
+    let updates = updates.subscribe();
+    let current = mhs.get(&key).await?;
+
+    let (send, receive) = mpsc::channel(16);
+    let mhs = mhs.clone();
+    tokio::spawn(async move { watch_key_worker(mhs, key, current, updates, send).await });
+
+    Ok(receive)
+}
+
+async fn watch_key_worker(
+    mhs: Arc<dyn MutableHashStore>,
+    key: String,
+    current: Option<Hash>,
+    mut updates: broadcast::Receiver<(String, Hash)>,
+    mut resp_chan: mpsc::Sender<Result<Hash, DagCacheError>>,
+) {
+    let mut last_sent = None;
+    let mut next = current;
+
+    loop {
+        if let Some(hash) = next.take() {
+            if last_sent != Some(hash) {
+                if let Err(e) = resp_chan.send(Ok(hash)).await {
+                    // client went away
+                    info!("watch on key {} closed: {:?}", &key, e);
+                    return;
+                }
+                last_sent = Some(hash);
+            }
+        }
+
+        next = match updates.recv().await {
+            Ok((k, hash)) if k == key => Some(hash),
+            Ok(_) => None,
+            Err(broadcast::RecvError::Lagged(n)) => {
+                // only the latest value matters, re-read it instead of replaying missed updates
+                info!("watch on key {} lagged by {} updates", &key, n);
+                match mhs.get(&key).await {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        if let Err(e) = resp_chan.send(Err(e)).await {
+                            error!("failed sending resp via mpsc due to {:?}", e);
+                        }
+                        return;
+                    }
+                }
+            }
+            Err(broadcast::RecvError::Closed) => return,
+        };
+    }
+}
@@ -0,0 +1,117 @@
+use crate::telemetry::{Fields, SpanId, TraceId};
+use libhoney::{json, FieldHolder, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing_distributed::{Event, Span};
+
+// field names honeycomb gives special meaning, user fields with these names are prefixed
+const RESERVED_FIELDS: [&str; 9] = [
+    "trace.span_id",
+    "trace.trace_id",
+    "trace.parent_id",
+    "service_name",
+    "level",
+    "Timestamp",
+    "name",
+    "target",
+    "duration_ms",
+];
+
+/// publishes spans and events to honeycomb.io
+pub struct HoneycombExporter {
+    // publishing requires &mut, so just mutex-wrap it
+    client: Mutex<libhoney::Client<libhoney::transmission::Transmission>>,
+}
+
+impl HoneycombExporter {
+    pub fn new(api_key: String, dataset: String) -> Self {
+        let config = libhoney::Config {
+            options: libhoney::client::Options {
+                api_key,
+                dataset,
+                ..libhoney::client::Options::default()
+            },
+            transmission_options: libhoney::transmission::Options {
+                max_batch_size: 1,
+                ..libhoney::transmission::Options::default()
+            },
+        };
+        HoneycombExporter {
+            client: Mutex::new(libhoney::init(config)),
+        }
+    }
+
+    pub fn report_span(&self, span: Span<Fields, SpanId, TraceId>) {
+        let mut values = to_values(span.values);
+        values.insert("trace.span_id".to_string(), json!(span.id.to_string()));
+        values.insert(
+            "trace.trace_id".to_string(),
+            json!(span.trace_id.to_string()),
+        );
+        values.insert(
+            "trace.parent_id".to_string(),
+            json!(span.parent_id.map(|id| id.to_string())),
+        );
+        values.insert("service_name".to_string(), json!(span.service_name));
+        values.insert("level".to_string(), json!(span.meta.level().to_string()));
+        values.insert(
+            "Timestamp".to_string(),
+            json!(span.initialized_at.to_rfc3339()),
+        );
+        values.insert("name".to_string(), json!(span.meta.name()));
+        values.insert("target".to_string(), json!(span.meta.target()));
+        values.insert(
+            "duration_ms".to_string(),
+            json!(span.elapsed.num_milliseconds()),
+        );
+        self.send(values);
+    }
+
+    pub fn report_event(&self, event: Event<Fields, SpanId, TraceId>) {
+        let mut values = to_values(event.values);
+        values.insert(
+            "trace.trace_id".to_string(),
+            json!(event.trace_id.to_string()),
+        );
+        values.insert(
+            "trace.parent_id".to_string(),
+            json!(event.parent_id.map(|id| id.to_string())),
+        );
+        values.insert("service_name".to_string(), json!(event.service_name));
+        values.insert("level".to_string(), json!(event.meta.level().to_string()));
+        values.insert(
+            "Timestamp".to_string(),
+            json!(event.initialized_at.to_rfc3339()),
+        );
+        values.insert("name".to_string(), json!(event.meta.name()));
+        values.insert("target".to_string(), json!(event.meta.target()));
+        // honeycomb treats events as zero-duration spans
+        values.insert("duration_ms".to_string(), json!(0));
+        self.send(values);
+    }
+
+    fn send(&self, values: HashMap<String, Value>) {
+        // failure is unrecoverable (mutex poisoned)
+        let mut client = self.client.lock().unwrap();
+        let mut event = client.new_event();
+        event.add(values);
+        if let Err(err) = event.send(&mut client) {
+            // can't report telemetry (buffer full), so log to stderr
+            eprintln!("error sending event to honeycomb, {:?}", err);
+        }
+    }
+}
+
+fn to_values(fields: Fields) -> HashMap<String, Value> {
+    fields
+        .0
+        .into_iter()
+        .map(|(name, value)| {
+            if RESERVED_FIELDS.contains(&name.as_str()) {
+                (format!("tracing.{}", name), value)
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
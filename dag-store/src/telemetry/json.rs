@@ -0,0 +1,42 @@
+use crate::telemetry::{Fields, SpanId, TraceId};
+use serde_json::{json, Value};
+use std::io::Write;
+use tracing_distributed::{Event, Span};
+
+pub fn span_to_json(span: Span<Fields, SpanId, TraceId>) -> Value {
+    json!({
+        "kind": "span",
+        "timestamp": span.initialized_at.to_rfc3339(),
+        "service_name": span.service_name,
+        "name": span.meta.name(),
+        "target": span.meta.target(),
+        "level": span.meta.level().to_string(),
+        "trace_id": span.trace_id.to_string(),
+        "span_id": span.id.to_string(),
+        "parent_id": span.parent_id.map(|id| id.to_string()),
+        "duration_ms": span.elapsed.num_milliseconds(),
+        "fields": span.values.0,
+    })
+}
+
+pub fn event_to_json(event: Event<Fields, SpanId, TraceId>) -> Value {
+    json!({
+        "kind": "event",
+        "timestamp": event.initialized_at.to_rfc3339(),
+        "service_name": event.service_name,
+        "name": event.meta.name(),
+        "target": event.meta.target(),
+        "level": event.meta.level().to_string(),
+        "trace_id": event.trace_id.to_string(),
+        "parent_id": event.parent_id.map(|id| id.to_string()),
+        "fields": event.values.0,
+    })
+}
+
+/// write value to stdout as a single line
+pub fn print(value: Value) {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    // nowhere to report a failed write to, telemetry is best effort
+    let _ = writeln!(stdout, "{}", value);
+}
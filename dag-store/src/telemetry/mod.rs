@@ -0,0 +1,332 @@
+// distributed tracing: span and trace ids, w3c traceparent propagation and a choice of backends
+// to publish spans and events to (see TelemetryConfig)
+pub mod honeycomb;
+pub mod json;
+pub mod otlp;
+
+use crate::config::{self, ConfigError};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use tracing::field::{Field, Visit};
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer, TraceCtxError};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry;
+
+/// grpc metadata field (and http header) carrying the w3c trace context of the calling span
+pub const TRACEPARENT_META_FIELD: &str = "traceparent";
+
+/// default endpoint of a local opentelemetry collector's otlp grpc receiver
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+
+/// identifies a distributed trace, 16 bytes as in the w3c trace context spec
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct TraceId(pub u128);
+
+/// identifies a span within a trace, 8 bytes as in the w3c trace context spec
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct SpanId(pub u64);
+
+impl TraceId {
+    pub fn generate() -> Self {
+        TraceId(rand::thread_rng().gen())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// w3c trace context (https://www.w3.org/TR/trace-context/), identifies the remote span a request
+/// was made from. every span is recorded, so the sampled flag is always set and ignored on parse
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TraceParent {
+    pub trace_id: TraceId,
+    pub parent_id: SpanId,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ParseTraceParentError(&'static str);
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "00-{}-{}-01", self.trace_id, self.parent_id)
+    }
+}
+
+impl FromStr for TraceParent {
+    type Err = ParseTraceParentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+        if parts.len() < 4 {
+            return Err(ParseTraceParentError(
+                "expected version-traceid-parentid-flags",
+            ));
+        }
+        let (version, trace_id, parent_id, flags) = (parts[0], parts[1], parts[2], parts[3]);
+
+        // later versions may append fields, version 00 may not
+        if !is_lower_hex(version, 2) || version == "ff" || (version == "00" && parts.len() > 4) {
+            return Err(ParseTraceParentError("unsupported version"));
+        }
+        if !is_lower_hex(flags, 2) {
+            return Err(ParseTraceParentError("invalid trace flags"));
+        }
+
+        // all zero ids are reserved as invalid
+        let trace_id = match u128::from_str_radix(trace_id, 16) {
+            Ok(id) if is_lower_hex(trace_id, 32) && id != 0 => TraceId(id),
+            _ => return Err(ParseTraceParentError("invalid trace id")),
+        };
+        let parent_id = match u64::from_str_radix(parent_id, 16) {
+            Ok(id) if is_lower_hex(parent_id, 16) && id != 0 => SpanId(id),
+            _ => return Err(ParseTraceParentError("invalid parent id")),
+        };
+
+        Ok(TraceParent {
+            trace_id,
+            parent_id,
+        })
+    }
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len
+        && s.chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+/// register the current span as the local root of a distributed trace
+pub fn register_dist_tracing_root(
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+) -> Result<(), TraceCtxError> {
+    tracing_distributed::register_dist_tracing_root(trace_id, remote_parent_span)
+}
+
+/// trace context to propagate to requests made from the current span
+pub fn current_trace_parent() -> Result<TraceParent, TraceCtxError> {
+    let (trace_id, parent_id) = tracing_distributed::current_dist_trace_ctx()?;
+    Ok(TraceParent {
+        trace_id,
+        parent_id,
+    })
+}
+
+/// where spans and events are published
+#[derive(Clone, Debug)]
+pub enum TelemetryConfig {
+    /// only log to stdout
+    None,
+    /// one json object per line on stdout, logs move to stderr
+    Stdout,
+    Honeycomb {
+        api_key: String,
+        dataset: String,
+    },
+    /// otlp over grpc, usually to a local opentelemetry collector
+    Otlp {
+        endpoint: String,
+    },
+}
+
+impl TelemetryConfig {
+    /// backend is one of none, stdout, honeycomb or otlp. if unset, honeycomb is used if a key
+    /// file is provided (as before backends were pluggable), otherwise none
+    pub fn from_opts(
+        backend: Option<String>,
+        honeycomb_key_file: Option<String>,
+        otlp_endpoint: Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let backend = backend.unwrap_or_else(|| match honeycomb_key_file {
+            Some(_) => "honeycomb".to_string(),
+            None => "none".to_string(),
+        });
+
+        match backend.as_str() {
+            "none" => Ok(TelemetryConfig::None),
+            "stdout" => Ok(TelemetryConfig::Stdout),
+            "honeycomb" => {
+                let path = honeycomb_key_file.ok_or_else(|| {
+                    ConfigError::invalid("telemetry", "honeycomb requires honeycomb_key_file")
+                })?;
+                let api_key = config::read_file(&path)?;
+                Ok(TelemetryConfig::Honeycomb {
+                    api_key: api_key.trim().to_string(),
+                    dataset: "dag-cache".to_string(), // TODO: better name for this
+                })
+            }
+            "otlp" => Ok(TelemetryConfig::Otlp {
+                endpoint: otlp_endpoint.unwrap_or_else(|| DEFAULT_OTLP_ENDPOINT.to_string()),
+            }),
+            other => Err(ConfigError::invalid(
+                "telemetry",
+                format!("{} is not one of none, stdout, honeycomb or otlp", other),
+            )),
+        }
+    }
+}
+
+/// field values recorded on a span or event
+#[derive(Default, Debug)]
+pub struct Fields(pub BTreeMap<String, Value>);
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+/// publishes spans and events to the configured backend
+pub enum Exporter {
+    Blackhole,
+    Stdout,
+    Honeycomb(Box<honeycomb::HoneycombExporter>),
+    Otlp(otlp::OtlpExporter),
+}
+
+impl Telemetry for Exporter {
+    type Visitor = Fields;
+    type TraceId = TraceId;
+    type SpanId = SpanId;
+
+    fn report_span(&self, span: Span<Fields, SpanId, TraceId>) {
+        match self {
+            Exporter::Blackhole => {}
+            Exporter::Stdout => json::print(json::span_to_json(span)),
+            Exporter::Honeycomb(honeycomb) => honeycomb.report_span(span),
+            Exporter::Otlp(otlp) => otlp.report_span(span),
+        }
+    }
+
+    fn report_event(&self, event: Event<Fields, SpanId, TraceId>) {
+        match self {
+            Exporter::Blackhole => {}
+            Exporter::Stdout => json::print(json::event_to_json(event)),
+            Exporter::Honeycomb(honeycomb) => honeycomb.report_event(event),
+            Exporter::Otlp(otlp) => otlp.report_event(event),
+        }
+    }
+}
+
+/// tracing layer publishing to the configured backend. the otlp exporter runs on the current
+/// tokio runtime, so this must be called from within one if it's used
+pub fn layer(
+    service_name: &'static str,
+    config: TelemetryConfig,
+) -> TelemetryLayer<Exporter, SpanId, TraceId> {
+    let exporter = match config {
+        TelemetryConfig::None => Exporter::Blackhole,
+        TelemetryConfig::Stdout => Exporter::Stdout,
+        TelemetryConfig::Honeycomb { api_key, dataset } => Exporter::Honeycomb(Box::new(
+            honeycomb::HoneycombExporter::new(api_key, dataset),
+        )),
+        TelemetryConfig::Otlp { endpoint } => {
+            Exporter::Otlp(otlp::OtlpExporter::spawn(service_name, endpoint))
+        }
+    };
+
+    // tracing ids are only unique within a process, mixing in a random instance id makes
+    // collisions between processes unlikely while keeping them unique within this one
+    let instance_id: u64 = rand::thread_rng().gen();
+    TelemetryLayer::new(service_name, exporter, move |tracing_id| {
+        SpanId(tracing_id.into_u64() ^ instance_id)
+    })
+}
+
+fn stdout_writer() -> Box<dyn Write> {
+    Box::new(io::stdout())
+}
+
+fn stderr_writer() -> Box<dyn Write> {
+    Box::new(io::stderr())
+}
+
+/// install a global subscriber publishing to the configured backend and logging to stdout
+/// (or stderr, if stdout is used for telemetry)
+pub fn init(service_name: &'static str, config: TelemetryConfig) {
+    let log_writer = match config {
+        TelemetryConfig::Stdout => stderr_writer,
+        _ => stdout_writer,
+    };
+
+    let subscriber = layer(service_name, config) // publish to tracing
+        .and_then(
+            tracing_subscriber::fmt::Layer::builder()
+                .with_writer(log_writer as fn() -> Box<dyn Write>)
+                .finish(),
+        ) // log to stdout
+        .and_then(LevelFilter::INFO) // omit low-level debug tracing (eg tokio executor)
+        .with_subscriber(registry::Registry::default()); // provide underlying span data store
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting global default failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let s = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let parsed = TraceParent::from_str(s).unwrap();
+        assert_eq!(parsed.trace_id, TraceId(0x4bf92f3577b34da6a3ce929d0e0e4736));
+        assert_eq!(parsed.parent_id, SpanId(0x00f067aa0ba902b7));
+        assert_eq!(parsed.to_string(), s);
+
+        // unsampled and future versions (with extra fields) are accepted
+        assert!(
+            TraceParent::from_str("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00")
+                .is_ok()
+        );
+        assert!(
+            TraceParent::from_str("cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x")
+                .is_ok()
+        );
+
+        let invalid = [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-+bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ];
+        for s in invalid.iter() {
+            assert!(TraceParent::from_str(s).is_err(), "accepted {}", s);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use crate::metrics;
+use crate::telemetry::{Fields, SpanId, TraceId};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing_distributed::{Event, Span};
+
+pub mod proto {
+    tonic::include_proto!("opentelemetry.proto.collector.trace.v1");
+}
+
+use proto::any_value;
+use proto::span::SpanKind;
+use proto::trace_service_client::TraceServiceClient;
+use proto::{
+    AnyValue, ExportTraceServiceRequest, InstrumentationScope, KeyValue, Resource, ResourceSpans,
+    ScopeSpans,
+};
+
+// spans queued for export, further spans are dropped until the exporter catches up
+const QUEUE_SIZE: usize = 4096;
+
+// spans are exported in batches of up to this many, at most once per interval
+const MAX_BATCH_SIZE: usize = 512;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// events are exported as part of their span, so are held until it closes. if more spans than
+// this have pending events, new events are dropped
+const MAX_SPANS_WITH_PENDING_EVENTS: usize = 10_000;
+
+/// exports spans to an opentelemetry collector via otlp over grpc. events are attached to the
+/// span they occurred in
+pub struct OtlpExporter {
+    queue: Mutex<mpsc::Sender<proto::Span>>,
+    pending_events: Mutex<HashMap<SpanId, Vec<proto::span::Event>>>,
+    // set while the queue is full, so only the first dropped span of each run is logged
+    dropping: AtomicBool,
+}
+
+impl OtlpExporter {
+    /// spawns the export loop on the current tokio runtime
+    pub fn spawn(service_name: &'static str, endpoint: String) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(export_loop(service_name, endpoint, receiver));
+        OtlpExporter {
+            queue: Mutex::new(sender),
+            pending_events: Mutex::new(HashMap::new()),
+            dropping: AtomicBool::new(false),
+        }
+    }
+
+    pub fn report_span(&self, span: Span<Fields, SpanId, TraceId>) {
+        let events = self
+            .pending_events
+            .lock()
+            .unwrap()
+            .remove(&span.id)
+            .unwrap_or_default();
+
+        let start = span.initialized_at.timestamp_nanos() as u64;
+        let elapsed = span.elapsed.num_nanoseconds().unwrap_or(0).max(0) as u64;
+
+        let mut attributes = to_attributes(span.values);
+        attributes.push(key_value(
+            "code.namespace",
+            string_value(span.meta.target()),
+        ));
+        attributes.push(key_value(
+            "level",
+            string_value(span.meta.level().to_string()),
+        ));
+
+        let span = proto::Span {
+            trace_id: span.trace_id.0.to_be_bytes().to_vec(),
+            span_id: span.id.0.to_be_bytes().to_vec(),
+            parent_span_id: span
+                .parent_id
+                .map(|id| id.0.to_be_bytes().to_vec())
+                .unwrap_or_default(),
+            name: span.meta.name().to_string(),
+            kind: SpanKind::Internal as i32,
+            start_time_unix_nano: start,
+            end_time_unix_nano: start + elapsed,
+            attributes,
+            events,
+        };
+
+        // a full queue means the collector is slow or unreachable, drop rather than block.
+        // drops are counted in dag_store_otlp_dropped_spans_total
+        if self.queue.lock().unwrap().try_send(span).is_err() {
+            metrics::OTLP_DROPPED_SPANS.inc();
+            if !self.dropping.swap(true, Ordering::Relaxed) {
+                eprintln!("otlp export queue full, dropping spans until the exporter catches up");
+            }
+        } else {
+            self.dropping.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn report_event(&self, event: Event<Fields, SpanId, TraceId>) {
+        let parent_id = match event.parent_id {
+            Some(parent_id) => parent_id,
+            None => return, // events outside of spans have nowhere to go
+        };
+
+        let mut fields = event.values;
+        // tracing records the message as a field, otlp has a name for it
+        let name = match fields.0.remove("message") {
+            Some(Value::String(message)) => message,
+            Some(message) => message.to_string(),
+            None => event.meta.name().to_string(),
+        };
+        let mut attributes = to_attributes(fields);
+        attributes.push(key_value(
+            "level",
+            string_value(event.meta.level().to_string()),
+        ));
+
+        let event = proto::span::Event {
+            time_unix_nano: event.initialized_at.timestamp_nanos() as u64,
+            name,
+            attributes,
+        };
+
+        let mut pending_events = self.pending_events.lock().unwrap();
+        if pending_events.len() < MAX_SPANS_WITH_PENDING_EVENTS
+            || pending_events.contains_key(&parent_id)
+        {
+            pending_events.entry(parent_id).or_default().push(event);
+        }
+    }
+}
+
+async fn export_loop(
+    service_name: &'static str,
+    endpoint: String,
+    mut receiver: mpsc::Receiver<proto::Span>,
+) {
+    let resource = Resource {
+        attributes: vec![key_value("service.name", string_value(service_name))],
+    };
+    let scope = InstrumentationScope {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let mut client = None;
+
+    while let Some(first) = receiver.recv().await {
+        // wait for more spans to build up a batch, then take whatever's queued
+        tokio::time::delay_for(EXPORT_INTERVAL).await;
+        let mut spans = vec![first];
+        while spans.len() < MAX_BATCH_SIZE {
+            match receiver.try_recv() {
+                Ok(span) => spans.push(span),
+                Err(_) => break,
+            }
+        }
+
+        let request = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(resource.clone()),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(scope.clone()),
+                    spans,
+                }],
+            }],
+        };
+
+        if client.is_none() {
+            match TraceServiceClient::connect(endpoint.clone()).await {
+                Ok(c) => client = Some(c),
+                Err(e) => {
+                    // logged to stderr, not via tracing, to avoid feeding back into telemetry
+                    eprintln!(
+                        "failed connecting to otlp collector at {}, {:?}",
+                        endpoint, e
+                    );
+                    continue;
+                }
+            }
+        }
+
+        // ASSERTION: client is always set here
+        if let Err(e) = client.as_mut().unwrap().export(request).await {
+            eprintln!("failed exporting spans to otlp collector, {:?}", e);
+            // reconnect on the next batch
+            client = None;
+        }
+    }
+}
+
+fn to_attributes(fields: Fields) -> Vec<KeyValue> {
+    fields
+        .0
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Bool(b) => any_value::Value::BoolValue(b),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => any_value::Value::IntValue(i),
+                    None => any_value::Value::DoubleValue(n.as_f64().unwrap_or(0.0)),
+                },
+                Value::String(s) => any_value::Value::StringValue(s),
+                other => any_value::Value::StringValue(other.to_string()),
+            };
+            KeyValue {
+                key,
+                value: Some(AnyValue { value: Some(value) }),
+            }
+        })
+        .collect()
+}
+
+fn key_value(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn string_value<S: Into<String>>(s: S) -> any_value::Value {
+    any_value::Value::StringValue(s.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{layer, register_dist_tracing_root, TelemetryConfig};
+    use crate::test_utils::{local_addr, wait_until_listening};
+    use proto::trace_service_server::{TraceService, TraceServiceServer};
+    use tonic::{Request, Response, Status};
+    use tracing_subscriber::layer::Layer;
+    use tracing_subscriber::registry;
+
+    // collector that forwards each export request to the test
+    struct Collector(Mutex<mpsc::Sender<ExportTraceServiceRequest>>);
+
+    #[tonic::async_trait]
+    impl TraceService for Collector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<proto::ExportTraceServiceResponse>, Status> {
+            let mut sender = self.0.lock().unwrap().clone();
+            sender.send(request.into_inner()).await.unwrap();
+            Ok(Response::new(proto::ExportTraceServiceResponse {}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let (sender, mut exported) = mpsc::channel(16);
+        let addr = local_addr();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(TraceServiceServer::new(Collector(Mutex::new(sender))))
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+        wait_until_listening(addr).await;
+
+        let config = TelemetryConfig::Otlp {
+            endpoint: format!("http://{}", addr),
+        };
+        let subscriber = layer("otlp-test", config).with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("handle_request", key = "a");
+            let _guard = span.enter();
+            register_dist_tracing_root(TraceId(42), Some(SpanId(7))).unwrap();
+            tracing::info!(nodes = 3, "fetched nodes");
+        });
+
+        let request = tokio::time::timeout(Duration::from_secs(5), exported.recv())
+            .await
+            .expect("timed out waiting for export")
+            .unwrap();
+
+        let resource_spans = &request.resource_spans[0];
+        let service_name = &resource_spans.resource.as_ref().unwrap().attributes[0];
+        assert_eq!(service_name.key, "service.name");
+        let span = &resource_spans.scope_spans[0].spans[0];
+        assert_eq!(span.name, "handle_request");
+        assert_eq!(span.trace_id, 42u128.to_be_bytes().to_vec());
+        assert_eq!(span.parent_span_id, 7u64.to_be_bytes().to_vec());
+        assert!(span.end_time_unix_nano >= span.start_time_unix_nano);
+        assert!(span.attributes.iter().any(|kv| kv.key == "key"));
+
+        assert_eq!(span.events.len(), 1);
+        assert_eq!(span.events[0].name, "fetched nodes");
+        let nodes = span.events[0]
+            .attributes
+            .iter()
+            .find(|kv| kv.key == "nodes");
+        let nodes = nodes.and_then(|kv| kv.value.clone()).and_then(|v| v.value);
+        assert_eq!(nodes, Some(any_value::Value::IntValue(3)));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::capabilities::cache::Cache;
+use crate::capabilities::store::FileSystemStore;
+use crate::capabilities::tenant::Quotas;
+use crate::capabilities::watch::{key_updates_channel, WatchedHashStore};
+use crate::server::app::Runtime;
+use crate::server::limits::Limits;
+use crate::telemetry::{self, TelemetryConfig};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry;
+
+pub fn init_test_env() {
+    let layer = telemetry::layer("dag-store-test", TelemetryConfig::None)
+        .and_then(tracing_subscriber::fmt::Layer::builder().finish())
+        .and_then(LevelFilter::INFO);
+
+    let subscriber = layer.with_subscriber(registry::Registry::default());
+
+    // attempt to set, failure means already set (other test suite, likely)
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+/// runtime backed by a fresh sled db in a tempdir (dropping the guard deletes it)
+pub fn test_runtime() -> (Runtime, tempdir::TempDir) {
+    let tmp_dir = tempdir::TempDir::new("dag-store-test").unwrap();
+    let fs_path = tmp_dir.path().to_str().unwrap().to_string();
+    let store = Arc::new(FileSystemStore::new(fs_path));
+    let key_updates = key_updates_channel();
+
+    let runtime = Runtime {
+        cache: Arc::new(Cache::new(1024 * 1024)),
+        mutable_hash_store: Arc::new(WatchedHashStore::new(store.clone(), key_updates.clone())),
+        hashed_blob_store: store,
+        key_updates,
+        prefetch: None,
+        quotas: Arc::new(Quotas::unlimited()),
+        auth: None,
+        limits: Arc::new(Limits::unlimited()),
+    };
+
+    (runtime, tmp_dir)
+}
+
+/// a localhost address with a free port, found by binding port 0 and releasing it again
+pub fn local_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+/// wait until something accepts connections on addr, panicking if nothing does within 5s
+pub async fn wait_until_listening(addr: SocketAddr) {
+    for _ in 0..100 {
+        if TcpStream::connect(addr).is_ok() {
+            return;
+        }
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+    }
+    panic!("nothing listening on {}", addr);
+}
+
+/// serve runtime on a free localhost port, returning its url and a second handle sharing its
+/// stores once it's accepting connections
+pub async fn spawn_dag_store(runtime: Runtime) -> (Runtime, String) {
+    let handle = Runtime {
+        cache: runtime.cache.clone(),
+        mutable_hash_store: runtime.mutable_hash_store.clone(),
+        hashed_blob_store: runtime.hashed_blob_store.clone(),
+        key_updates: runtime.key_updates.clone(),
+        prefetch: runtime.prefetch,
+        quotas: runtime.quotas.clone(),
+        auth: runtime.auth.clone(),
+        limits: runtime.limits.clone(),
+    };
+
+    let addr = local_addr();
+    tokio::spawn(async move {
+        crate::run(runtime, addr, None).await.unwrap();
+    });
+    wait_until_listening(addr).await;
+
+    (handle, format!("http://localhost:{}", addr.port()))
+}
@@ -0,0 +1,229 @@
+use crate::config::{check_pair, ConfigError};
+use crate::server::auth;
+use dag_store_types::types::errors::DagCacheError;
+use dag_store_types::types::grpc::dag_store_client::DagStoreClient;
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+use tonic::transport::{Channel, Endpoint, Server};
+
+/// pem encoded certificate and key files for serving grpc over tls
+#[derive(Clone, Debug)]
+pub struct ServerTls {
+    pub cert_file: String,
+    pub key_file: String,
+    /// if set, clients must present a certificate signed by this ca (mutual tls)
+    pub client_ca_file: Option<String>,
+}
+
+/// pem encoded files used when connecting to a dag-store over tls
+#[derive(Clone, Debug, Default)]
+pub struct ClientTls {
+    /// ca used to verify the server's certificate
+    pub ca_file: Option<String>,
+    /// certificate and key presented to servers requiring mutual tls
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+}
+
+impl ServerTls {
+    /// None if no certificate is configured, an error if only one of cert and key are provided
+    pub fn from_opts(
+        cert_file: Option<String>,
+        key_file: Option<String>,
+        client_ca_file: Option<String>,
+    ) -> Result<Option<Self>, ConfigError> {
+        check_pair("tls_cert_file", &cert_file, "tls_key_file", &key_file)?;
+        match (cert_file, key_file) {
+            (Some(cert_file), Some(key_file)) => Ok(Some(ServerTls {
+                cert_file,
+                key_file,
+                client_ca_file,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn configure(&self, server: Server) -> Result<Server, DagCacheError> {
+        let cert = std::fs::read(&self.cert_file).map_err(DagCacheError::unexpected)?;
+        let key = std::fs::read(&self.key_file).map_err(DagCacheError::unexpected)?;
+        let mut config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+        if let Some(client_ca_file) = &self.client_ca_file {
+            let ca = std::fs::read(client_ca_file).map_err(DagCacheError::unexpected)?;
+            config = config.client_ca_root(Certificate::from_pem(ca));
+        }
+        Ok(server.tls_config(config))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn configure(&self, _server: Server) -> Result<Server, DagCacheError> {
+        Err(not_supported())
+    }
+}
+
+impl ClientTls {
+    /// None if no tls options are set
+    pub fn from_opts(
+        ca_file: Option<String>,
+        cert_file: Option<String>,
+        key_file: Option<String>,
+    ) -> Option<Self> {
+        if ca_file.is_none() && cert_file.is_none() && key_file.is_none() {
+            return None;
+        }
+        Some(ClientTls {
+            ca_file,
+            cert_file,
+            key_file,
+        })
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn configure(&self, endpoint: Endpoint) -> Result<Endpoint, DagCacheError> {
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca_file) = &self.ca_file {
+            let ca = std::fs::read(ca_file).map_err(DagCacheError::unexpected)?;
+            config = config.ca_certificate(Certificate::from_pem(ca));
+        }
+        match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => {
+                let cert = std::fs::read(cert_file).map_err(DagCacheError::unexpected)?;
+                let key = std::fs::read(key_file).map_err(DagCacheError::unexpected)?;
+                config = config.identity(Identity::from_pem(cert, key));
+            }
+            (None, None) => {}
+            _ => {
+                return Err(DagCacheError::UnexpectedError(
+                    "client tls requires both a certificate and a key file".to_string(),
+                ))
+            }
+        }
+        Ok(endpoint.tls_config(config))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn configure(&self, _endpoint: Endpoint) -> Result<Endpoint, DagCacheError> {
+        Err(not_supported())
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn not_supported() -> DagCacheError {
+    DagCacheError::UnexpectedError("dag-store was built without the tls feature".to_string())
+}
+
+/// build an endpoint for url, using tls if configured
+pub fn endpoint(url: String, tls: Option<&ClientTls>) -> Result<Endpoint, DagCacheError> {
+    let endpoint = Endpoint::from_shared(url).map_err(DagCacheError::unexpected)?;
+    match tls {
+        Some(tls) => tls.configure(endpoint),
+        None => Ok(endpoint),
+    }
+}
+
+/// connect to the dag-store at url, using tls if configured
+pub async fn connect(
+    url: String,
+    tls: Option<&ClientTls>,
+) -> Result<DagStoreClient<Channel>, DagCacheError> {
+    connect_with_token(url, tls, None).await
+}
+
+/// as connect, presenting token (if any) as a bearer token on every request
+pub async fn connect_with_token(
+    url: String,
+    tls: Option<&ClientTls>,
+    token: Option<&str>,
+) -> Result<DagStoreClient<Channel>, DagCacheError> {
+    let channel = endpoint(url, tls)?
+        .connect()
+        .await
+        .map_err(DagCacheError::unexpected)?;
+    client(channel, token)
+}
+
+/// a client for channel, presenting token (if any) as a bearer token on every request
+pub fn client(
+    channel: Channel,
+    token: Option<&str>,
+) -> Result<DagStoreClient<Channel>, DagCacheError> {
+    match token {
+        Some(token) => Ok(DagStoreClient::with_interceptor(
+            channel,
+            auth::bearer_interceptor(token)?,
+        )),
+        None => Ok(DagStoreClient::new(channel)),
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_test_env, local_addr, test_runtime, wait_until_listening};
+    use dag_store_types::types::domain::Node;
+    use dag_store_types::types::encodings::Base64;
+    use futures::future::FutureExt;
+    use tokio::sync::oneshot;
+
+    fn testdata(file: &str) -> Option<String> {
+        Some(format!(
+            "{}/testdata/tls/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            file
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        init_test_env();
+        let (runtime, _dir) = test_runtime();
+        let server_tls = ServerTls {
+            cert_file: testdata("server.pem").unwrap(),
+            key_file: testdata("server.key").unwrap(),
+            client_ca_file: testdata("ca.pem"),
+        };
+        let addr = local_addr();
+        let (stop, stopped) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let shutdown = stopped.map(|_| ());
+            crate::run_with_shutdown(runtime, addr, Some(server_tls), shutdown)
+                .await
+                .unwrap();
+        });
+        wait_until_listening(addr).await;
+
+        let url = format!("https://localhost:{}", addr.port());
+        let node = Node {
+            links: vec![],
+            data: Base64(vec![1, 2, 3]),
+        };
+
+        let client_tls = ClientTls {
+            ca_file: testdata("ca.pem"),
+            cert_file: testdata("client.pem"),
+            key_file: testdata("client.key"),
+        };
+        let mut client = connect(url.clone(), Some(&client_tls)).await.unwrap();
+        client.put_node(node.clone().into_proto()).await.unwrap();
+
+        // without a client certificate the handshake is rejected
+        let anonymous = ClientTls {
+            ca_file: testdata("ca.pem"),
+            ..ClientTls::default()
+        };
+        let rejected = match connect(url.clone(), Some(&anonymous)).await {
+            Ok(mut client) => client.put_node(node.clone().into_proto()).await.is_err(),
+            Err(_) => true,
+        };
+        assert!(rejected);
+
+        // plaintext clients can't connect either
+        let rejected = match connect(url.replace("https", "http"), None).await {
+            Ok(mut client) => client.put_node(node.into_proto()).await.is_err(),
+            Err(_) => true,
+        };
+        assert!(rejected);
+
+        stop.send(()).unwrap();
+    }
+}
@@ -0,0 +1,17 @@
+#!/bin/bash
+# regenerates the self-signed ca and the server and client certs used by the tls tests.
+# certs are valid for localhost and expire in 100 years
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days 36500 -subj "/CN=dag-store test ca" \
+    -keyout ca.key -out ca.pem
+
+for name in server client; do
+    openssl req -newkey rsa:2048 -nodes -subj "/CN=localhost" -keyout "$name.key" -out "$name.csr"
+    openssl x509 -req -in "$name.csr" -CA ca.pem -CAkey ca.key -CAcreateserial -days 36500 \
+        -extfile <(printf "subjectAltName=DNS:localhost\nbasicConstraints=CA:FALSE") \
+        -out "$name.pem"
+    rm "$name.csr"
+done
+rm -f ca.srl ca.key
@@ -69,6 +69,7 @@ pub struct State {
     // node (header or body) being edited, as property of state & note node tree
     // focus includes path to root from that node
     last_known_hash: Option<TypedHash<CannonicalNode>>, // for CAS
+    notebook: String, // saves are committed to this notebook's branch
     // concept: have this be EditState | KeyboardNavFocusState
     edit_state: Option<EditState>,
     fetch_service: FetchService,
@@ -119,6 +120,7 @@ pub enum BackendMsg {
 
 #[derive(serde::Deserialize, Clone, Debug, PartialEq, Properties)]
 pub struct Arg {
+    pub notebook: String,
     pub hash: Option<TypedHash<CannonicalNode>>,
 }
 
@@ -126,11 +128,11 @@ impl Component for State {
     type Message = Msg;
     type Properties = Arg;
 
-    fn create(opt_hash: Self::Properties, link: ComponentLink<Self>) -> Self {
+    fn create(arg: Self::Properties, link: ComponentLink<Self>) -> Self {
         let mut nodes = HashMap::new();
 
-        let (root_node, last_known_hash, edit_state) = match opt_hash {
-            Arg { hash: None } => {
+        let (root_node, last_known_hash, edit_state) = match arg.hash {
+            None => {
                 let fresh_root = InMemNode {
                     hash: None,             // not persisted
                     inner: Node::new(None), // None b/c node is root (no parent)
@@ -144,7 +146,7 @@ impl Component for State {
 
                 (NodeRef::Modified(id), None, Some(es))
             }
-            Arg { hash: Some(h) } => (
+            Some(h) => (
                 NodeRef::Unmodified(RemoteNodeRef(NodeId::root(), h)),
                 Some(h),
                 None,
@@ -163,6 +165,7 @@ impl Component for State {
             focus_node: root_node,
             root_node,
             last_known_hash,
+            notebook: arg.notebook,
             edit_state,
             // TODO: split out display-relevant state and capabilities
             link,
@@ -666,8 +669,11 @@ impl State {
                     .expect("failure validating tree while building put request");
 
                     let req = notes_types::api::PutReq {
+                        notebook: self.notebook.clone(),
                         tree,
                         cas_hash: self.last_known_hash,
+                        author: None,
+                        message: None,
                     };
 
                     self.push_nodes(req);
@@ -6,18 +6,23 @@ fn main() {
     let value = js!({
         return window.starting_hash;
     });
-    let arg: notes::Arg = match value {
+    let hash = match value {
         stdweb::Value::String(s) => {
             if s.is_empty() {
-                notes::Arg { hash: None }
+                None
             } else {
                 let hash = Hash::from_base58(&s)
                     .expect("unable to parse hash (handlebar template bug, FIXME)")
                     .promote::<CannonicalNode>();
-                notes::Arg { hash: Some(hash) }
+                Some(hash)
             }
         }
         _ => panic!("unexpected type from handlebar template, FIXME"),
     };
+    let notebook = match js!({ return window.notebook; }) {
+        stdweb::Value::String(s) => s,
+        _ => panic!("unexpected type from handlebar template, FIXME"),
+    };
+    let arg = notes::Arg { notebook, hash };
     yew::start_app_with_props::<notes::State>(arg);
 }
@@ -0,0 +1,315 @@
+use crate::add_request_meta;
+use dag_store_types::types::{
+    api::{bulk_put, get, list_keys},
+    domain::{self, TypedHash},
+    encodings::Base64,
+    grpc::{self, dag_store_client::DagStoreClient},
+    validated_tree::ValidatedTree,
+};
+use notes_types::api::{
+    legacy_notebook_key, notebook_name, Branch, HistoryEntry, CAS_KEY_PREFIX, DEFAULT_AUTHOR,
+};
+use notes_types::commits::Commit;
+use notes_types::notes::CannonicalNode;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::transport::Channel;
+use tracing::info;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+/// returned when a save isn't based on the current head commit
+#[derive(Debug)]
+pub struct StaleRootError(pub String);
+
+impl std::fmt::Display for StaleRootError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for StaleRootError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// returned when creating a branch from a root that isn't in the store
+#[derive(Debug)]
+pub struct UnknownRootError(pub String);
+
+impl std::fmt::Display for UnknownRootError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for UnknownRootError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// wall-clock time for commit timestamps
+pub fn now_millis() -> Result<u64> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(since_epoch.as_millis() as u64)
+}
+
+/// commit the key currently points to, if any. if the default notebook's key is unset, it's
+/// migrated from the key used before notebooks were named
+pub async fn get_head(
+    client: &mut DagStoreClient<Channel>,
+    key: &str,
+) -> Result<Option<TypedHash<Commit>>> {
+    if let Some(head) = get_key(client, key).await? {
+        return Ok(Some(head.promote()));
+    }
+
+    let legacy_key = match legacy_notebook_key(key) {
+        Some(legacy_key) => legacy_key,
+        None => return Ok(None),
+    };
+    let head = match get_key(client, legacy_key).await? {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+
+    info!("migrating {} from legacy key {}", key, legacy_key);
+    match copy_key(client, key, head).await {
+        Ok(head) => Ok(Some(head.promote())),
+        // raced with another migration or save, use whatever won
+        Err(e) => match get_key(client, key).await? {
+            Some(head) => Ok(Some(head.promote())),
+            None => Err(e),
+        },
+    }
+}
+
+async fn get_key(client: &mut DagStoreClient<Channel>, key: &str) -> Result<Option<domain::Hash>> {
+    let mut request = tonic::Request::new(grpc::GetHashForKeyReq {
+        key: key.to_string(),
+    });
+    add_request_meta(&mut request);
+
+    let response = client.get_hash_for_key(request).await?;
+    let hash = response
+        .into_inner()
+        .hash
+        .map(domain::Hash::from_proto)
+        .transpose()?;
+    Ok(hash)
+}
+
+// point key at hash if it's currently unset, returning the hash it now points at. there's no
+// rpc to set a key directly, so the (already present) node is re-put with a cas, deduplicated
+// by the store
+async fn copy_key(
+    client: &mut DagStoreClient<Channel>,
+    key: &str,
+    hash: domain::Hash,
+) -> Result<domain::Hash> {
+    let mut request = tonic::Request::new(hash.into_proto());
+    add_request_meta(&mut request);
+    let response = client.get_node(request).await?;
+    let node = get::Resp::from_proto(response.into_inner())?.requested_node;
+    // checked before the cas, as a re-put node that hashes differently would move the key
+    if node.canonical_hash() != hash {
+        return Err(format!("node {} doesn't hash to itself, not migrating", hash).into());
+    }
+
+    let node = bulk_put::Node {
+        links: node
+            .links
+            .into_iter()
+            .map(bulk_put::NodeLink::Remote)
+            .collect(),
+        data: node.data,
+    };
+    let req = bulk_put::Req {
+        validated_tree: ValidatedTree::validate(node, HashMap::new())?,
+        cas: Some(bulk_put::CAS {
+            required_previous_hash: None,
+            cas_key: key.to_string(),
+        }),
+    };
+    let mut request = tonic::Request::new(req.into_proto());
+    add_request_meta(&mut request);
+    let response = client.put_nodes(request).await?;
+    let response = bulk_put::Resp::from_proto(response.into_inner())?;
+    Ok(response.root_hash)
+}
+
+pub async fn get_commit(
+    client: &mut DagStoreClient<Channel>,
+    hash: TypedHash<Commit>,
+) -> Result<Commit> {
+    let mut request = tonic::Request::new(hash.demote().into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.get_node(request).await?;
+    let response = get::Resp::from_proto(response.into_inner())?;
+    Commit::from_generic(response.requested_node)
+}
+
+/// what a key points to. keys written before commits were introduced point directly at the
+/// root of a notes tree, with no history
+pub enum Head {
+    Commit(Commit),
+    LegacyRoot(TypedHash<CannonicalNode>),
+}
+
+impl Head {
+    pub fn root(&self) -> Option<TypedHash<CannonicalNode>> {
+        match self {
+            Head::Commit(commit) => commit.root(),
+            Head::LegacyRoot(root) => Some(*root),
+        }
+    }
+}
+
+/// read the node a key's head hash points to, either a commit or a legacy tree root
+pub async fn read_head(
+    client: &mut DagStoreClient<Channel>,
+    hash: TypedHash<Commit>,
+) -> Result<Head> {
+    let mut request = tonic::Request::new(hash.demote().into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.get_node(request).await?;
+    let response = get::Resp::from_proto(response.into_inner())?;
+    let data = &response.requested_node.data.0[..];
+    match Commit::decode(data) {
+        Ok(commit) => Ok(Head::Commit(commit)),
+        Err(e) => match CannonicalNode::decode(data) {
+            Ok(_) => Ok(Head::LegacyRoot(hash.demote().promote())),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+/// if key points at a legacy tree root, wrap it in a commit (with no history) and point key
+/// at that instead. fails if key has moved on since head was read
+pub async fn upgrade_head(
+    client: &mut DagStoreClient<Channel>,
+    key: &str,
+    head: TypedHash<Commit>,
+) -> Result<TypedHash<Commit>> {
+    let root = match read_head(client, head).await? {
+        Head::Commit(_) => return Ok(head),
+        Head::LegacyRoot(root) => root,
+    };
+
+    let commit = Commit::Commit {
+        parent: null_commit(client).await?,
+        additional_parents: Vec::new(),
+        root,
+        author: DEFAULT_AUTHOR.to_string(),
+        timestamp_millis: now_millis()?,
+        message: Some("import notebook saved before history was recorded".to_string()),
+    };
+    put_commit(client, key, commit, Some(head)).await
+}
+
+/// write commit, then point key at it if it currently points at previous
+pub async fn put_commit(
+    client: &mut DagStoreClient<Channel>,
+    key: &str,
+    commit: Commit,
+    previous: Option<TypedHash<Commit>>,
+) -> Result<TypedHash<Commit>> {
+    let validated_tree = ValidatedTree::validate(commit.into_generic()?, HashMap::new())?;
+    let req = bulk_put::Req {
+        validated_tree,
+        cas: Some(bulk_put::CAS {
+            required_previous_hash: previous.map(|p| p.demote()),
+            cas_key: key.to_string(),
+        }),
+    };
+    let mut request = tonic::Request::new(req.into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.put_nodes(request).await?;
+    let response = bulk_put::Resp::from_proto(response.into_inner())?;
+    Ok(response.root_hash.promote())
+}
+
+pub async fn has_root(
+    client: &mut DagStoreClient<Channel>,
+    root: TypedHash<CannonicalNode>,
+) -> Result<bool> {
+    let mut request = tonic::Request::new(grpc::HasNodesReq {
+        hashes: vec![root.demote().into_proto()],
+    });
+    add_request_meta(&mut request);
+
+    let response = client.has_nodes(request).await?;
+    Ok(response.into_inner().present == vec![true])
+}
+
+/// notebooks present in the store, ordered by name
+pub async fn list_branches(client: &mut DagStoreClient<Channel>) -> Result<Vec<Branch>> {
+    let req = list_keys::Req {
+        prefix: CAS_KEY_PREFIX.to_string(),
+    };
+    let mut request = tonic::Request::new(req.into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.list_keys(request).await?;
+    let response = list_keys::Resp::from_proto(response.into_inner())?;
+    let branches = response
+        .keys
+        .into_iter()
+        .filter_map(|(key, hash)| {
+            notebook_name(&key).map(|name| Branch {
+                name: name.to_string(),
+                head: hash.promote(),
+            })
+        })
+        .collect();
+    Ok(branches)
+}
+
+/// the shared origin of all histories (idempotent, content-addressed)
+pub async fn null_commit(client: &mut DagStoreClient<Channel>) -> Result<TypedHash<Commit>> {
+    let node = domain::Node {
+        links: Vec::new(),
+        data: Base64(Commit::Null.encode()?),
+    };
+    let mut request = tonic::Request::new(node.into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.put_node(request).await?;
+    let hash = domain::Hash::from_proto(response.into_inner())?;
+    Ok(hash.promote())
+}
+
+/// commits reachable from head, breadth-first via parent links (excluding the null commit)
+pub async fn history(
+    client: &mut DagStoreClient<Channel>,
+    head: TypedHash<Commit>,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>> {
+    let mut res = Vec::new();
+    let mut visited = HashSet::new();
+    let mut frontier = VecDeque::new();
+    frontier.push_back(head);
+
+    while let Some(hash) = frontier.pop_front() {
+        if res.len() >= limit {
+            break;
+        }
+        if !visited.insert(hash) {
+            continue;
+        }
+
+        let commit = get_commit(client, hash).await?;
+        if commit == Commit::Null {
+            continue;
+        }
+        frontier.extend(commit.parents());
+        res.push(HistoryEntry { hash, commit });
+    }
+
+    Ok(res)
+}
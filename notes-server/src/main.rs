@@ -1,24 +1,30 @@
 #![deny(warnings)]
 
+mod commits;
 mod opts;
+use dag_store::telemetry::{
+    current_trace_parent, register_dist_tracing_root, TraceId, TRACEPARENT_META_FIELD,
+};
+use dag_store::tls::ServerTls;
 use dag_store_types::types::{
-    api::{bulk_put, get},
+    api::{bulk_put, get, resolve},
     domain::{self, Hash},
-    grpc::{self, dag_store_client::DagStoreClient},
+    grpc::dag_store_client::DagStoreClient,
 };
 #[cfg(feature = "embed-wasm")]
 use headers::HeaderMapExt;
+use notes_types::{
+    api::{Branch, CreateBranchReq, HistoryEntry},
+    commits::Commit,
+};
 use opts::{Opt, Runtime};
 use serde::Serialize;
 use serde_json::json;
-use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::Arc,
-};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use structopt::StructOpt;
-use tonic::metadata::MetadataValue;
+use tonic::{metadata::MetadataValue, transport::Channel};
 use tracing::{error, info, instrument};
-use tracing_honeycomb::{current_dist_trace_ctx, register_dist_tracing_root, SpanId, TraceId};
 use warp::{reject, Filter};
 
 // TODO: struct w/ domain types & etc
@@ -34,18 +40,37 @@ struct ErrorMessage<'a> {
     message: &'a str,
 }
 
+// max commits returned by the history endpoint
+const HISTORY_LIMIT: usize = 100;
+
 // used to provide shared runtime ctx - there's probably a better way to do this
 static mut GLOBAL_CTX: Option<Arc<Runtime>> = None;
 
+fn try_get_ctx() -> Option<Arc<Runtime>> {
+    unsafe { GLOBAL_CTX.clone() }
+}
+
 fn get_ctx() -> Arc<Runtime> {
-    unsafe {
-        match &GLOBAL_CTX {
-            Some(x) => x.clone(),
-            None => panic!("global ctx not set"),
-        }
+    match try_get_ctx() {
+        Some(x) => x,
+        None => panic!("global ctx not set"),
     }
 }
 
+/// connect to the dag store, using tls and presenting a bearer token if configured
+async fn connect(
+    url: String,
+) -> Result<DagStoreClient<Channel>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // the global ctx is unset in tests, which run against a dag store without auth
+    let ctx = try_get_ctx();
+    let tls = ctx.as_ref().and_then(|ctx| ctx.dag_store_tls.as_ref());
+    let token = ctx.as_ref().and_then(|ctx| ctx.dag_store_token.as_deref());
+    let client = dag_store::tls::connect_with_token(url, tls, token)
+        .await
+        .map_err(|e| format!("failed connecting to dag store: {:?}", e))?;
+    Ok(client)
+}
+
 fn register_trace_root() {
     println!("register trace root");
     let trace_id = TraceId::generate();
@@ -53,18 +78,15 @@ fn register_trace_root() {
     println!("register trace root done");
 }
 
-fn add_tracing_to_meta<T>(request: &mut tonic::Request<T>) {
+/// attach tracing context to an outgoing request
+fn add_request_meta<T>(request: &mut tonic::Request<T>) {
     let meta = request.metadata_mut();
 
-    let (trace_id, span_id) = current_dist_trace_ctx().unwrap();
+    let traceparent = current_trace_parent().unwrap();
 
     meta.insert(
-        TraceId::meta_field_name(),
-        MetadataValue::from_str(&trace_id.to_string()).unwrap(),
-    );
-    meta.insert(
-        SpanId::meta_field_name(),
-        MetadataValue::from_str(&span_id.to_string()).unwrap(),
+        TRACEPARENT_META_FIELD,
+        MetadataValue::from_str(&traceparent.to_string()).unwrap(),
     );
 }
 
@@ -75,14 +97,12 @@ async fn get_nodes(
 ) -> Result<notes_types::api::GetResp, Box<dyn std::error::Error + Send + Sync + 'static>> {
     register_trace_root();
 
-    let mut client = DagStoreClient::connect(url)
-        .await
-        .map_err(|e| Box::new(e))?;
+    let mut client = connect(url).await?;
 
     let hash = Hash::from_base58(&raw_hash).map_err(|e| Box::new(e))?;
 
     let mut request = tonic::Request::new(hash.into_proto());
-    add_tracing_to_meta(&mut request);
+    add_request_meta(&mut request);
 
     let response = client.get_node(request).await.map_err(|e| Box::new(e))?;
     let response = get::Resp::from_proto(response.into_inner()).map_err(|e| Box::new(e))?;
@@ -90,33 +110,56 @@ async fn get_nodes(
     Ok(response)
 }
 
+#[instrument]
+async fn resolve_path(
+    url: String,
+    raw_hash: String,
+    raw_path: String,
+) -> Result<notes_types::api::ResolveResp, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    register_trace_root();
+
+    let mut client = connect(url).await?;
+
+    let hash = Hash::from_base58(&raw_hash).map_err(|e| Box::new(e))?;
+    let path = raw_path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(notes_types::notes::NodeId::from_base58)
+        .map(|id| id.map(|id| resolve::Segment::Id(id.into_generic())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let req = resolve::Req {
+        root: resolve::Root::Hash(hash),
+        path,
+    };
+    let mut request = tonic::Request::new(req.into_proto());
+    add_request_meta(&mut request);
+
+    let response = client.resolve(request).await.map_err(|e| Box::new(e))?;
+    let response = resolve::Resp::from_proto(response.into_inner()).map_err(|e| Box::new(e))?;
+    let response = notes_types::api::ResolveResp::from_generic(response)?;
+    Ok(response)
+}
+
 #[instrument]
 async fn get_initial_state(
     url: String,
+    notebook: String,
 ) -> Result<Option<domain::Hash>, Box<dyn std::error::Error + Send + Sync + 'static>> {
     println!("get initial state first line");
     register_trace_root();
 
-    let mut client = DagStoreClient::connect(url)
-        .await
-        .map_err(|e| Box::new(e))?;
+    let mut client = connect(url).await?;
 
-    // TODO: validate base58 here
-    // TODO: dedup cas hash
-    let mut request = tonic::Request::new(grpc::GetHashForKeyReq {
-        key: notes_types::api::CAS_KEY.to_string(),
-    });
-    add_tracing_to_meta(&mut request);
-
-    let response = client.get_hash_for_key(request).await?;
-    let response = response
-        .into_inner()
-        .hash
-        .map(|p| domain::Hash::from_proto(p))
-        .transpose()
-        .map_err(|e| Box::new(e))?;
+    // key points at the head commit, clients only care about its root
+    let key = notes_types::api::notebook_key(&notebook)?;
+    let head = commits::get_head(&mut client, &key).await?;
+    let root = match head {
+        Some(head) => commits::read_head(&mut client, head).await?.root(),
+        None => None,
+    };
 
-    Ok(response)
+    Ok(root.map(|r| r.demote()))
 }
 
 #[instrument]
@@ -126,33 +169,194 @@ async fn put_nodes(
 ) -> Result<bulk_put::Resp, Box<dyn std::error::Error + Send + Sync + 'static>> {
     register_trace_root();
 
+    let key = notes_types::api::notebook_key(&put_req.notebook)?;
+    let cas_hash = put_req.cas_hash;
+    let author = put_req
+        .author
+        .clone()
+        .unwrap_or_else(|| notes_types::api::DEFAULT_AUTHOR.to_string());
+    let message = put_req.message.clone();
     let put_req = put_req.into_generic()?;
 
     // TODO: better mgmt for grpc port/host
-    let mut client = DagStoreClient::connect(url)
-        .await
-        .map_err(|e| Box::new(e))?;
+    let mut client = connect(url).await?;
+
+    // edits must be based on the current head, checked again atomically when committing
+    let head = commits::get_head(&mut client, &key).await?;
+    let head_root = match head {
+        Some(head) => commits::read_head(&mut client, head).await?.root(),
+        None => None,
+    };
+    if head_root != cas_hash {
+        return Err(Box::new(commits::StaleRootError(format!(
+            "edit based on {:?} but head is {:?}",
+            cas_hash.map(|h| h.demote()),
+            head_root.map(|h| h.demote())
+        ))));
+    }
 
     // TODO: validate base58 here
     let mut request = tonic::Request::new(put_req.into_proto());
-    add_tracing_to_meta(&mut request);
+    add_request_meta(&mut request);
 
     let response = client.put_nodes(request).await.map_err(|e| Box::new(e))?;
 
     // NOTE: no need to use specific repr, hash and client id are generic enough
     let response = bulk_put::Resp::from_proto(response.into_inner()).map_err(|e| Box::new(e))?;
 
+    // a legacy head is wrapped in a commit on first save, so it becomes this one's parent
+    let (parent, head) = match head {
+        Some(head) => {
+            let head = commits::upgrade_head(&mut client, &key, head).await?;
+            (head, Some(head))
+        }
+        None => (commits::null_commit(&mut client).await?, None),
+    };
+    let commit = Commit::Commit {
+        parent,
+        additional_parents: Vec::new(),
+        root: response.root_hash.promote(),
+        author,
+        timestamp_millis: commits::now_millis()?,
+        message,
+    };
+    commits::put_commit(&mut client, &key, commit, head).await?;
+
     Ok(response)
 }
 
+#[instrument]
+async fn get_history(
+    url: String,
+    notebook: String,
+) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    register_trace_root();
+
+    let mut client = connect(url).await?;
+
+    let key = notes_types::api::notebook_key(&notebook)?;
+    let head = commits::get_head(&mut client, &key).await?;
+    match head {
+        Some(head) => match commits::read_head(&mut client, head).await? {
+            // saved before history was recorded
+            commits::Head::LegacyRoot(_) => Ok(Vec::new()),
+            commits::Head::Commit(_) => commits::history(&mut client, head, HISTORY_LIMIT).await,
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+#[instrument]
+async fn get_commit(
+    url: String,
+    raw_hash: String,
+) -> Result<HistoryEntry, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    register_trace_root();
+
+    let mut client = connect(url).await?;
+
+    let hash = Hash::from_base58(&raw_hash).map_err(|e| Box::new(e))?.promote();
+    let commit = commits::get_commit(&mut client, hash).await?;
+    Ok(HistoryEntry { hash, commit })
+}
+
+#[instrument]
+async fn list_branches(
+    url: String,
+) -> Result<Vec<Branch>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    register_trace_root();
+
+    let mut client = connect(url).await?;
+
+    commits::list_branches(&mut client).await
+}
+
+#[instrument]
+async fn create_branch(
+    url: String,
+    req: CreateBranchReq,
+) -> Result<Branch, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    register_trace_root();
+
+    let key = notes_types::api::notebook_key(&req.name)?;
+
+    let mut client = connect(url).await?;
+
+    // commits link to their root without uploading it, so it must already be present
+    if !commits::has_root(&mut client, req.root).await? {
+        return Err(Box::new(commits::UnknownRootError(format!(
+            "no tree with root {} in store",
+            req.root.demote()
+        ))));
+    }
+
+    let commit = Commit::Commit {
+        parent: commits::null_commit(&mut client).await?,
+        additional_parents: Vec::new(),
+        root: req.root,
+        author: req
+            .author
+            .unwrap_or_else(|| notes_types::api::DEFAULT_AUTHOR.to_string()),
+        timestamp_millis: commits::now_millis()?,
+        message: Some(format!("create notebook from {}", req.root.demote())),
+    };
+    // no previous hash, fails if the branch already exists
+    let head = commits::put_commit(&mut client, &key, commit, None).await?;
+
+    Ok(Branch {
+        name: req.name,
+        head,
+    })
+}
+
+async fn index(notebook: String) -> Result<impl warp::Reply, warp::Rejection> {
+    let url = get_ctx().dag_store_url.to_string();
+    let res = get_initial_state(url, notebook.clone()).await;
+
+    match res {
+        Ok(resp) => {
+            info!("initial state resp: {:?}", &resp);
+            let initial_hash = resp.map(|h| format!("{}", h)).unwrap_or_default();
+            let t = crate::opts::WithTemplate {
+                name: "index.html",
+                value: json!({ "initial_hash": initial_hash, "notebook": notebook }),
+            };
+            Ok(get_ctx().render(t))
+        }
+        Err(e) => {
+            error!("err on get initial state: {:?}", e);
+            Err(reject::custom::<Error>(Error(e)))
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let opt = Opt::from_args();
-    let runtime = opt.into_runtime();
+    let print_config = opt.print_config;
+    let runtime = match opt.into_config() {
+        Ok(config) if print_config => {
+            print!("{}", dag_store::config::to_toml(&config));
+            return;
+        }
+        Ok(config) => config.into_runtime(),
+        Err(e) => Err(e),
+    };
+    let runtime = match runtime {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("invalid config: {}", e);
+            std::process::exit(2);
+        }
+    };
     unsafe {
         GLOBAL_CTX = Some(Arc::new(runtime));
     }
 
+    serve(routes(), get_ctx().bind_addr, get_ctx().https.clone()).await
+}
+
+fn routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let get_route = warp::path("node")
         .and(warp::path::param::<String>())
         // .end()
@@ -173,33 +377,111 @@ async fn main() {
             }
         });
 
-    let index_route = warp::get().and(warp::path::end()).and_then(|| {
-        async {
-            let url = get_ctx().dag_store_url.to_string();
-            let res = get_initial_state(url).await;
-
-            match res {
-                Ok(resp) => {
-                    info!("initial state resp: {:?}", &resp);
-                    let t = match resp {
-                        Some(h) => crate::opts::WithTemplate {
-                            name: "index.html",
-                            value: json!({ "initial_hash": format!("{}", h) }),
-                        },
-                        None => crate::opts::WithTemplate {
-                            name: "index.html",
-                            value: json!({"initial_hash" : ""}),
-                        },
-                    };
-                    Ok(get_ctx().render(t))
+    // deep link to a node via the NodeIds on the path to it, eg /resolve/<root hash>/<id>/<id>
+    let resolve_route = warp::path("resolve")
+        .and(warp::path::param::<String>())
+        .and(warp::path::tail())
+        .and_then({
+            |raw_hash: String, tail: warp::path::Tail| {
+                async move {
+                    let url = get_ctx().dag_store_url.to_string();
+                    let res = resolve_path(url, raw_hash, tail.as_str().to_string()).await;
+
+                    match res {
+                        Ok(resp) => Ok(warp::reply::json(&resp)),
+                        Err(e) => {
+                            error!("err on resolving path: {:?}", e);
+                            Err(reject::custom::<Error>(Error(e)))
+                        }
+                    }
+                }
+            }
+        });
+
+    let history_route = warp::get()
+        .and(warp::path("history"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(|notebook: String| {
+            async move {
+                let url = get_ctx().dag_store_url.to_string();
+                let res = get_history(url, notebook).await;
+
+                match res {
+                    Ok(resp) => Ok(warp::reply::json(&resp)),
+                    Err(e) => {
+                        error!("err on getting history: {:?}", e);
+                        Err(reject::custom::<Error>(Error(e)))
+                    }
                 }
-                Err(e) => {
-                    error!("err on get initial state: {:?}", e);
-                    Err(reject::custom::<Error>(Error(e)))
+            }
+        });
+
+    let commit_route = warp::get()
+        .and(warp::path("commit"))
+        .and(warp::path::param::<String>())
+        .and_then(|raw_hash: String| {
+            async move {
+                let url = get_ctx().dag_store_url.to_string();
+                let res = get_commit(url, raw_hash).await;
+
+                match res {
+                    Ok(resp) => Ok(warp::reply::json(&resp)),
+                    Err(e) => {
+                        error!("err on getting commit: {:?}", e);
+                        Err(reject::custom::<Error>(Error(e)))
+                    }
                 }
             }
-        }
-    });
+        });
+
+    let index_route = warp::get()
+        .and(warp::path::end())
+        .and_then(|| index(notes_types::api::DEFAULT_NOTEBOOK.to_string()));
+
+    let notebook_route = warp::get()
+        .and(warp::path("notebook"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(index);
+
+    let list_branches_route = warp::get()
+        .and(warp::path("branches"))
+        .and(warp::path::end())
+        .and_then(|| {
+            async {
+                let url = get_ctx().dag_store_url.to_string();
+                let res = list_branches(url).await;
+
+                match res {
+                    Ok(resp) => Ok(warp::reply::json(&resp)),
+                    Err(e) => {
+                        error!("err on listing branches: {:?}", e);
+                        Err(reject::custom::<Error>(Error(e)))
+                    }
+                }
+            }
+        });
+
+    let create_branch_route = warp::post()
+        .and(warp::path("branches"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and_then(|req: CreateBranchReq| {
+            async move {
+                let url = get_ctx().dag_store_url.to_string();
+                let res = create_branch(url, req).await;
+
+                match res {
+                    Ok(resp) => Ok(warp::reply::json(&resp)),
+                    Err(e) => {
+                        error!("err on creating branch: {:?}", e);
+                        Err(reject::custom::<Error>(Error(e)))
+                    }
+                }
+            }
+        });
 
     let post_route = warp::post()
         .and(warp::path("nodes"))
@@ -260,10 +542,39 @@ async fn main() {
             },
         );
 
-    let routes = get_route.or(post_route).or(index_route).or(static_route);
+    get_route
+        .or(resolve_route)
+        .or(history_route)
+        .or(commit_route)
+        .or(post_route)
+        .or(list_branches_route)
+        .or(create_branch_route)
+        .or(notebook_route)
+        .or(index_route)
+        .or(static_route)
+}
 
-    let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), get_ctx().port);
-    warp::serve(routes).run(socket).await;
+/// serve routes on socket, over https if configured
+async fn serve<F>(routes: F, socket: SocketAddr, https: Option<ServerTls>)
+where
+    F: Filter<Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply,
+{
+    let server = warp::serve(routes);
+    match https {
+        #[cfg(feature = "tls")]
+        Some(https) => {
+            server
+                .tls()
+                .cert_path(https.cert_file)
+                .key_path(https.key_file)
+                .run(socket)
+                .await
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => panic!("notes-server was built without the tls feature"),
+        None => server.run(socket).await,
+    }
 }
 
 // FIXME: should be omitable
@@ -296,63 +607,23 @@ mod tests {
     use notes_types::notes::*;
     use std::collections::HashMap;
 
-    use dag_store::capabilities::cache::Cache;
-    use dag_store::capabilities::store::FileSystemStore;
-    use std::sync::Arc;
-    use tracing_honeycomb::new_blackhole_telemetry_layer;
-    use tracing_subscriber::filter::LevelFilter;
-    use tracing_subscriber::layer::Layer;
-    use tracing_subscriber::registry;
-
-    pub fn init_test_env() {
-        let layer = new_blackhole_telemetry_layer()
-            .and_then(tracing_subscriber::fmt::Layer::builder().finish())
-            .and_then(LevelFilter::INFO);
-
-        let subscriber = layer.with_subscriber(registry::Registry::default());
-
-        // attempt to set, failure means already set (other test suite, likely)
-        let _ = tracing::subscriber::set_global_default(subscriber);
-    }
-
-    fn spawn_dag_store(port: u16) -> tempdir::TempDir {
-        let tmp_dir = tempdir::TempDir::new("dag-store-test").unwrap();
-        let fs_path = tmp_dir.path().to_str().unwrap().to_string();
-        let store = Arc::new(FileSystemStore::new(fs_path));
+    use dag_store::test_utils::{init_test_env, spawn_dag_store, test_runtime};
 
-        let cache = Arc::new(Cache::new(64));
-
-        let runtime = dag_store::server::app::Runtime {
-            cache: cache,
-            mutable_hash_store: store.clone(),
-            hashed_blob_store: store,
-        };
-
-        let bind_to = format!("0.0.0.0:{}", &port);
-        let addr = bind_to.parse().unwrap();
-
-        tokio::spawn(async move {
-            dag_store::run(runtime, addr).await.unwrap();
-            ()
-        });
-
-        // return guard
-        tmp_dir
+    fn notebook() -> String {
+        notes_types::api::DEFAULT_NOTEBOOK.to_string()
     }
 
     #[tokio::test]
     async fn test_batch_upload() {
         init_test_env();
 
-        let dag_store_port = 6666;
-        let tmp_dir = spawn_dag_store(dag_store_port);
+        let (runtime, tmp_dir) = test_runtime();
+        let (_runtime, dag_store_url) = spawn_dag_store(runtime).await;
 
         // TODO: test env might have to be manual - how to express test dep on other bin in project?
 
-        let dag_store_url = format!("http://localhost:{}", dag_store_port);
-
         // - get state, no hash.
-        let state = get_initial_state(dag_store_url.to_string()).await.unwrap();
+        let state = get_initial_state(dag_store_url.to_string(), notebook()).await.unwrap();
         assert_eq!(state, None);
 
         let node1 = notes_types::notes::Node {
@@ -379,8 +650,11 @@ mod tests {
         .expect("failure validating tree while building put request");
 
         let put_req = notes_types::api::PutReq {
+            notebook: notebook(),
             tree,
             cas_hash: None,
+            author: None,
+            message: None,
         };
 
         // - push small tree with hash + no CAS hash
@@ -389,7 +663,7 @@ mod tests {
             .unwrap()
             .root_hash;
 
-        let state = get_initial_state(dag_store_url.to_string()).await.unwrap();
+        let state = get_initial_state(dag_store_url.to_string(), notebook()).await.unwrap();
         assert_eq!(state, Some(hash.clone()));
 
         // - get tree, recursive expansion of same (NOTE: only one layer currently)
@@ -404,6 +678,247 @@ mod tests {
             node1.map(|n| n.node_id())
         );
 
+        // - resolve child by NodeId path from root
+        let resolve_resp = resolve_path(
+            dag_store_url.to_string(),
+            hash.to_string(),
+            NodeId(1).to_base58(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolve_resp.requested_node.header, "hdr 2");
+        assert_eq!(resolve_resp.path.len(), 1);
+        assert_eq!(resolve_resp.path[0].0, NodeId(1));
+
+        // - each save is recorded as a commit
+        let history = get_history(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].commit.root().map(|r| r.demote()), Some(hash));
+
+        let edited = notes_types::notes::Node {
+            parent: None,
+            children: vec![NodeRef::Unmodified(resolve_resp.path[0].clone())],
+            header: "hdr edited".to_string(),
+        };
+        let put_req = |cas_hash| notes_types::api::PutReq {
+            notebook: notebook(),
+            tree: ValidatedTree_::validate_(edited.clone(), HashMap::new(), |_| {
+                std::iter::empty()
+            })
+            .expect("failure validating tree while building put request"),
+            cas_hash,
+            author: Some("alice".to_string()),
+            message: Some("edit header".to_string()),
+        };
+
+        // - saves not based on the current head are rejected
+        assert!(put_nodes(dag_store_url.to_string(), put_req(None))
+            .await
+            .is_err());
+
+        let hash2 = put_nodes(dag_store_url.to_string(), put_req(Some(hash.promote())))
+            .await
+            .unwrap()
+            .root_hash;
+        let state = get_initial_state(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(state, Some(hash2));
+
+        let history = get_history(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].commit.root().map(|r| r.demote()), Some(hash2));
+        assert_eq!(history[0].commit.parents(), vec![history[1].hash]);
+        match &history[0].commit {
+            Commit::Commit {
+                author, message, ..
+            } => {
+                assert_eq!(author, "alice");
+                assert_eq!(message.as_deref(), Some("edit header"));
+            }
+            Commit::Null => panic!("null commit in history"),
+        }
+
+        let commit = get_commit(dag_store_url.to_string(), history[1].hash.to_string())
+            .await
+            .unwrap();
+        assert_eq!(commit, history[1]);
+
+        // - notebooks can be branched from any existing root
+        let create = |name: &str, root| CreateBranchReq {
+            name: name.to_string(),
+            root,
+            author: None,
+        };
+        let branch = create_branch(dag_store_url.to_string(), create("fork", hash.promote()))
+            .await
+            .unwrap();
+        let fork_history = get_history(dag_store_url.to_string(), "fork".to_string())
+            .await
+            .unwrap();
+        assert_eq!(fork_history.len(), 1);
+        assert_eq!(fork_history[0].hash, branch.head);
+        let state = get_initial_state(dag_store_url.to_string(), "fork".to_string())
+            .await
+            .unwrap();
+        assert_eq!(state, Some(hash));
+
+        // existing branch names and unknown roots are rejected
+        assert!(
+            create_branch(dag_store_url.to_string(), create("fork", hash2.promote()))
+                .await
+                .is_err()
+        );
+        let unknown = domain::Hash::from_bytes(&[0; 32]).unwrap().promote();
+        assert!(
+            create_branch(dag_store_url.to_string(), create("other", unknown))
+                .await
+                .is_err()
+        );
+
+        let branches = list_branches(dag_store_url.to_string()).await.unwrap();
+        let names: Vec<&str> = branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["fork", "main"]);
+        assert_eq!(branches[1].head, history[0].hash);
+
+        drop(tmp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_key_migrated() {
+        init_test_env();
+
+        let (runtime, tmp_dir) = test_runtime();
+        let (_runtime, dag_store_url) = spawn_dag_store(runtime).await;
+
+        // - a tree saved before commits or named notebooks, the key points straight at its root
+        let legacy = notes_types::notes::Node::<NodeRef> {
+            parent: None,
+            children: vec![NodeRef::Modified(NodeId(1))],
+            header: "legacy".to_string(),
+        };
+        let child = notes_types::notes::Node::<NodeRef> {
+            parent: Some(NodeId::root()),
+            children: Vec::new(),
+            header: "legacy child".to_string(),
+        };
+        let mut children = HashMap::new();
+        children.insert(NodeId(1).into_generic(), child.into_generic().unwrap());
+        let req = bulk_put::Req {
+            validated_tree: dag_store_types::types::validated_tree::ValidatedTree::validate(
+                legacy.into_generic().unwrap(),
+                children,
+            )
+            .unwrap(),
+            cas: Some(bulk_put::CAS {
+                required_previous_hash: None,
+                cas_key: notes_types::api::LEGACY_CAS_KEY.to_string(),
+            }),
+        };
+        let mut client = connect(dag_store_url.clone()).await.unwrap();
+        let response = client
+            .put_nodes(tonic::Request::new(req.into_proto()))
+            .await
+            .unwrap();
+        let legacy_root = bulk_put::Resp::from_proto(response.into_inner())
+            .unwrap()
+            .root_hash;
+
+        // - read via the default notebook, migrating the key
+        let state = get_initial_state(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(state, Some(legacy_root));
+        let key = notes_types::api::notebook_key(&notebook()).unwrap();
+        let migrated = client
+            .get_hash_for_key(tonic::Request::new(
+                dag_store_types::types::grpc::GetHashForKeyReq { key },
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .hash;
+        assert_eq!(migrated, Some(legacy_root.into_proto()));
+        let history = get_history(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(history, vec![]);
+
+        // - first save wraps the legacy root in a commit
+        let edited = notes_types::notes::Node {
+            parent: None,
+            children: Vec::new(),
+            header: "edited".to_string(),
+        };
+        let put_req = notes_types::api::PutReq {
+            notebook: notebook(),
+            tree: ValidatedTree_::validate_(edited, HashMap::new(), |_| std::iter::empty())
+                .expect("failure validating tree while building put request"),
+            cas_hash: Some(legacy_root.promote()),
+            author: None,
+            message: None,
+        };
+        let hash = put_nodes(dag_store_url.to_string(), put_req)
+            .await
+            .unwrap()
+            .root_hash;
+
+        let history = get_history(dag_store_url.to_string(), notebook()).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].commit.root().map(|r| r.demote()), Some(hash));
+        assert_eq!(
+            history[1].commit.root().map(|r| r.demote()),
+            Some(legacy_root)
+        );
+
+        drop(tmp_dir);
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_https() {
+        init_test_env();
+
+        let (runtime, tmp_dir) = test_runtime();
+        let (_runtime, dag_store_url) = spawn_dag_store(runtime).await;
+
+        let testdata = |file: &str| {
+            format!(
+                "{}/../dag-store/testdata/tls/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                file
+            )
+        };
+        let https = ServerTls {
+            cert_file: testdata("server.pem"),
+            key_file: testdata("server.key"),
+            client_ca_file: None,
+        };
+        let socket = dag_store::test_utils::local_addr();
+        unsafe {
+            GLOBAL_CTX = Some(Arc::new(Runtime {
+                bind_addr: socket,
+                dag_store_url,
+                dag_store_token: None,
+                dag_store_tls: None,
+                https: Some(https.clone()),
+                hb: opts::mk_template(),
+            }));
+        }
+        tokio::spawn(serve(routes(), socket, Some(https)));
+        dag_store::test_utils::wait_until_listening(socket).await;
+
+        let ca = std::fs::read(testdata("ca.pem")).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+            .build()
+            .unwrap();
+
+        // - the api is served over https, the notebook has no history yet
+        let url = format!("https://localhost:{}/history/{}", socket.port(), notebook());
+        let resp = client.get(&url).send().await.unwrap();
+        assert!(resp.status().is_success());
+        let history: Vec<HistoryEntry> = resp.json().await.unwrap();
+        assert!(history.is_empty());
+
+        // - plaintext requests aren't answered
+        let plaintext = client.get(&url.replace("https", "http")).send().await;
+        assert!(plaintext.map(|r| !r.status().is_success()).unwrap_or(true));
+
         drop(tmp_dir);
     }
 }
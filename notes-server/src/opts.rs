@@ -1,28 +1,128 @@
+use dag_store::config::{self, check_pair, ConfigError};
+use dag_store::telemetry::{self, TelemetryConfig};
+use dag_store::tls::{ClientTls, ServerTls};
 use handlebars::Handlebars;
-use std::{fs::File, io::prelude::*};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use structopt::StructOpt;
-use tracing_honeycomb::new_honeycomb_telemetry_layer;
-use tracing_subscriber::{filter::LevelFilter, layer::Layer, registry};
 
+/// command line flags, each of which can also be set via an env var or in the config file.
+/// flags take precedence over env vars, which take precedence over the config file
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "notes server",
     about = "provides notes server-specific functionality"
 )]
 pub struct Opt {
-    #[structopt(short = "p", long = "port")]
-    port: u16,
+    /// toml file to read settings from, named as the flags below are (eg `dag_store_url = "..."`)
+    #[structopt(short = "c", long = "config", env = "NOTES_SERVER_CONFIG")]
+    config: Option<String>,
 
-    #[structopt(short = "h", long = "honeycomb_key_file")]
-    honeycomb_key_file: String,
+    /// print the config resulting from the config file, env vars and flags, then exit
+    #[structopt(long = "print-config")]
+    pub print_config: bool,
 
-    #[structopt(short = "u", long = "dag_store_url")]
-    dag_store_url: String,
+    /// address to serve http on, defaults to 0.0.0.0:3030
+    #[structopt(long = "bind_addr", env = "NOTES_SERVER_BIND_ADDR")]
+    bind_addr: Option<SocketAddr>,
+
+    /// port to serve http on, overrides the port in bind_addr
+    #[structopt(short = "p", long = "port", env = "NOTES_SERVER_PORT")]
+    port: Option<u16>,
+
+    /// where to publish traces: none, stdout (as json), honeycomb or otlp. defaults to honeycomb
+    /// if honeycomb_key_file is set, otherwise none
+    #[structopt(long = "telemetry", env = "NOTES_SERVER_TELEMETRY")]
+    telemetry: Option<String>,
+
+    #[structopt(
+        short = "h",
+        long = "honeycomb_key_file",
+        env = "NOTES_SERVER_HONEYCOMB_KEY_FILE"
+    )]
+    honeycomb_key_file: Option<String>,
+
+    /// otlp grpc endpoint of an opentelemetry collector, defaults to http://localhost:4317
+    #[structopt(long = "otlp_endpoint", env = "NOTES_SERVER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    #[structopt(
+        short = "u",
+        long = "dag_store_url",
+        env = "NOTES_SERVER_DAG_STORE_URL"
+    )]
+    dag_store_url: Option<String>,
+
+    /// file containing a bearer token sent with every request to the dag store
+    #[structopt(
+        long = "dag_store_token_file",
+        env = "NOTES_SERVER_DAG_STORE_TOKEN_FILE"
+    )]
+    dag_store_token_file: Option<String>,
+
+    /// pem encoded certificate to serve https with (requires the tls feature)
+    #[structopt(long = "tls_cert_file", env = "NOTES_SERVER_TLS_CERT_FILE")]
+    tls_cert_file: Option<String>,
+
+    /// pem encoded private key for tls_cert_file
+    #[structopt(long = "tls_key_file", env = "NOTES_SERVER_TLS_KEY_FILE")]
+    tls_key_file: Option<String>,
+
+    /// pem encoded ca used to verify the dag store, enables tls for dag store connections
+    #[structopt(long = "dag_store_ca_file", env = "NOTES_SERVER_DAG_STORE_CA_FILE")]
+    dag_store_ca_file: Option<String>,
+
+    /// client certificate presented to the dag store (mutual tls)
+    #[structopt(long = "dag_store_cert_file", env = "NOTES_SERVER_DAG_STORE_CERT_FILE")]
+    dag_store_cert_file: Option<String>,
+
+    /// private key for dag_store_cert_file
+    #[structopt(long = "dag_store_key_file", env = "NOTES_SERVER_DAG_STORE_KEY_FILE")]
+    dag_store_key_file: Option<String>,
+}
+
+/// settings as read from the config file, see Opt for what each does
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub telemetry: Option<String>,
+    pub honeycomb_key_file: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub dag_store_url: Option<String>,
+    pub dag_store_token_file: Option<String>,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub dag_store_ca_file: Option<String>,
+    pub dag_store_cert_file: Option<String>,
+    pub dag_store_key_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addr: ([0, 0, 0, 0], 3030).into(),
+            telemetry: None,
+            honeycomb_key_file: None,
+            otlp_endpoint: None,
+            dag_store_url: None,
+            dag_store_token_file: None,
+            tls_cert_file: None,
+            tls_key_file: None,
+            dag_store_ca_file: None,
+            dag_store_cert_file: None,
+            dag_store_key_file: None,
+        }
+    }
 }
 
 pub struct Runtime {
-    pub port: u16,
+    pub bind_addr: SocketAddr,
     pub dag_store_url: String,
+    pub dag_store_token: Option<String>,
+    pub dag_store_tls: Option<ClientTls>,
+    /// serve https if set, warp doesn't support client certificates so client_ca_file is unused
+    pub https: Option<ServerTls>,
     pub hb: Handlebars,
 }
 
@@ -46,39 +146,84 @@ pub struct WithTemplate<T: serde::Serialize> {
 }
 
 impl Opt {
-    pub fn into_runtime(self) -> Runtime {
-        let mut file =
-            File::open(self.honeycomb_key_file).expect("failed opening honeycomb key file");
-        let mut honeycomb_key = String::new();
-        file.read_to_string(&mut honeycomb_key)
-            .expect("failed reading honeycomb key file");
-
-        let honeycomb_config = libhoney::Config {
-            options: libhoney::client::Options {
-                api_key: honeycomb_key,
-                dataset: "dag-cache".to_string(), // TODO: better name for this
-                ..libhoney::client::Options::default()
-            },
-            transmission_options: libhoney::transmission::Options {
-                max_batch_size: 1,
-                ..libhoney::transmission::Options::default()
-            },
+    /// read the config file (if any), override it with the flags and env vars that were set and
+    /// validate the result
+    pub fn into_config(self) -> Result<Config, ConfigError> {
+        let mut config: Config = match &self.config {
+            Some(path) => config::load_file(path)?,
+            None => Config::default(),
         };
 
-        let telemetry_layer = new_honeycomb_telemetry_layer("notes-server", honeycomb_config);
+        if let Some(bind_addr) = self.bind_addr {
+            config.bind_addr = bind_addr;
+        }
+        if let Some(port) = self.port {
+            config.bind_addr.set_port(port);
+        }
+        config.telemetry = self.telemetry.or(config.telemetry);
+        config.honeycomb_key_file = self.honeycomb_key_file.or(config.honeycomb_key_file);
+        config.otlp_endpoint = self.otlp_endpoint.or(config.otlp_endpoint);
+        config.dag_store_url = self.dag_store_url.or(config.dag_store_url);
+        config.dag_store_token_file = self.dag_store_token_file.or(config.dag_store_token_file);
+        config.tls_cert_file = self.tls_cert_file.or(config.tls_cert_file);
+        config.tls_key_file = self.tls_key_file.or(config.tls_key_file);
+        config.dag_store_ca_file = self.dag_store_ca_file.or(config.dag_store_ca_file);
+        config.dag_store_cert_file = self.dag_store_cert_file.or(config.dag_store_cert_file);
+        config.dag_store_key_file = self.dag_store_key_file.or(config.dag_store_key_file);
 
-        let subscriber = telemetry_layer // publish to tracing
-            .and_then(tracing_subscriber::fmt::Layer::builder().finish()) // log to stdout
-            .and_then(LevelFilter::INFO) // omit low-level debug tracing (eg tokio executor)
-            .with_subscriber(registry::Registry::default()); // provide underlying span data store
+        config.validate()?;
+        Ok(config)
+    }
+}
 
-        tracing::subscriber::set_global_default(subscriber).expect("setting global default failed");
+impl Config {
+    /// checks settings that don't require reading files, those are checked by into_runtime
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.dag_store_url.is_none() {
+            return Err(ConfigError::required("dag_store_url"));
+        }
+        check_pair(
+            "tls_cert_file",
+            &self.tls_cert_file,
+            "tls_key_file",
+            &self.tls_key_file,
+        )?;
+        check_pair(
+            "dag_store_cert_file",
+            &self.dag_store_cert_file,
+            "dag_store_key_file",
+            &self.dag_store_key_file,
+        )?;
+        Ok(())
+    }
+
+    pub fn into_runtime(self) -> Result<Runtime, ConfigError> {
+        self.validate()?;
+        let telemetry_config = TelemetryConfig::from_opts(
+            self.telemetry,
+            self.honeycomb_key_file,
+            self.otlp_endpoint,
+        )?;
+        telemetry::init("notes-server", telemetry_config);
 
-        Runtime {
-            port: self.port,
-            dag_store_url: self.dag_store_url,
+        let dag_store_token = match self.dag_store_token_file {
+            Some(path) => Some(config::read_token_file("dag_store_token_file", &path)?),
+            None => None,
+        };
+
+        Ok(Runtime {
+            bind_addr: self.bind_addr,
+            // ASSERTION: validate checks dag_store_url is set
+            dag_store_url: self.dag_store_url.unwrap(),
+            dag_store_token,
+            dag_store_tls: ClientTls::from_opts(
+                self.dag_store_ca_file,
+                self.dag_store_cert_file,
+                self.dag_store_key_file,
+            ),
+            https: ServerTls::from_opts(self.tls_cert_file, self.tls_key_file, None)?,
             hb: mk_template(),
-        }
+        })
     }
 }
 
@@ -93,6 +238,7 @@ pub fn mk_template() -> Handlebars {
                 <body>
                     <script>
                         window.starting_hash=\"{{initial_hash}}\";
+                        window.notebook=\"{{notebook}}\";
                     </script>
                     <script src=\"/notes.js\"></script>
                 </body>
@@ -1,3 +1,4 @@
+use crate::commits::Commit;
 use crate::notes;
 use dag_store_types::types::domain::TypedHash;
 use dag_store_types::types::validated_tree::ValidatedTree_;
@@ -6,10 +7,50 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 
-pub static CAS_KEY: &str = "notes-app";
+// each notebook (branch) is a separate key, notes-app/<name>, pointing at its head commit
+pub static CAS_KEY_PREFIX: &str = "notes-app/";
+
+pub static DEFAULT_NOTEBOOK: &str = "main";
+
+// key the only notebook was stored under before notebooks were named, now the default notebook
+pub static LEGACY_CAS_KEY: &str = "notes-app";
+
+// commit author used if a save doesn't specify one. authors are whatever the client claims,
+// notes-server has no notion of identity to check them against
+pub static DEFAULT_AUTHOR: &str = "anonymous";
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync + 'static>>;
 
+/// dag-store key for a notebook. names are restricted so they can be used as url path segments
+pub fn notebook_key(name: &str) -> Result<String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(Box::new(ParseError(format!(
+            "invalid notebook name {:?}, must be non-empty and contain only [a-zA-Z0-9._-]",
+            name
+        ))));
+    }
+    Ok(format!("{}{}", CAS_KEY_PREFIX, name))
+}
+
+/// inverse of notebook_key, None for keys not belonging to a notebook
+pub fn notebook_name(key: &str) -> Option<&str> {
+    key.strip_prefix(CAS_KEY_PREFIX)
+}
+
+/// key a notebook was stored under before notebooks were named, if it existed then
+pub fn legacy_notebook_key(key: &str) -> Option<&'static str> {
+    if notebook_name(key) == Some(DEFAULT_NOTEBOOK) {
+        Some(LEGACY_CAS_KEY)
+    } else {
+        None
+    }
+}
+
+fn default_notebook() -> String {
+    DEFAULT_NOTEBOOK.to_string()
+}
+
 #[derive(PartialEq, Eq, Clone, Serialize, Debug, Deserialize)]
 pub struct GetResp {
     pub requested_node: notes::Node<notes::RemoteNodeRef>,
@@ -37,15 +78,69 @@ impl GetResp {
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Serialize, Debug, Deserialize)]
+pub struct ResolveResp {
+    pub requested_node: notes::Node<notes::RemoteNodeRef>,
+    // refs traversed from the root to reach requested_node, root-most first
+    pub path: Vec<notes::RemoteNodeRef>,
+}
+
+impl ResolveResp {
+    pub fn from_generic(g: api::resolve::Resp) -> Result<Self> {
+        let requested_node = notes::Node::from_generic(g.node)?;
+        let mut path = Vec::with_capacity(g.path.len());
+        for hdr in g.path.into_iter() {
+            let id = notes::NodeId::from_generic(hdr.id)?;
+            path.push(notes::RemoteNodeRef(id, hdr.hash.promote()));
+        }
+
+        Ok(ResolveResp {
+            requested_node,
+            path,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Debug, Deserialize)]
+pub struct HistoryEntry {
+    pub hash: TypedHash<Commit>,
+    pub commit: Commit,
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Debug, Deserialize)]
+pub struct Branch {
+    pub name: String,
+    pub head: TypedHash<Commit>,
+}
+
+/// start a new notebook with an existing tree as its first commit
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreateBranchReq {
+    pub name: String,
+    pub root: TypedHash<notes::CannonicalNode>,
+    // self-asserted, defaults to DEFAULT_AUTHOR
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
 // TODO: will need to make this heterogenous - must allow tree w/ commits + notes
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PutReq {
+    #[serde(default = "default_notebook")]
+    pub notebook: String,
     // TODO: this fails to serialize b/c key type is not string which JSON requires
     pub tree: ValidatedTree_<notes::NodeId, notes::Node<notes::NodeRef>>,
+    // root of the commit this edit is based on, must match the current head commit's root
     pub cas_hash: Option<TypedHash<notes::CannonicalNode>>,
+    // recorded on the resulting commit, author defaults to DEFAULT_AUTHOR and is self-asserted
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 impl PutReq {
+    /// tree upload only, cas_hash is checked against (and the key updated to) commits
     pub fn into_generic(self) -> Result<api::bulk_put::Req> {
         let head = self.tree.root_node.into_generic()?;
         let mut extra_nodes = HashMap::new();
@@ -58,10 +153,7 @@ impl PutReq {
 
         let req = api::bulk_put::Req {
             validated_tree,
-            cas: Some(api::bulk_put::CAS {
-                required_previous_hash: self.cas_hash.map(|x| x.demote()),
-                cas_key: CAS_KEY.to_string(),
-            }),
+            cas: None,
         };
         Ok(req)
     }
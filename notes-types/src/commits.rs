@@ -1,13 +1,165 @@
+use crate::api::{Result, DEFAULT_AUTHOR};
 use crate::notes::CannonicalNode;
-use dag_store_types::types::domain::TypedHash;
+use dag_store_types::types::domain::{self, TypedHash};
+use dag_store_types::types::{api, encodings};
 use serde::{Deserialize, Serialize};
 
+// NOTE: encoded with serde_json, which writes struct fields in declaration order. commits
+//       must not contain maps or floats so the encoding (and thus the hash) is canonical.
+//       fields added after the first release default when missing, so older commits still
+//       decode (hashes are never recomputed from decoded commits, only from stored bytes)
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
 pub enum Commit {
     Commit {
         parent: TypedHash<Commit>, // always at least one (NonEmpty)
         additional_parents: Vec<TypedHash<Commit>>,
         root: TypedHash<CannonicalNode>,
+        // self-asserted by whoever saved the commit, not authenticated
+        #[serde(default = "default_author")]
+        author: String,
+        #[serde(default)]
+        timestamp_millis: u64, // wall-clock time since the unix epoch, 0 if unknown
+        #[serde(default)]
+        message: Option<String>,
     },
     Null, // shared origin for all commits
 }
+
+fn default_author() -> String {
+    DEFAULT_AUTHOR.to_string()
+}
+
+// link ids used for the hashes a commit references, so they're reachable in the dag
+const ROOT_LINK_ID: u128 = 0;
+const PARENT_LINK_ID: u128 = 1;
+const FIRST_ADDITIONAL_PARENT_LINK_ID: u128 = 2;
+
+impl Commit {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let res = serde_json::to_vec(self)?;
+        Ok(res)
+    }
+
+    pub fn decode(v: &[u8]) -> Result<Self> {
+        let res = serde_json::from_slice(v)?;
+        Ok(res)
+    }
+
+    pub fn root(&self) -> Option<TypedHash<CannonicalNode>> {
+        match self {
+            Commit::Commit { root, .. } => Some(*root),
+            Commit::Null => None,
+        }
+    }
+
+    /// parents of this commit, first parent first
+    pub fn parents(&self) -> Vec<TypedHash<Commit>> {
+        match self {
+            Commit::Commit {
+                parent,
+                additional_parents,
+                ..
+            } => std::iter::once(*parent)
+                .chain(additional_parents.iter().cloned())
+                .collect(),
+            Commit::Null => Vec::new(),
+        }
+    }
+
+    pub fn from_generic(g: domain::Node) -> Result<Self> {
+        Commit::decode(&g.data.0[..])
+    }
+
+    /// encode as a dag node, linking to the root and parents (all already in the store)
+    pub fn into_generic(self) -> Result<api::bulk_put::Node> {
+        let data = encodings::Base64(self.encode()?);
+
+        let mut links = Vec::new();
+        if let Commit::Commit {
+            parent,
+            additional_parents,
+            root,
+            ..
+        } = self
+        {
+            let link = |id: u128, hash: domain::Hash| {
+                api::bulk_put::NodeLink::Remote(domain::Header {
+                    id: domain::Id(id),
+                    hash,
+                    size: 0, // TODO: FIXME impl or drop size field. idk.
+                })
+            };
+            links.push(link(ROOT_LINK_ID, root.demote()));
+            links.push(link(PARENT_LINK_ID, parent.demote()));
+            for (i, p) in additional_parents.into_iter().enumerate() {
+                links.push(link(
+                    FIRST_ADDITIONAL_PARENT_LINK_ID + i as u128,
+                    p.demote(),
+                ));
+            }
+        }
+
+        Ok(api::bulk_put::Node { data, links })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_encoding_is_canonical() {
+        let hash = |b: u8| domain::Hash::from_bytes(&[b; 32]).unwrap();
+        let commit = Commit::Commit {
+            parent: hash(1).promote(),
+            additional_parents: vec![hash(2).promote()],
+            root: hash(3).promote(),
+            author: "alice".to_string(),
+            timestamp_millis: 1_577_836_800_000,
+            message: None,
+        };
+
+        let encoded = commit.encode().unwrap();
+        let decoded = Commit::decode(&encoded).unwrap();
+        assert_eq!(decoded, commit);
+        // re-encoding a decoded commit must not change its hash
+        assert_eq!(decoded.encode().unwrap(), encoded);
+
+        // fields are always written in the same order
+        let encoded = String::from_utf8(encoded).unwrap();
+        let positions: Vec<usize> = [
+            "\"parent\"",
+            "\"additional_parents\"",
+            "\"root\"",
+            "\"author\"",
+            "\"timestamp_millis\":1577836800000",
+            "\"message\":null",
+        ]
+        .iter()
+        .map(|field| encoded.find(field).unwrap())
+        .collect();
+        let mut sorted = positions.clone();
+        sorted.sort();
+        assert_eq!(positions, sorted);
+    }
+
+    #[test]
+    fn test_decode_commit_without_metadata() {
+        let hash = |b: u8| domain::Hash::from_bytes(&[b; 32]).unwrap();
+        let commit = Commit::Commit {
+            parent: hash(1).promote(),
+            additional_parents: vec![],
+            root: hash(3).promote(),
+            author: DEFAULT_AUTHOR.to_string(),
+            timestamp_millis: 0,
+            message: None,
+        };
+
+        // as written before author, timestamp and message were recorded
+        let encoded = String::from_utf8(commit.encode().unwrap()).unwrap();
+        let start = encoded.find(",\"author\"").unwrap();
+        let legacy = format!("{}}}}}", &encoded[..start]);
+
+        assert_eq!(Commit::decode(legacy.as_bytes()).unwrap(), commit);
+    }
+}
@@ -1,4 +1,5 @@
 #![deny(warnings)]
 pub mod api;
 pub mod commits;
+pub mod merge;
 pub mod notes;
@@ -0,0 +1,476 @@
+use crate::api::{ParseError, PutReq, Result};
+use crate::notes::{CannonicalNode, NodeId, NodeRef};
+use dag_store_types::types::domain::TypedHash;
+use dag_store_types::types::validated_tree::ValidatedTree_;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// fully materialized notes tree, rooted at NodeId::root()
+pub type Tree = HashMap<NodeId, CannonicalNode>;
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+pub enum Conflict {
+    /// both sides changed the header differently, ours was kept
+    Header {
+        id: NodeId,
+        base: Option<String>,
+        ours: String,
+        theirs: String,
+    },
+    /// both sides moved the node to different parents, ours was kept
+    Parent {
+        id: NodeId,
+        ours: Option<NodeId>,
+        theirs: Option<NodeId>,
+    },
+    /// one side deleted the node (or one of its ancestors) while the other modified it, the
+    /// node was kept
+    DeleteModify { id: NodeId, deleted_in_ours: bool },
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeResult {
+    pub tree: Tree,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// three-way merge of two trees descended from base, keyed by NodeId. header and parent
+/// edits are merged per node, child list edits are merged per child. conflicting edits are
+/// resolved in favor of ours (keeping rather than deleting nodes) and reported, as are moves
+/// that only conflict in combination (eg each side moving a different node under the other)
+pub fn merge(base: &Tree, ours: &Tree, theirs: &Tree) -> MergeResult {
+    let mut conflicts = Vec::new();
+    let mut tree = HashMap::new();
+
+    let mut ids: Vec<NodeId> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    // deterministic conflict order
+    ids.sort_by_key(|id| id.0);
+
+    for id in ids.into_iter() {
+        let merged = match (base.get(&id), ours.get(&id), theirs.get(&id)) {
+            (b, Some(o), Some(t)) => Some(merge_node(id, b, o, t, &mut conflicts)),
+            (Some(b), Some(o), None) if o == b => None,
+            (Some(b), None, Some(t)) if t == b => None,
+            (Some(_), Some(o), None) => {
+                conflicts.push(Conflict::DeleteModify {
+                    id,
+                    deleted_in_ours: false,
+                });
+                Some(o.clone())
+            }
+            (Some(_), None, Some(t)) => {
+                conflicts.push(Conflict::DeleteModify {
+                    id,
+                    deleted_in_ours: true,
+                });
+                Some(t.clone())
+            }
+            (None, Some(o), None) => Some(o.clone()),
+            (None, None, Some(t)) => Some(t.clone()),
+            (_, None, None) => None,
+        };
+        if let Some(node) = merged {
+            tree.insert(id, node);
+        }
+    }
+
+    restore_ancestors(&mut tree, ours, theirs, base, &mut conflicts);
+    revert_stranded(&mut tree, ours, theirs, &mut conflicts);
+    // reverting to our parent may point at one they deleted
+    restore_ancestors(&mut tree, ours, theirs, base, &mut conflicts);
+    rebuild_children(&mut tree);
+    remove_unreachable(&mut tree);
+
+    MergeResult { tree, conflicts }
+}
+
+fn merge_node(
+    id: NodeId,
+    base: Option<&CannonicalNode>,
+    ours: &CannonicalNode,
+    theirs: &CannonicalNode,
+    conflicts: &mut Vec<Conflict>,
+) -> CannonicalNode {
+    let header =
+        merge3(base.map(|b| &b.header), &ours.header, &theirs.header).unwrap_or_else(|| {
+            conflicts.push(Conflict::Header {
+                id,
+                base: base.map(|b| b.header.clone()),
+                ours: ours.header.clone(),
+                theirs: theirs.header.clone(),
+            });
+            ours.header.clone()
+        });
+
+    let parent =
+        merge3(base.map(|b| &b.parent), &ours.parent, &theirs.parent).unwrap_or_else(|| {
+            conflicts.push(Conflict::Parent {
+                id,
+                ours: ours.parent,
+                theirs: theirs.parent,
+            });
+            ours.parent
+        });
+
+    let base_children = base.map(|b| &b.children[..]).unwrap_or(&[]);
+    let children = merge_children(base_children, &ours.children, &theirs.children);
+
+    CannonicalNode {
+        parent,
+        children,
+        header,
+    }
+}
+
+// None if both sides changed the value differently
+fn merge3<T: PartialEq + Clone>(base: Option<&T>, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || base == Some(theirs) {
+        Some(ours.clone())
+    } else if base == Some(ours) {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+// keeps our ordering, applying their removals and inserting their additions after the
+// nearest preceding child they share with us
+fn merge_children(base: &[NodeId], ours: &[NodeId], theirs: &[NodeId]) -> Vec<NodeId> {
+    if ours == theirs || theirs == base {
+        return ours.to_vec();
+    }
+    if ours == base {
+        return theirs.to_vec();
+    }
+
+    // drop children they removed
+    let mut res: Vec<NodeId> = ours
+        .iter()
+        .filter(|id| !base.contains(id) || theirs.contains(id))
+        .cloned()
+        .collect();
+
+    for (i, id) in theirs.iter().enumerate() {
+        if base.contains(id) || res.contains(id) {
+            continue;
+        }
+        let pos = theirs[..i]
+            .iter()
+            .rev()
+            .find_map(|prev| res.iter().position(|x| x == prev))
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        res.insert(pos, *id);
+    }
+
+    res
+}
+
+// a node kept due to a conflict may have had an ancestor deleted on the other side
+fn restore_ancestors(
+    tree: &mut Tree,
+    ours: &Tree,
+    theirs: &Tree,
+    base: &Tree,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let mut pending: Vec<NodeId> = tree.keys().cloned().collect();
+    while let Some(id) = pending.pop() {
+        let parent = match tree.get(&id).and_then(|n| n.parent) {
+            Some(parent) if !tree.contains_key(&parent) => parent,
+            _ => continue,
+        };
+        let restored = ours
+            .get(&parent)
+            .or_else(|| theirs.get(&parent))
+            .or_else(|| base.get(&parent));
+        if let Some(node) = restored {
+            conflicts.push(Conflict::DeleteModify {
+                id: parent,
+                deleted_in_ours: !ours.contains_key(&parent),
+            });
+            tree.insert(parent, node.clone());
+            pending.push(parent);
+        }
+    }
+}
+
+// parents merged per node can combine into a cycle (ours moves a under b, theirs moves b
+// under a) that would otherwise be silently dropped as unreachable. any node no longer
+// reachable from the root that took their parent is reverted to ours, one at a time as each
+// revert may reconnect others
+fn revert_stranded(tree: &mut Tree, ours: &Tree, theirs: &Tree, conflicts: &mut Vec<Conflict>) {
+    loop {
+        let mut stranded = stranded(tree);
+        // deterministic conflict order
+        stranded.sort_by_key(|id| id.0);
+        let revert = stranded.into_iter().find_map(|id| {
+            let ours_parent = ours.get(&id)?.parent;
+            if tree.get(&id)?.parent != ours_parent {
+                Some((id, ours_parent))
+            } else {
+                None
+            }
+        });
+
+        match revert {
+            Some((id, parent)) => {
+                conflicts.push(Conflict::Parent {
+                    id,
+                    ours: parent,
+                    theirs: theirs.get(&id).and_then(|n| n.parent),
+                });
+                if let Some(node) = tree.get_mut(&id) {
+                    node.parent = parent;
+                }
+            }
+            None => return,
+        }
+    }
+}
+
+// nodes whose chain of parents doesn't lead to the root
+fn stranded(tree: &Tree) -> Vec<NodeId> {
+    let mut rooted: HashMap<NodeId, bool> = HashMap::new();
+    rooted.insert(NodeId::root(), true);
+    for id in tree.keys() {
+        let mut path: Vec<NodeId> = Vec::new();
+        let mut next = Some(*id);
+        let reaches_root = loop {
+            let current = match next {
+                Some(current) => current,
+                None => break false,
+            };
+            if let Some(known) = rooted.get(&current) {
+                break *known;
+            }
+            match tree.get(&current) {
+                Some(node) if !path.contains(&current) => {
+                    path.push(current);
+                    next = node.parent;
+                }
+                // missing parent or cycle
+                _ => break false,
+            }
+        };
+        for p in path.into_iter() {
+            rooted.insert(p, reaches_root);
+        }
+    }
+    rooted
+        .into_iter()
+        .filter(|(_, reaches_root)| !reaches_root)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+// each node's parent field is authoritative, make child lists agree with it
+fn rebuild_children(tree: &mut Tree) {
+    let mut by_parent: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+    for (id, node) in tree.iter() {
+        if let Some(parent) = node.parent {
+            by_parent.entry(parent).or_default().push(*id);
+        }
+    }
+
+    for (id, node) in tree.iter_mut() {
+        let mut expected = by_parent.remove(id).unwrap_or_default();
+        expected.sort_by_key(|c| c.0);
+
+        let mut children: Vec<NodeId> = Vec::with_capacity(expected.len());
+        for child in node.children.iter() {
+            if expected.contains(child) && !children.contains(child) {
+                children.push(*child);
+            }
+        }
+        for child in expected.into_iter() {
+            if !children.contains(&child) {
+                children.push(child);
+            }
+        }
+        node.children = children;
+    }
+}
+
+fn remove_unreachable(tree: &mut Tree) {
+    let mut reachable = HashSet::new();
+    let mut frontier = VecDeque::new();
+    frontier.push_back(NodeId::root());
+    while let Some(id) = frontier.pop_front() {
+        if let Some(node) = tree.get(&id) {
+            if reachable.insert(id) {
+                frontier.extend(node.children.iter().cloned());
+            }
+        }
+    }
+    tree.retain(|id, _| reachable.contains(id));
+}
+
+impl MergeResult {
+    /// build a put request for the merged tree, to be applied to notebook on top of cas_hash
+    /// (the root the merge was performed against). every node is uploaded, unchanged nodes are
+    /// deduplicated by the dag store
+    pub fn into_put_req(
+        self,
+        notebook: String,
+        cas_hash: Option<TypedHash<CannonicalNode>>,
+    ) -> Result<PutReq> {
+        let mut nodes: HashMap<NodeId, _> = self
+            .tree
+            .into_iter()
+            .map(|(id, node)| (id, node.map(NodeRef::Modified)))
+            .collect();
+        let root_node = nodes
+            .remove(&NodeId::root())
+            .ok_or_else(|| Box::new(ParseError("merged tree has no root".to_string())))?;
+
+        let tree = ValidatedTree_::validate_(root_node, nodes, |n| {
+            n.children
+                .iter()
+                .map(|r| r.node_id())
+                .collect::<Vec<_>>()
+                .into_iter()
+        })?;
+
+        Ok(PutReq {
+            notebook,
+            tree,
+            cas_hash,
+            author: None,
+            message: Some("merge".to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(parent: Option<u128>, children: Vec<u128>, header: &str) -> CannonicalNode {
+        CannonicalNode {
+            parent: parent.map(NodeId),
+            children: children.into_iter().map(NodeId).collect(),
+            header: header.to_string(),
+        }
+    }
+
+    fn tree(nodes: Vec<(u128, CannonicalNode)>) -> Tree {
+        nodes.into_iter().map(|(id, n)| (NodeId(id), n)).collect()
+    }
+
+    #[test]
+    fn test_merge_concurrent_edits() {
+        let base = tree(vec![
+            (0, node(None, vec![1, 2], "root")),
+            (1, node(Some(0), vec![], "one")),
+            (2, node(Some(0), vec![], "two")),
+        ]);
+        // edit one's header, add three at the end
+        let ours = tree(vec![
+            (0, node(None, vec![1, 2, 3], "root")),
+            (1, node(Some(0), vec![], "one (ours)")),
+            (2, node(Some(0), vec![], "two")),
+            (3, node(Some(0), vec![], "three")),
+        ]);
+        // delete two, add four at the start, edit root
+        let theirs = tree(vec![
+            (0, node(None, vec![4, 1], "root (theirs)")),
+            (1, node(Some(0), vec![], "one")),
+            (4, node(Some(0), vec![], "four")),
+        ]);
+
+        let res = merge(&base, &ours, &theirs);
+        assert_eq!(res.conflicts, vec![]);
+        assert_eq!(
+            res.tree[&NodeId(0)],
+            node(None, vec![4, 1, 3], "root (theirs)")
+        );
+        assert_eq!(res.tree[&NodeId(1)].header, "one (ours)");
+        assert!(!res.tree.contains_key(&NodeId(2)));
+
+        let put_req = res.into_put_req("main".to_string(), None).unwrap();
+        assert_eq!(put_req.tree.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = tree(vec![
+            (0, node(None, vec![1], "root")),
+            (1, node(Some(0), vec![2], "one")),
+            (2, node(Some(1), vec![], "two")),
+        ]);
+        // edit root, edit two
+        let ours = tree(vec![
+            (0, node(None, vec![1], "root (ours)")),
+            (1, node(Some(0), vec![2], "one")),
+            (2, node(Some(1), vec![], "two (ours)")),
+        ]);
+        // edit root, delete one and two
+        let theirs = tree(vec![(0, node(None, vec![], "root (theirs)"))]);
+
+        let res = merge(&base, &ours, &theirs);
+        assert_eq!(
+            res.conflicts,
+            vec![
+                Conflict::Header {
+                    id: NodeId(0),
+                    base: Some("root".to_string()),
+                    ours: "root (ours)".to_string(),
+                    theirs: "root (theirs)".to_string(),
+                },
+                Conflict::DeleteModify {
+                    id: NodeId(2),
+                    deleted_in_ours: false,
+                },
+                Conflict::DeleteModify {
+                    id: NodeId(1),
+                    deleted_in_ours: false,
+                },
+            ]
+        );
+        // modified node kept along with its deleted ancestor
+        assert_eq!(res.tree[&NodeId(0)], node(None, vec![1], "root (ours)"));
+        assert_eq!(res.tree[&NodeId(1)], node(Some(0), vec![2], "one"));
+        assert_eq!(res.tree[&NodeId(2)].header, "two (ours)");
+    }
+
+    #[test]
+    fn test_merge_swapped_moves() {
+        let base = tree(vec![
+            (0, node(None, vec![1, 2], "root")),
+            (1, node(Some(0), vec![], "a")),
+            (2, node(Some(0), vec![], "b")),
+        ]);
+        // move a under b
+        let ours = tree(vec![
+            (0, node(None, vec![2], "root")),
+            (1, node(Some(2), vec![], "a")),
+            (2, node(Some(0), vec![1], "b")),
+        ]);
+        // move b under a
+        let theirs = tree(vec![
+            (0, node(None, vec![1], "root")),
+            (1, node(Some(0), vec![2], "a")),
+            (2, node(Some(1), vec![], "b")),
+        ]);
+
+        // merged per node that's a cycle, b reverts to our parent
+        let res = merge(&base, &ours, &theirs);
+        assert_eq!(
+            res.conflicts,
+            vec![Conflict::Parent {
+                id: NodeId(2),
+                ours: Some(NodeId(0)),
+                theirs: Some(NodeId(1)),
+            }]
+        );
+        assert_eq!(res.tree, ours);
+    }
+}
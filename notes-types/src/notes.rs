@@ -23,6 +23,26 @@ impl NodeId {
     pub fn into_generic(self) -> Id {
         Id(self.0)
     }
+
+    pub fn to_base58(&self) -> String {
+        let bytes = encodings::Base58::from_bytes(self.0.to_be_bytes().to_vec());
+        format!("{}", bytes)
+    }
+
+    /// parse from the same base58 encoding used when serializing
+    pub fn from_base58(b58: &str) -> Result<Self> {
+        let bytes = encodings::Base58::from_string(b58)
+            .map_err(|e| ParseError(format!("invalid b58: {:?}", e)))?;
+        if bytes.0.len() != 16 {
+            return Err(Box::new(ParseError(format!(
+                "wrong byte array size, expected 16 got {}",
+                bytes.0.len()
+            ))));
+        }
+        let mut array = [0; 16];
+        array.copy_from_slice(&bytes.0[..]);
+        Ok(NodeId(u128::from_be_bytes(array)))
+    }
 }
 
 impl<'de> Deserialize<'de> for NodeId {